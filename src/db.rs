@@ -3,11 +3,12 @@ use gpui::{point, size, AppContext, Bounds, Global, Pixels};
 use rusqlite::{
     params,
     types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, Value, ValueRef},
-    Connection, ToSql,
+    Connection, OpenFlags, ToSql,
 };
 use std::{
     path::{Path, PathBuf},
     rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
 };
 use uuid::Uuid;
 
@@ -17,6 +18,9 @@ pub struct DB {
     connection: Rc<Connection>,
 }
 
+/// Files beyond the most recently opened ones are forgotten, with their settings.
+const RECENT_FILES_LIMIT: usize = 500;
+
 #[derive(Debug)]
 struct ExistingMigration {
     migration: String,
@@ -70,6 +74,56 @@ const MIGRATIONS: &[Migration] = &[
             created_at  TEXT DEFAULT current_timestamp
         )",
     },
+    Migration {
+        migration: "4_create_undo_histories",
+        statement: "CREATE TABLE undo_histories (
+            id          INTEGER PRIMARY KEY,
+            file_id     BLOB NOT NULL UNIQUE,
+            history     TEXT NOT NULL,
+            created_at  TEXT DEFAULT current_timestamp
+        )",
+    },
+    Migration {
+        migration: "5_add_window_id_to_open_windows",
+        statement: "ALTER TABLE open_windows ADD COLUMN window_id BLOB DEFAULT NULL",
+    },
+    Migration {
+        migration: "6_add_tab_idx_to_open_windows",
+        statement: "ALTER TABLE open_windows ADD COLUMN tab_idx INTEGER DEFAULT 0",
+    },
+    Migration {
+        migration: "7_create_theme_settings",
+        statement: "CREATE TABLE theme_settings (
+            id          INTEGER PRIMARY KEY CHECK (id = 1),
+            mode        TEXT NOT NULL,
+            light_theme TEXT NOT NULL,
+            dark_theme  TEXT NOT NULL,
+            created_at  TEXT DEFAULT current_timestamp
+        )",
+    },
+    Migration {
+        migration: "8_create_command_usages",
+        statement: "CREATE TABLE command_usages (
+            id          INTEGER PRIMARY KEY,
+            action_name TEXT NOT NULL UNIQUE,
+            used_at     INTEGER NOT NULL,
+            created_at  TEXT DEFAULT current_timestamp
+        )",
+    },
+    Migration {
+        migration: "9_create_recent_files",
+        statement: "CREATE TABLE recent_files (
+            id          INTEGER PRIMARY KEY,
+            file_path   TEXT NOT NULL UNIQUE,
+            opened_at   INTEGER NOT NULL,
+            open_count  INTEGER NOT NULL DEFAULT 1,
+            cursor      INTEGER DEFAULT NULL,
+            anchor      INTEGER DEFAULT NULL,
+            scroll_x    REAL DEFAULT NULL,
+            scroll_y    REAL DEFAULT NULL,
+            created_at  TEXT DEFAULT current_timestamp
+        )",
+    },
 ];
 
 #[derive(Debug)]
@@ -83,8 +137,37 @@ pub struct PathSettings {
     pub word_wrap: bool,
 }
 
+#[derive(Debug)]
+pub struct RecentFile {
+    pub path: PathBuf,
+    pub open_count: usize,
+}
+
+/// Where the selection and view of a file were, to open it there again.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FilePosition {
+    /// Char offset of the moving end of the selection.
+    pub cursor: usize,
+    /// Char offset of the fixed end, the selection is reversed when it's after the cursor.
+    pub anchor: usize,
+    pub scroll_x: f32,
+    pub scroll_y: f32,
+}
+
+#[derive(Debug)]
+pub struct ThemeSettings {
+    pub mode: String,
+    pub light_theme: String,
+    pub dark_theme: String,
+}
+
 impl DB {
     pub fn register_global(cx: &mut AppContext) -> Result<()> {
+        cx.set_global::<DB>(Self::open()?);
+        Ok(())
+    }
+
+    pub fn open() -> Result<Self> {
         let app_data_path = app_data_path()?;
         let db_file = app_data_path.join("db.sqlite");
         let connection = Connection::open(db_file)?;
@@ -92,13 +175,20 @@ impl DB {
         Self::migrate(&connection)?;
         Self::cleanup(&connection)?;
 
-        let this = Self {
+        Ok(Self {
             connection: Rc::new(connection),
-        };
+        })
+    }
 
-        cx.set_global::<DB>(this);
+    /// For reading while the app may be running, without migrating or cleaning up. Fails when
+    /// the app never ran.
+    pub fn open_read_only() -> Result<Self> {
+        let db_file = app_data_path()?.join("db.sqlite");
+        let connection = Connection::open_with_flags(db_file, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
 
-        Ok(())
+        Ok(Self {
+            connection: Rc::new(connection),
+        })
     }
 
     pub fn window_position(&self, file_path: &Path) -> Option<Vec<WindowPosition>> {
@@ -186,6 +276,138 @@ impl DB {
         self
     }
 
+    pub fn theme_settings(&self) -> Option<ThemeSettings> {
+        self.connection
+            .query_row(
+                "SELECT mode, light_theme, dark_theme FROM theme_settings WHERE id = 1",
+                (),
+                |row| {
+                    Ok(ThemeSettings {
+                        mode: row.get(0)?,
+                        light_theme: row.get(1)?,
+                        dark_theme: row.get(2)?,
+                    })
+                },
+            )
+            .ok()
+    }
+
+    pub fn update_theme_settings(&self, settings: &ThemeSettings) {
+        _ = self.connection.execute(
+            "
+            INSERT INTO theme_settings (id, mode, light_theme, dark_theme)
+            VALUES (1, ?1, ?2, ?3)
+            ON CONFLICT DO
+            UPDATE SET mode = ?1, light_theme = ?2, dark_theme = ?3
+            ",
+            params![settings.mode, settings.light_theme, settings.dark_theme],
+        );
+    }
+
+    /// Action names run from the command palette, most recently used first.
+    pub fn command_usages(&self) -> Vec<String> {
+        let Ok(mut stmt) = self
+            .connection
+            .prepare("SELECT action_name FROM command_usages ORDER BY used_at DESC")
+        else {
+            return Vec::new();
+        };
+
+        stmt.query_map((), |row| row.get(0))
+            .map(|rows| rows.filter_map(|m| m.ok()).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn command_used(&self, action_name: &str) {
+        _ = self.connection.execute(
+            "
+            INSERT INTO command_usages (action_name, used_at)
+            VALUES (?1, ?2)
+            ON CONFLICT DO
+            UPDATE SET used_at = ?2
+            ",
+            params![action_name, unix_millis()],
+        );
+    }
+
+    /// Most recently opened first.
+    pub fn recent_files(&self) -> Vec<RecentFile> {
+        let Ok(mut stmt) = self
+            .connection
+            .prepare("SELECT file_path, open_count FROM recent_files ORDER BY opened_at DESC")
+        else {
+            return Vec::new();
+        };
+
+        stmt.query_map((), |row| {
+            Ok(RecentFile {
+                path: PathBuf::from(row.get::<_, String>(0)?),
+                open_count: row.get(1)?,
+            })
+        })
+        .map(|rows| rows.filter_map(|m| m.ok()).collect())
+        .unwrap_or_default()
+    }
+
+    pub fn recent_file_opened(&self, file_path: &Path) {
+        let Some(file_path_str) = file_path.to_str() else {
+            return;
+        };
+
+        _ = self.connection.execute(
+            "
+            INSERT INTO recent_files (file_path, opened_at)
+            VALUES (?1, ?2)
+            ON CONFLICT DO
+            UPDATE SET opened_at = ?2, open_count = open_count + 1
+            ",
+            params![file_path_str, unix_millis()],
+        );
+    }
+
+    pub fn recent_file_position(&self, file_path: &Path) -> Option<FilePosition> {
+        let file_path_str = file_path.to_str()?;
+
+        self.connection
+            .query_row(
+                "SELECT cursor, anchor, scroll_x, scroll_y FROM recent_files WHERE file_path = ?1",
+                params![file_path_str],
+                |row| {
+                    Ok(FilePosition {
+                        cursor: row.get(0)?,
+                        anchor: row.get(1)?,
+                        scroll_x: row.get(2)?,
+                        scroll_y: row.get(3)?,
+                    })
+                },
+            )
+            .ok()
+    }
+
+    /// Files that were open when the app was last quit don't have to be in the list yet.
+    pub fn update_recent_file_position(&self, file_path: &Path, position: FilePosition) {
+        let Some(file_path_str) = file_path.to_str() else {
+            return;
+        };
+
+        _ = self.connection.execute(
+            "
+            INSERT INTO recent_files (file_path, opened_at, cursor, anchor, scroll_x, scroll_y)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            ON CONFLICT DO
+            UPDATE SET cursor = ?3, anchor = ?4, scroll_x = ?5, scroll_y = ?6
+            ",
+            params![
+                file_path_str,
+                unix_millis(),
+                position.cursor,
+                position.anchor,
+                position.scroll_x,
+                position.scroll_y
+            ],
+        );
+    }
+
     pub fn tmp_file_load(&self, file_id: MyUuid) -> Option<String> {
         self.connection
             .query_row(
@@ -214,18 +436,49 @@ impl DB {
             .execute("DELETE FROM tmp_files WHERE file_id = ?1", params![file_id]);
     }
 
+    pub fn undo_history_load(&self, file_id: MyUuid) -> Option<String> {
+        self.connection
+            .query_row(
+                "SELECT history FROM undo_histories WHERE file_id = ?1",
+                params![file_id],
+                |row| row.get(0),
+            )
+            .ok()
+    }
+
+    pub fn undo_history_save(&self, file_id: MyUuid, history: String) {
+        _ = self.connection.execute(
+            "
+            INSERT INTO undo_histories (file_id, history)
+            VALUES (?1, ?2)
+            ON CONFLICT DO
+            UPDATE SET history = ?2
+            ",
+            params![file_id, history],
+        );
+    }
+
+    pub fn undo_history_delete(&self, file_id: MyUuid) {
+        _ = self.connection.execute(
+            "DELETE FROM undo_histories WHERE file_id = ?1",
+            params![file_id],
+        );
+    }
+
     pub fn open_windows(&self) -> Option<Vec<OldOpenWindow>> {
         Some(
             self.connection
                 .prepare(
-                    "SELECT file_id, file_path
-                    FROM open_windows",
+                    "SELECT file_id, file_path, window_id, tab_idx
+                    FROM open_windows
+                    ORDER BY tab_idx",
                 )
                 .ok()?
                 .query_map((), |row| {
                     Ok(OldOpenWindow {
                         file_id: row.get(0)?,
                         file_path: row.get(1).ok(),
+                        window_id: row.get(2).ok(),
                     })
                 })
                 .ok()?
@@ -235,7 +488,7 @@ impl DB {
     }
 
     pub fn open_windows_add(&self, file_id: Option<MyUuid>, file_path: Option<&PathBuf>) -> MyUuid {
-        let file_id = file_id.unwrap_or(MyUuid(Uuid::new_v4()));
+        let file_id = file_id.unwrap_or_else(MyUuid::new_v4);
 
         if let Some(file_path_str) = file_path.map(|f| f.to_str()) {
             _ = self.connection.execute(
@@ -252,6 +505,15 @@ impl DB {
         file_id
     }
 
+    pub fn open_windows_update_tabs(&self, window_id: MyUuid, file_ids: &[MyUuid]) {
+        for (tab_idx, file_id) in file_ids.iter().enumerate() {
+            _ = self.connection.execute(
+                "UPDATE open_windows SET window_id = ?1, tab_idx = ?2 WHERE file_id = ?3",
+                params![window_id, tab_idx, file_id],
+            );
+        }
+    }
+
     pub fn open_windows_remove(&self, file_id: MyUuid) {
         _ = self.connection.execute(
             "DELETE FROM open_windows WHERE file_id = ?1",
@@ -260,12 +522,17 @@ impl DB {
     }
 
     fn cleanup(connection: &Connection) -> Result<()> {
-        connection.execute_batch(
+        connection.execute_batch(&format!(
             "
             DELETE FROM window_positions WHERE created_at < datetime('now', '-6 month');
-            DELETE FROM file_settings WHERE created_at < datetime('now', '-6 month');
-            ",
-        )?;
+            DELETE FROM recent_files WHERE id NOT IN (
+                SELECT id FROM recent_files ORDER BY opened_at DESC LIMIT {RECENT_FILES_LIMIT}
+            );
+            DELETE FROM file_settings WHERE created_at < datetime('now', '-6 month')
+                AND file_path NOT IN (SELECT file_path FROM recent_files);
+            DELETE FROM undo_histories WHERE file_id NOT IN (SELECT file_id FROM open_windows);
+            "
+        ))?;
 
         Ok(())
     }
@@ -313,6 +580,12 @@ impl DB {
 
 impl Global for DB {}
 
+fn unix_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as i64)
+}
+
 pub trait DbConnection {
     fn db_connection(&self) -> &DB;
 }
@@ -342,6 +615,12 @@ impl ToSql for MyPixels {
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct MyUuid(Uuid);
 
+impl MyUuid {
+    pub fn new_v4() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
 impl FromSql for MyUuid {
     fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
         let value = value.as_blob()?;
@@ -368,4 +647,5 @@ impl PartialEq<Uuid> for MyUuid {
 pub struct OldOpenWindow {
     pub file_id: MyUuid,
     pub file_path: Option<String>,
+    pub window_id: Option<MyUuid>,
 }
@@ -1,6 +1,16 @@
+mod command_palette;
+mod diff;
 pub mod editor;
+mod file_finder;
+mod file_io;
+mod go_to_line;
+pub mod html_export;
+mod markdown_preview;
 mod modal_manager;
+mod outline;
+mod picker;
 mod search;
 mod status_bar;
 mod theme_selector;
 mod title_bar;
+pub mod workspace;
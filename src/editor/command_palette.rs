@@ -0,0 +1,166 @@
+use std::cmp::Reverse;
+
+use gpui::*;
+
+use crate::{
+    db::DbConnection,
+    fuzzy::{fuzzy_match, recency_bonus},
+    views::match_label::MatchLabel,
+};
+
+use super::{
+    editor::ToggleCommandPalette,
+    picker::{Picker, PickerDelegate},
+};
+
+struct Command {
+    name: String,
+    keybinding: Option<String>,
+    action: Box<dyn Action>,
+}
+
+struct CommandMatch {
+    command_idx: usize,
+    /// Byte offsets into the command's name.
+    positions: Vec<usize>,
+}
+
+pub struct CommandPalette {
+    commands: Vec<Command>,
+    /// Action names, most recently used first.
+    recent: Vec<String>,
+    matches: Vec<CommandMatch>,
+    previous_focus: Option<FocusHandle>,
+}
+
+impl CommandPalette {
+    pub fn new(cx: &mut ViewContext<Picker<Self>>) -> Self {
+        // the palette isn't focused yet, so these are the actions of the view it was opened from
+        let previous_focus = cx.focused();
+        let mut commands: Vec<Command> = cx
+            .available_actions()
+            .into_iter()
+            .filter(|action| !action.partial_eq(&ToggleCommandPalette))
+            .map(|action| Command {
+                name: command_name(action.name()),
+                keybinding: cx
+                    .bindings_for_action(&*action)
+                    .last()
+                    .map(|binding| keystrokes_label(binding.keystrokes())),
+                action,
+            })
+            .collect();
+        commands.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Self {
+            commands,
+            recent: cx.db_connection().command_usages(),
+            matches: Vec::new(),
+            previous_focus,
+        }
+    }
+}
+
+impl PickerDelegate for CommandPalette {
+    const KEY_CONTEXT: &'static str = "CommandPalette";
+
+    fn match_count(&self) -> usize {
+        self.matches.len()
+    }
+
+    /// Best matches first, recently used commands get a bonus that beats a slightly better
+    /// match and lead an empty query.
+    fn update_matches(&mut self, query: String, _cx: &mut ViewContext<Picker<Self>>) {
+        let mut ranked: Vec<(i32, usize, CommandMatch)> = self
+            .commands
+            .iter()
+            .enumerate()
+            .filter_map(|(command_idx, command)| {
+                let fuzzy_match = fuzzy_match(&query, &command.name)?;
+                let recency = self
+                    .recent
+                    .iter()
+                    .position(|name| name == command.action.name());
+                Some((
+                    fuzzy_match.score + recency_bonus(recency),
+                    recency.unwrap_or(usize::MAX),
+                    CommandMatch {
+                        command_idx,
+                        positions: fuzzy_match.positions,
+                    },
+                ))
+            })
+            .collect();
+        ranked.sort_by_key(|(score, recency, _)| (Reverse(*score), *recency));
+
+        self.matches = ranked.into_iter().map(|(_, _, m)| m).collect();
+    }
+
+    /// Closes the palette and runs the selected action in the view it was opened from.
+    fn confirm(&mut self, idx: usize, cx: &mut ViewContext<Picker<Self>>) {
+        let action = self.commands[self.matches[idx].command_idx]
+            .action
+            .boxed_clone();
+
+        cx.db_connection().command_used(action.name());
+        cx.emit(DismissEvent);
+        // the action goes to whatever is focused when it is dispatched
+        if let Some(previous_focus) = &self.previous_focus {
+            previous_focus.focus(cx);
+        }
+        cx.dispatch_action(action);
+    }
+
+    fn render_match(&self, idx: usize, _cx: &mut ViewContext<Picker<Self>>) -> AnyElement {
+        let command_match = &self.matches[idx];
+        let command = &self.commands[command_match.command_idx];
+
+        div()
+            .flex()
+            .justify_between()
+            .gap_4()
+            .child(MatchLabel::new(
+                command.name.clone(),
+                command_match.positions.clone(),
+            ))
+            .children(command.keybinding.clone())
+            .into_any_element()
+    }
+
+    fn status(&self) -> Option<&'static str> {
+        self.matches.is_empty().then_some("No matching commands")
+    }
+}
+
+/// `workspace::ActivateTab1` reads as `Activate Tab 1`.
+fn command_name(action_name: &str) -> String {
+    let name = action_name
+        .rsplit_once("::")
+        .map_or(action_name, |(_, name)| name);
+
+    let mut words = String::new();
+    let mut previous: Option<char> = None;
+    for c in name.chars() {
+        let starts_word = match previous {
+            Some(previous) => {
+                (c.is_uppercase() && !previous.is_uppercase())
+                    || (c.is_ascii_digit() && !previous.is_ascii_digit())
+            }
+            None => false,
+        };
+        if starts_word {
+            words.push(' ');
+        }
+        words.push(c);
+        previous = Some(c);
+    }
+    words
+}
+
+fn keystrokes_label(keystrokes: &[Keystroke]) -> String {
+    keystrokes
+        .iter()
+        .map(|keystroke| keystroke.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,204 @@
+use std::fmt::Write;
+
+const CONTEXT_LINES: usize = 3;
+/// Above this many cells the LCS table gets too big and the changed block is shown as a whole.
+const MAX_TABLE_SIZE: usize = 4_000_000;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Line based diff of `old` and `new` in unified format.
+pub fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let lines = diff_lines(&old_lines, &new_lines);
+
+    let mut out = String::new();
+    _ = writeln!(out, "--- {old_name}");
+    _ = writeln!(out, "+++ {new_name}");
+
+    let changes: Vec<usize> = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Line::Same(_)))
+        .map(|(idx, _)| idx)
+        .collect();
+
+    let mut idx = 0;
+    while idx < changes.len() {
+        let start = changes[idx].saturating_sub(CONTEXT_LINES);
+        let mut end = changes[idx];
+        while idx < changes.len() && changes[idx] <= end + CONTEXT_LINES * 2 {
+            end = changes[idx];
+            idx += 1;
+        }
+        let end = (end + CONTEXT_LINES + 1).min(lines.len());
+
+        let (old_start, new_start) = positions_before(&lines, start);
+        let hunk = &lines[start..end];
+        let old_len = hunk
+            .iter()
+            .filter(|line| !matches!(line, Line::Added(_)))
+            .count();
+        let new_len = hunk
+            .iter()
+            .filter(|line| !matches!(line, Line::Removed(_)))
+            .count();
+
+        // an empty side is numbered by the line before it, like diff does
+        _ = writeln!(
+            out,
+            "@@ -{},{} +{},{} @@",
+            old_start + old_len.min(1),
+            old_len,
+            new_start + new_len.min(1),
+            new_len
+        );
+        for line in hunk {
+            _ = match line {
+                Line::Same(text) => writeln!(out, " {text}"),
+                Line::Removed(text) => writeln!(out, "-{text}"),
+                Line::Added(text) => writeln!(out, "+{text}"),
+            };
+        }
+    }
+
+    out
+}
+
+fn positions_before(lines: &[Line], idx: usize) -> (usize, usize) {
+    lines[..idx]
+        .iter()
+        .fold((0, 0), |(old, new), line| match line {
+            Line::Same(_) => (old + 1, new + 1),
+            Line::Removed(_) => (old + 1, new),
+            Line::Added(_) => (old, new + 1),
+        })
+}
+
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Line<'a>> {
+    let prefix = old
+        .iter()
+        .zip(new.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    let mut lines: Vec<Line> = old[..prefix].iter().map(|line| Line::Same(line)).collect();
+
+    if (old_mid.len() + 1) * (new_mid.len() + 1) > MAX_TABLE_SIZE {
+        lines.extend(old_mid.iter().map(|line| Line::Removed(line)));
+        lines.extend(new_mid.iter().map(|line| Line::Added(line)));
+    } else {
+        lines.extend(lcs_diff(old_mid, new_mid));
+    }
+
+    lines.extend(
+        old[old.len() - suffix..]
+            .iter()
+            .map(|line| Line::Same(line)),
+    );
+    lines
+}
+
+fn lcs_diff<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Line<'a>> {
+    let width = new.len() + 1;
+    // table[i * width + j] is the LCS length of old[i..] and new[j..]
+    let mut table = vec![0u32; (old.len() + 1) * width];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            table[i * width + j] = if old[i] == new[j] {
+                table[(i + 1) * width + j + 1] + 1
+            } else {
+                table[(i + 1) * width + j].max(table[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::with_capacity(old.len() + new.len());
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            lines.push(Line::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if table[(i + 1) * width + j] >= table[i * width + j + 1] {
+            lines.push(Line::Removed(old[i]));
+            i += 1;
+        } else {
+            lines.push(Line::Added(new[j]));
+            j += 1;
+        }
+    }
+    lines.extend(old[i..].iter().map(|line| Line::Removed(line)));
+    lines.extend(new[j..].iter().map(|line| Line::Added(line)));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbered(lines: std::ops::Range<usize>) -> String {
+        lines.map(|line| format!("{line}\n")).collect()
+    }
+
+    #[test]
+    fn no_hunks_without_changes() {
+        let text = numbered(1..5);
+        assert_eq!(unified_diff(&text, &text, "a", "b"), "--- a\n+++ b\n");
+    }
+
+    #[test]
+    fn shows_context_around_a_change() {
+        let old = numbered(1..11);
+        let new = old.replace("\n5\n", "\nfive\n");
+        assert_eq!(
+            unified_diff(&old, &new, "a", "b"),
+            "--- a\n+++ b\n@@ -2,7 +2,7 @@\n 2\n 3\n 4\n-5\n+five\n 6\n 7\n 8\n"
+        );
+    }
+
+    #[test]
+    fn merges_close_changes_and_splits_distant_ones() {
+        let old = numbered(1..31);
+        let close = old.replace("\n5\n", "\nx\n").replace("\n10\n", "\ny\n");
+        assert_eq!(
+            unified_diff(&old, &close, "a", "b").matches("@@ -").count(),
+            1
+        );
+
+        let distant = old.replace("\n5\n", "\nx\n").replace("\n25\n", "\ny\n");
+        let diff = unified_diff(&old, &distant, "a", "b");
+        assert_eq!(diff.matches("@@ -").count(), 2);
+        assert!(diff.contains("@@ -22,7 +22,7 @@"));
+    }
+
+    #[test]
+    fn numbers_insertions_and_deletions() {
+        assert_eq!(
+            unified_diff("", "a\nb\n", "a", "b"),
+            "--- a\n+++ b\n@@ -0,0 +1,2 @@\n+a\n+b\n"
+        );
+        assert_eq!(
+            unified_diff("a\nb\n", "", "a", "b"),
+            "--- a\n+++ b\n@@ -1,2 +0,0 @@\n-a\n-b\n"
+        );
+        assert_eq!(
+            unified_diff("a\nc\n", "a\nb\nc\n", "a", "b"),
+            "--- a\n+++ b\n@@ -1,2 +1,3 @@\n a\n+b\n c\n"
+        );
+    }
+}
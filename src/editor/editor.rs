@@ -1,22 +1,40 @@
 use std::{
-    fs,
+    fs, io,
     path::{Path, PathBuf},
-    time::Duration,
+    time::{Duration, SystemTime},
 };
 
 use gpui::*;
+use prelude::FluentBuilder;
 
 use crate::{
-    db::{DbConnection, MyUuid},
-    settings_manager::CurrentSettings,
+    db::{DbConnection, FilePosition, MyUuid},
+    file_watcher::{FileWatcher, PathChanged, Watch},
+    settings_manager::BackupMode,
     theme_manager::ActiveTheme,
-    views::text_input::text_input::{TextInput, TextInputMode},
+    views::text_input::{
+        history::History,
+        markdown::{self, Document},
+        selection::Selection,
+        text_input::{TextInput, TextInputMode},
+    },
     ContentChanged,
 };
 
 use super::{
-    modal_manager::ModalManager, search::SearchView, status_bar::StatusBar,
-    theme_selector::ThemeSelector, title_bar::TitleBar,
+    command_palette::CommandPalette,
+    diff::unified_diff,
+    file_finder::FileFinder,
+    file_io::{encode, read_decoded, write_atomic, Encoding, LineEnding},
+    go_to_line::GoToLine,
+    html_export::to_html,
+    markdown_preview::MarkdownPreview,
+    modal_manager::ModalManager,
+    outline::Outline,
+    picker::Picker,
+    search::SearchView,
+    status_bar::StatusBar,
+    theme_selector::ThemeSelector,
 };
 
 actions!(
@@ -28,48 +46,97 @@ actions!(
         WindowClose,
         Minimize,
         ToggleTheme,
-        Search
+        Search,
+        SplitRight,
+        SplitDown,
+        CloseSplit,
+        ReloadFile,
+        ToggleCommandPalette,
+        ToggleGoToLine,
+        ToggleFileFinder,
+        ToggleRecentFiles,
+        TogglePreview,
+        ToggleOutline,
+        ExportHtml
     ]
 );
 
+#[derive(Clone, Copy, PartialEq)]
+enum SplitDirection {
+    Right,
+    Down,
+}
+
+/// A second view of the editor's buffer, next to or below the main one.
+struct Split {
+    text_input: View<TextInput>,
+    direction: SplitDirection,
+    _subscriptions: Vec<Subscription>,
+}
+
+pub enum EditorEvent {
+    Closed,
+    CloseAborted,
+    ShowDiff(String),
+    OpenFile(PathBuf),
+}
+
+impl EventEmitter<EditorEvent> for Editor {}
+
 pub struct Editor {
     file_id: MyUuid,
     text_input: View<TextInput>,
-    title_bar: View<TitleBar>,
+    split: Option<Split>,
+    preview: Option<View<MarkdownPreview>>,
     status_bar: View<StatusBar>,
     modal_manager: View<ModalManager>,
     search_view: View<SearchView>,
     bounds_save_task_queue: Option<Task<()>>,
+    history_save_task_queue: Option<Task<()>>,
+    position_save_task: Option<Task<()>>,
+    /// Last position seen, the text input also notifies for blinking and scroll bar fades.
+    last_position: Option<FilePosition>,
+    /// Last position written for the file, so unchanged positions aren't written again.
+    saved_position: Option<FilePosition>,
+    /// Modification time of the file when it was last read or written by us.
+    disk_modified: Option<SystemTime>,
+    /// A newer version on disk the user chose to keep their changes over.
+    ignored_disk_modified: Option<SystemTime>,
+    is_prompting_reload: bool,
+    encoding: Encoding,
+    line_ending: LineEnding,
+    /// The file and the project settings files its settings come from.
+    watch: Watch,
     _subscriptions: Vec<Subscription>,
 }
 
 impl Editor {
     pub fn new(file_id: MyUuid, cx: &mut ViewContext<Self>) -> Self {
-        let handle = cx.view().downgrade();
-        cx.on_window_should_close(move |cx| {
-            handle
-                .update(cx, |this, cx| {
-                    cx.db_connection().open_windows_remove(this.file_id);
-                    this.allowed_to_close_window(cx)
-                })
-                .unwrap_or(true)
-        });
-
-        cx.bind_keys([KeyBinding::new("cmd-t", ToggleTheme, None)]);
-        cx.bind_keys([KeyBinding::new("cmd-f", Search, None)]);
-
         let text_input = cx.new_view(|cx| TextInput::new(TextInputMode::Full, cx));
 
         let weak_handle = text_input.downgrade();
-        let title_bar = cx.new_view(|_| TitleBar::new(weak_handle.clone()));
-        let status_bar = cx.new_view(|_| StatusBar::new(weak_handle.clone()));
+        let editor_handle = cx.view().downgrade();
+        let status_bar = cx.new_view(|_| StatusBar::new(weak_handle.clone(), editor_handle));
         let search_view = cx.new_view(|cx| SearchView::new(weak_handle.clone(), cx));
 
+        let file_watcher = FileWatcher::global(cx);
+        let watch = file_watcher.update(cx, |file_watcher, _| file_watcher.watch(Vec::new()));
+
+        let focus_handle = text_input.read(cx).focus_handle.clone();
         let _subscriptions = vec![
-            cx.subscribe(&text_input, |this, view, _: &ContentChanged, cx| {
-                let content = &view.read(cx).content;
-                cx.db_connection()
-                    .tmp_file_save(this.file_id, content.into());
+            cx.subscribe(&file_watcher, |this, _, event: &PathChanged, cx| {
+                if this.watch.contains(&event.0) {
+                    this.path_changed(&event.0, cx);
+                }
+            }),
+            cx.subscribe(&text_input, Self::content_changed),
+            cx.observe(&text_input, |this, _, cx| this.position_changed(cx)),
+            cx.on_focus_in(&focus_handle, |this, cx| {
+                this.set_active_text_input(this.text_input.downgrade(), cx);
+            }),
+            cx.on_app_quit(|this, cx| {
+                this.save_position(cx);
+                async {}
             }),
             cx.observe_window_bounds(|this, cx| {
                 if this.bounds_save_task_queue.is_some() {
@@ -89,10 +156,10 @@ impl Editor {
                                     WindowBounds::Windowed(bounds) => bounds,
                                     WindowBounds::Maximized(bounds) => bounds,
                                 };
-                                let text_input = this.text_input.read(cx);
-                                let path = text_input
-                                    .file_path()
-                                    .clone()
+                                let path = this
+                                    .text_input
+                                    .read(cx)
+                                    .file_path(cx)
                                     .unwrap_or(Path::new("").to_path_buf());
 
                                 cx.db_connection().update_window_position(
@@ -112,55 +179,284 @@ impl Editor {
         Self {
             file_id,
             text_input,
-            title_bar,
+            split: None,
+            preview: None,
             status_bar,
             modal_manager: cx.new_view(ModalManager::new),
             search_view,
             bounds_save_task_queue: None,
+            history_save_task_queue: None,
+            position_save_task: None,
+            last_position: None,
+            saved_position: None,
+            disk_modified: None,
+            ignored_disk_modified: None,
+            is_prompting_reload: false,
+            encoding: Encoding::Utf8,
+            line_ending: LineEnding::Lf,
+            watch,
             _subscriptions,
         }
     }
 
-    fn save_handler(&mut self, _: &Save, cx: &mut ViewContext<Self>) {
-        self.save(cx);
+    pub fn file_id(&self) -> MyUuid {
+        self.file_id
     }
 
-    fn save(&mut self, cx: &mut ViewContext<Self>) {
-        let text_input = self.text_input.read(cx);
+    pub fn file_path(&self, cx: &AppContext) -> Option<PathBuf> {
+        self.text_input.read(cx).file_path(cx)
+    }
+
+    pub fn is_dirty(&self, cx: &AppContext) -> bool {
+        self.text_input.read(cx).is_dirty(cx)
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    /// The content is kept as is, the next save writes it in the new encoding.
+    pub fn set_encoding(&mut self, encoding: Encoding, cx: &mut ViewContext<Self>) {
+        if self.encoding != encoding {
+            self.encoding = encoding;
+            self.text_input
+                .update(cx, |this, cx| this.mark_dirty(true, cx));
+        }
+    }
+
+    pub fn set_line_ending(&mut self, line_ending: LineEnding, cx: &mut ViewContext<Self>) {
+        if self.line_ending != line_ending {
+            self.line_ending = line_ending;
+            self.text_input
+                .update(cx, |this, cx| this.mark_dirty(true, cx));
+        }
+    }
 
-        if let Some(path) = text_input.file_path() {
-            self.save_file(path.into(), cx);
+    fn content_changed(
+        &mut self,
+        text_input: View<TextInput>,
+        _: &ContentChanged,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let text_input = text_input.read(cx);
+        if text_input.is_dirty(cx) {
+            cx.db_connection()
+                .tmp_file_save(self.file_id, (&text_input.content).into());
         } else {
-            self.save_as(cx);
+            cx.db_connection().tmp_file_delete(self.file_id);
+        }
+        self.queue_history_save(cx);
+    }
+
+    /// Points the status bar and search at the pane that has focus.
+    fn set_active_text_input(
+        &mut self,
+        text_input: WeakView<TextInput>,
+        cx: &mut ViewContext<Self>,
+    ) {
+        self.status_bar.update(cx, |status_bar, cx| {
+            status_bar.set_text_input(text_input.clone(), cx)
+        });
+        self.search_view.update(cx, |search_view, cx| {
+            search_view.set_text_input(text_input, cx)
+        });
+    }
+
+    fn split(&mut self, direction: SplitDirection, cx: &mut ViewContext<Self>) {
+        if let Some(split) = self.split.as_mut() {
+            split.direction = direction;
+            cx.notify();
+            return;
+        }
+
+        let primary = self.text_input.read(cx);
+        let buffer = primary.buffer.clone();
+        let soft_wrap = primary.soft_wrap_enabled();
+        let selected_range = primary.selected_range.clone();
+
+        let text_input = cx.new_view(|cx| {
+            let mut text_input = TextInput::for_buffer(TextInputMode::Full, buffer, cx);
+            text_input.set_soft_wrap(soft_wrap, cx);
+            text_input.move_to(selected_range.end, cx);
+            text_input
+        });
+
+        let weak_text_input = text_input.downgrade();
+        let focus_handle = text_input.read(cx).focus_handle.clone();
+        let _subscriptions = vec![
+            cx.subscribe(&text_input, Self::content_changed),
+            cx.on_focus_in(&focus_handle, move |this, cx| {
+                this.set_active_text_input(weak_text_input.clone(), cx);
+            }),
+        ];
+
+        cx.focus_view(&text_input);
+        self.split = Some(Split {
+            text_input,
+            direction,
+            _subscriptions,
+        });
+        cx.notify();
+    }
+
+    fn split_right(&mut self, _: &SplitRight, cx: &mut ViewContext<Self>) {
+        self.split(SplitDirection::Right, cx);
+    }
+
+    fn split_down(&mut self, _: &SplitDown, cx: &mut ViewContext<Self>) {
+        self.split(SplitDirection::Down, cx);
+    }
+
+    fn close_split(&mut self, _: &CloseSplit, cx: &mut ViewContext<Self>) {
+        if self.split.take().is_some() {
+            cx.focus_view(&self.text_input);
+            self.set_active_text_input(self.text_input.downgrade(), cx);
+            cx.notify();
+        }
+    }
+
+    /// The rendered markdown of the buffer to the right of the text.
+    fn toggle_preview(&mut self, _: &TogglePreview, cx: &mut ViewContext<Self>) {
+        if self.preview.take().is_none() {
+            if !self.text_input.read(cx).is_markdown(cx) {
+                self.show_notice("Only markdown files can be previewed", cx);
+                return;
+            }
+            let text_input = self.text_input.clone();
+            self.preview = Some(cx.new_view(|cx| MarkdownPreview::new(text_input, cx)));
+        }
+        cx.notify();
+    }
+
+    fn queue_history_save(&mut self, cx: &mut ViewContext<Self>) {
+        if self.history_save_task_queue.is_some() {
+            return;
+        }
+
+        self.history_save_task_queue = Some(cx.spawn(|this, mut cx| async move {
+            cx.background_executor()
+                .timer(Duration::from_millis(500))
+                .await;
+            this.update(&mut cx, |this, cx| {
+                let history = this.text_input.read(cx).history(cx);
+                if let Some(json) = history.into_json() {
+                    cx.db_connection().undo_history_save(this.file_id, json);
+                }
+                this.history_save_task_queue.take();
+            })
+            .ok();
+        }));
+    }
+
+    fn save_handler(&mut self, _: &Save, cx: &mut ViewContext<Self>) {
+        self.save(cx, |_, _, _| {});
+    }
+
+    /// `on_done` learns whether the file was written, saving can wait on a prompt.
+    fn save(
+        &mut self,
+        cx: &mut ViewContext<Self>,
+        on_done: impl FnOnce(&mut Self, bool, &mut ViewContext<Self>) + 'static,
+    ) {
+        if let Some(path) = self.text_input.read(cx).file_path(cx) {
+            if self.changed_on_disk(&path) {
+                self.prompt_overwrite(path, cx, on_done);
+            } else {
+                let saved = self.save_file(path, cx);
+                on_done(self, saved, cx);
+            }
+        } else {
+            self.save_as(cx, on_done);
         }
     }
 
     fn save_as_handler(&mut self, _: &SaveAs, cx: &mut ViewContext<Self>) {
-        self.save_as(cx);
+        self.save_as(cx, |_, _, _| {});
     }
 
-    fn save_as(&mut self, cx: &mut ViewContext<Self>) {
+    fn save_as(
+        &mut self,
+        cx: &mut ViewContext<Self>,
+        on_done: impl FnOnce(&mut Self, bool, &mut ViewContext<Self>) + 'static,
+    ) {
         self.prompt_for_new_path(
             |this, path, mut cx| {
-                if let Some(path) = path {
-                    cx.update(|cx| {
-                        if let Some(this) = this.upgrade() {
-                            this.update(cx, |this, cx| {
-                                this.text_input
-                                    .update(cx, |this, cx| this.set_file_path(path.into(), cx));
-                                this.save_file(path.into(), cx);
-                            });
-                        }
-                    })
-                    .ok();
-                } else {
-                    // todo: handle
-                }
+                let path = path.cloned();
+                cx.update(|cx| {
+                    if let Some(this) = this.upgrade() {
+                        this.update(cx, |this, cx| {
+                            let saved = match path {
+                                Some(path) => {
+                                    this.text_input.update(cx, |this, cx| {
+                                        this.set_file_path(path.clone(), cx)
+                                    });
+                                    this.save_file(path, cx)
+                                }
+                                None => false,
+                            };
+                            on_done(this, saved, cx);
+                        });
+                    }
+                })
+                .ok();
             },
             cx,
         );
     }
 
+    fn export_html(&mut self, _: &ExportHtml, cx: &mut ViewContext<Self>) {
+        if !self.text_input.read(cx).is_markdown(cx) {
+            self.show_notice("Only markdown files can be exported to HTML", cx);
+            return;
+        }
+
+        self.prompt_for_new_path(
+            |this, path, mut cx| {
+                let Some(path) = path else {
+                    return;
+                };
+                cx.update(|cx| {
+                    if let Some(this) = this.upgrade() {
+                        this.update(cx, |this, cx| this.write_html(path, cx));
+                    }
+                })
+                .ok();
+            },
+            cx,
+        );
+    }
+
+    fn write_html(&mut self, path: &Path, cx: &mut ViewContext<Self>) {
+        let buffer = self.text_input.read(cx).buffer.read(cx);
+        // large files aren't parsed while editing
+        let document = match buffer.parse_tree.as_ref() {
+            Some(tree) => Some(Document::new(&buffer.content, tree, &buffer.syntax_map)),
+            None => markdown::parse(&buffer.content),
+        };
+        let Some(document) = document else {
+            return;
+        };
+
+        let file_path = buffer.file_path.clone();
+        let title = file_path
+            .as_deref()
+            .and_then(Path::file_stem)
+            .map_or("untitled".to_string(), |stem| {
+                stem.to_string_lossy().to_string()
+            });
+        let base_dir = file_path.as_deref().and_then(Path::parent);
+        let html = to_html(&document, &title, cx.theme(), base_dir);
+
+        // the export is generated, a previous one isn't worth a backup
+        if let Err(error) = write_atomic(path, html.as_bytes(), BackupMode::Off) {
+            self.show_error(format!("Couldn't export {}", file_name(path)), &error, cx);
+        }
+    }
+
     pub fn read_file(
         &mut self,
         path: &PathBuf,
@@ -168,7 +464,25 @@ impl Editor {
         cx: &mut ViewContext<Self>,
     ) {
         let is_dirty = previous_content.is_some();
-        let new_content = previous_content.or(fs::read_to_string(path).ok());
+        let new_content = match read_decoded(path) {
+            Ok(decoded) => {
+                self.encoding = decoded.encoding;
+                self.line_ending = decoded.line_ending;
+                previous_content.or(Some(decoded.content))
+            }
+            Err(error) => {
+                if error.kind() != io::ErrorKind::NotFound {
+                    self.show_error(format!("Couldn't open {}", file_name(path)), &error, cx);
+                }
+                previous_content
+            }
+        };
+        let history = cx
+            .db_connection()
+            .undo_history_load(self.file_id)
+            .and_then(|json| History::from_json(&json));
+        let position = cx.db_connection().recent_file_position(path);
+        self.saved_position = position;
 
         self.text_input.update(cx, |this, cx| {
             this.set_file_path(path.into(), cx);
@@ -176,28 +490,269 @@ impl Editor {
                 this.insert(&new_content, cx);
             }
             this.mark_dirty(is_dirty, cx);
-            this.move_to(0, cx);
+            match position {
+                Some(position) => {
+                    let len = this.content.len_chars();
+                    let cursor = position.cursor.min(len);
+                    let anchor = position.anchor.min(len);
+                    this.restore_view(
+                        vec![Selection::new(
+                            cursor.min(anchor)..cursor.max(anchor),
+                            cursor < anchor,
+                        )],
+                        point(px(position.scroll_x), px(position.scroll_y)),
+                        cx,
+                    );
+                }
+                None => this.move_to(0, cx),
+            }
 
             if let Some(settings) = cx.db_connection().path_settings(path) {
                 this.set_soft_wrap(settings.word_wrap, cx);
             }
+
+            if let Some(history) = history {
+                this.restore_history(history, cx);
+            }
         });
+
+        self.disk_modified = disk_modified(path);
+        self.update_watch(cx);
+    }
+
+    fn update_watch(&self, cx: &AppContext) {
+        let mut paths: Vec<PathBuf> = self.file_path(cx).into_iter().collect();
+        for text_input in self.text_inputs() {
+            paths.extend_from_slice(text_input.read(cx).file_settings.project_file_paths());
+        }
+        self.watch.set_paths(paths);
+    }
+
+    fn text_inputs(&self) -> impl Iterator<Item = &View<TextInput>> {
+        [
+            Some(&self.text_input),
+            self.split.as_ref().map(|s| &s.text_input),
+        ]
+        .into_iter()
+        .flatten()
     }
 
-    fn save_file(&mut self, path: PathBuf, cx: &mut ViewContext<Self>) {
+    fn path_changed(&mut self, path: &Path, cx: &mut ViewContext<Self>) {
+        let mut settings_changed = false;
+        for text_input in self.text_inputs().cloned().collect::<Vec<_>>() {
+            settings_changed |= text_input.update(cx, |text_input, cx| {
+                text_input.check_project_settings(path, cx)
+            });
+        }
+        if settings_changed {
+            // a project file closer to the file can take over
+            self.update_watch(cx);
+            cx.notify();
+        }
+
+        if self.file_path(cx).as_deref() == Some(path) {
+            self.check_disk_state(cx);
+        }
+    }
+
+    fn changed_on_disk(&self, path: &Path) -> bool {
+        match (disk_modified(path), self.disk_modified) {
+            (Some(modified), Some(known)) => modified != known,
+            _ => false,
+        }
+    }
+
+    fn check_disk_state(&mut self, cx: &mut ViewContext<Self>) {
+        if self.is_prompting_reload {
+            return;
+        }
+
+        let Some(path) = self.file_path(cx) else {
+            return;
+        };
+        let Some(modified) = disk_modified(&path) else {
+            return;
+        };
+        if self.disk_modified.is_none()
+            || Some(modified) == self.disk_modified
+            || Some(modified) == self.ignored_disk_modified
+        {
+            return;
+        }
+
+        if self.is_dirty(cx) {
+            self.prompt_reload(path, modified, cx);
+        } else {
+            self.reload(&path, cx);
+        }
+    }
+
+    fn prompt_reload(&mut self, path: PathBuf, modified: SystemTime, cx: &mut ViewContext<Self>) {
+        self.is_prompting_reload = true;
+
+        let message = format!("{} changed on disk", file_name(&path));
+        let detail = "Reloading discards your unsaved changes";
+        let prompt = cx.prompt(
+            PromptLevel::Warning,
+            &message,
+            Some(detail),
+            &["Reload", "Keep Mine", "Show Diff"],
+        );
+        cx.spawn(|this, mut cx| async move {
+            let answer = prompt.await.ok();
+            this.update(&mut cx, |this, cx| {
+                this.is_prompting_reload = false;
+                match answer {
+                    Some(0) => this.reload(&path, cx),
+                    Some(2) => {
+                        this.ignored_disk_modified = Some(modified);
+                        this.show_diff(&path, cx);
+                    }
+                    _ => this.ignored_disk_modified = Some(modified),
+                }
+            })
+            .ok();
+        })
+        .detach();
+    }
+
+    fn prompt_overwrite(
+        &mut self,
+        path: PathBuf,
+        cx: &mut ViewContext<Self>,
+        on_done: impl FnOnce(&mut Self, bool, &mut ViewContext<Self>) + 'static,
+    ) {
+        let message = format!("{} changed on disk since it was opened", file_name(&path));
+        let detail = "Saving overwrites the newer version";
+        let prompt = cx.prompt(
+            PromptLevel::Warning,
+            &message,
+            Some(detail),
+            &["Overwrite", "Show Diff", "Cancel"],
+        );
+        cx.spawn(|this, mut cx| async move {
+            match prompt.await.ok() {
+                Some(0) => this.update(&mut cx, |this, cx| {
+                    let saved = this.save_file(path, cx);
+                    on_done(this, saved, cx);
+                }),
+                Some(1) => this.update(&mut cx, |this, cx| {
+                    this.show_diff(&path, cx);
+                    on_done(this, false, cx);
+                }),
+                _ => this.update(&mut cx, |this, cx| on_done(this, false, cx)),
+            }
+        })
+        .detach();
+    }
+
+    /// Replaces the buffer with what is on disk, as an undoable edit.
+    fn reload(&mut self, path: &Path, cx: &mut ViewContext<Self>) {
+        let new_content = match read_decoded(path) {
+            Ok(decoded) => {
+                self.encoding = decoded.encoding;
+                self.line_ending = decoded.line_ending;
+                decoded.content
+            }
+            Err(error) => {
+                self.show_error(format!("Couldn't reload {}", file_name(path)), &error, cx);
+                return;
+            }
+        };
+
+        self.text_input.update(cx, |this, cx| {
+            if this.content != new_content.as_str() {
+                let cursor = this.cursor_offset();
+                this.replace_ranges_bytes(vec![(0..this.content.len_bytes(), new_content)], cx);
+                this.move_to(cursor.min(this.content.len_chars()), cx);
+            }
+            this.mark_dirty(false, cx);
+        });
+
+        self.disk_modified = disk_modified(path);
+        self.ignored_disk_modified = None;
+        cx.db_connection().tmp_file_delete(self.file_id);
+        cx.notify();
+    }
+
+    fn reload_file(&mut self, _: &ReloadFile, cx: &mut ViewContext<Self>) {
+        let Some(path) = self.file_path(cx) else {
+            return;
+        };
+
+        if self.is_dirty(cx) {
+            let Some(modified) = disk_modified(&path) else {
+                return;
+            };
+            self.prompt_reload(path, modified, cx);
+        } else {
+            self.reload(&path, cx);
+        }
+    }
+
+    fn show_diff(&mut self, path: &Path, cx: &mut ViewContext<Self>) {
+        let on_disk = read_decoded(path)
+            .map(|decoded| decoded.content)
+            .unwrap_or_default();
+        let in_editor = self.text_input.read(cx).content.to_string();
+        let name = file_name(path);
+
+        cx.emit(EditorEvent::ShowDiff(unified_diff(
+            &on_disk,
+            &in_editor,
+            &format!("{name} (on disk)"),
+            &format!("{name} (unsaved)"),
+        )));
+    }
+
+    /// Fills a fresh editor with text that is not backed by a file.
+    pub fn set_content(&mut self, content: &str, cx: &mut ViewContext<Self>) {
+        self.text_input.update(cx, |this, cx| {
+            this.insert(content, cx);
+            this.mark_dirty(false, cx);
+            this.move_to(0, cx);
+        });
+    }
+
+    /// False when the file couldn't be written, after telling the user.
+    fn save_file(&mut self, path: PathBuf, cx: &mut ViewContext<Self>) -> bool {
         let text_input = self.text_input.read(cx);
-        match fs::write(path.clone(), text_input.content.to_string()) {
-            Ok(()) => self
-                .text_input
-                .update(cx, |this, cx| this.mark_dirty(false, cx)),
-            Err(error) => println!("{error:?}"),
+        let content = text_input.content.to_string();
+        let backup = text_input.file_settings.settings.backup;
+        let result = encode(&content, self.encoding, self.line_ending)
+            .and_then(|bytes| write_atomic(&path, &bytes, backup));
+        if let Err(error) = result {
+            self.show_error(format!("Couldn't save {}", file_name(&path)), &error, cx);
+            return false;
         }
 
+        self.text_input
+            .update(cx, |this, cx| this.mark_dirty(false, cx));
+        self.disk_modified = disk_modified(&path);
+        self.ignored_disk_modified = None;
+        self.update_watch(cx);
+
         cx.db_connection()
             .update_path_settings(&path, self.text_input.read(cx).soft_wrap_enabled())
             .tmp_file_delete(self.file_id);
 
         cx.notify();
+        true
+    }
+
+    fn show_error(&self, message: String, error: &io::Error, cx: &mut ViewContext<Self>) {
+        let detail = error.to_string();
+        let prompt = cx.prompt(PromptLevel::Critical, &message, Some(&detail), &["Ok"]);
+        cx.foreground_executor()
+            .spawn(async { prompt.await.ok() })
+            .detach();
+    }
+
+    fn show_notice(&self, message: &str, cx: &mut ViewContext<Self>) {
+        let prompt = cx.prompt(PromptLevel::Info, message, None, &["Ok"]);
+        cx.foreground_executor()
+            .spawn(async { prompt.await.ok() })
+            .detach();
     }
 
     fn prompt_for_new_path(
@@ -230,14 +785,67 @@ impl Editor {
     }
 
     fn close_window(&mut self, _: &WindowClose, cx: &mut ViewContext<Self>) {
-        if self.allowed_to_close_window(cx) {
-            cx.remove_window();
+        self.request_close(cx);
+    }
+
+    /// Writes the position once the cursor and scrolling have been still for a moment.
+    fn position_changed(&mut self, cx: &mut ViewContext<Self>) {
+        let position = self.position(cx);
+        if self.last_position == Some(position) {
+            return;
         }
+        self.last_position = Some(position);
+
+        self.position_save_task = Some(cx.spawn(|this, mut cx| async move {
+            cx.background_executor()
+                .timer(Duration::from_millis(500))
+                .await;
+            this.update(&mut cx, |this, cx| {
+                this.save_position(cx);
+                this.position_save_task.take();
+            })
+            .ok();
+        }));
     }
 
-    fn allowed_to_close_window(&self, cx: &mut ViewContext<Self>) -> bool {
-        if !self.text_input.read(cx).is_dirty() {
-            return true;
+    fn position(&self, cx: &AppContext) -> FilePosition {
+        let text_input = self.text_input.read(cx);
+        let selection = text_input.selection();
+        let (cursor, anchor) = if selection.reversed {
+            (selection.range.start, selection.range.end)
+        } else {
+            (selection.range.end, selection.range.start)
+        };
+        let scroll_offset = text_input.scroll_offset(cx);
+        FilePosition {
+            cursor,
+            anchor,
+            scroll_x: scroll_offset.x.0,
+            scroll_y: scroll_offset.y.0,
+        }
+    }
+
+    /// Remembered per path, the file opens there the next time.
+    fn save_position(&mut self, cx: &mut ViewContext<Self>) {
+        let Some(path) = self.text_input.read(cx).file_path(cx) else {
+            return;
+        };
+        let position = self.position(cx);
+        if self.saved_position == Some(position) {
+            return;
+        }
+        cx.db_connection()
+            .update_recent_file_position(&path, position);
+        self.saved_position = Some(position);
+    }
+
+    /// Emits `EditorEvent::Closed` right away, or after asking what to do with unsaved changes.
+    pub fn request_close(&mut self, cx: &mut ViewContext<Self>) {
+        self.save_position(cx);
+
+        if !self.text_input.read(cx).is_dirty(cx) {
+            cx.emit(EditorEvent::Closed);
+            return;
         }
 
         let message = "Close without saving?";
@@ -250,17 +858,23 @@ impl Editor {
         );
         cx.spawn(|this, mut cx| async move {
             match prompt.await.ok() {
+                // the tab stays open when saving was cancelled or failed
                 Some(0) => this.update(&mut cx, |this, cx| {
-                    this.save(cx);
-                    cx.remove_window();
+                    this.save(cx, |_, saved, cx| {
+                        cx.emit(if saved {
+                            EditorEvent::Closed
+                        } else {
+                            EditorEvent::CloseAborted
+                        })
+                    })
                 }),
-                Some(1) => this.update(&mut cx, |_, cx| cx.remove_window()),
-                Some(2 | 3_usize..) | None => Ok(()),
+                Some(1) => this.update(&mut cx, |_, cx| cx.emit(EditorEvent::Closed)),
+                Some(2 | 3_usize..) | None => {
+                    this.update(&mut cx, |_, cx| cx.emit(EditorEvent::CloseAborted))
+                }
             }
         })
         .detach();
-
-        false
     }
 
     fn toggle_modal(&mut self, _: &ToggleTheme, cx: &mut ViewContext<Self>) {
@@ -269,6 +883,57 @@ impl Editor {
         });
     }
 
+    fn toggle_command_palette(&mut self, _: &ToggleCommandPalette, cx: &mut ViewContext<Self>) {
+        self.modal_manager.update(cx, |modal_layer, cx| {
+            modal_layer.toggle_modal(cx, |cx| Picker::new(CommandPalette::new(cx), cx));
+        });
+    }
+
+    fn toggle_file_finder(&mut self, _: &ToggleFileFinder, cx: &mut ViewContext<Self>) {
+        let file_path = self.file_path(cx);
+        let editor = cx.view().downgrade();
+        self.modal_manager.update(cx, |modal_layer, cx| {
+            modal_layer.toggle_modal(cx, |cx| {
+                Picker::new(FileFinder::project(file_path, editor, cx), cx)
+            });
+        });
+    }
+
+    fn toggle_recent_files(&mut self, _: &ToggleRecentFiles, cx: &mut ViewContext<Self>) {
+        let editor = cx.view().downgrade();
+        self.modal_manager.update(cx, |modal_layer, cx| {
+            modal_layer.toggle_modal(cx, |cx| Picker::new(FileFinder::recent(editor, cx), cx));
+        });
+    }
+
+    fn toggle_go_to_line_handler(&mut self, _: &ToggleGoToLine, cx: &mut ViewContext<Self>) {
+        self.toggle_go_to_line(cx);
+    }
+
+    pub fn toggle_go_to_line(&mut self, cx: &mut ViewContext<Self>) {
+        let text_input = self.focused_text_input(cx);
+        self.modal_manager.update(cx, |modal_layer, cx| {
+            modal_layer.toggle_modal(cx, |cx| GoToLine::new(text_input, cx));
+        });
+    }
+
+    fn toggle_outline(&mut self, _: &ToggleOutline, cx: &mut ViewContext<Self>) {
+        let text_input = self.focused_text_input(cx);
+        self.modal_manager.update(cx, |modal_layer, cx| {
+            modal_layer.toggle_modal(cx, |cx| Picker::new(Outline::new(text_input, cx), cx));
+        });
+    }
+
+    /// The pane that has focus, the primary one when neither has it.
+    fn focused_text_input(&self, cx: &WindowContext) -> View<TextInput> {
+        self.split
+            .as_ref()
+            .map(|split| &split.text_input)
+            .filter(|text_input| text_input.read(cx).focus_handle.is_focused(cx))
+            .unwrap_or(&self.text_input)
+            .clone()
+    }
+
     fn open_search(&mut self, _: &Search, cx: &mut ViewContext<Self>) {
         self.search_view
             .update(cx, |search_view, cx| search_view.show(cx));
@@ -277,6 +942,8 @@ impl Editor {
 
 impl Render for Editor {
     fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let settings = self.text_input.read(cx).file_settings.settings.clone();
+
         div()
             .flex()
             .flex_col()
@@ -289,19 +956,62 @@ impl Render for Editor {
             .on_action(cx.listener(Self::close_window))
             .on_action(cx.listener(Self::about))
             .on_action(cx.listener(Self::toggle_modal))
+            .on_action(cx.listener(Self::toggle_command_palette))
+            .on_action(cx.listener(Self::toggle_go_to_line_handler))
+            .on_action(cx.listener(Self::toggle_file_finder))
+            .on_action(cx.listener(Self::toggle_recent_files))
+            .on_action(cx.listener(Self::toggle_outline))
             .on_action(cx.listener(Self::open_search))
-            .child(self.title_bar.clone())
+            .on_action(cx.listener(Self::split_right))
+            .on_action(cx.listener(Self::split_down))
+            .on_action(cx.listener(Self::close_split))
+            .on_action(cx.listener(Self::toggle_preview))
+            .on_action(cx.listener(Self::export_html))
+            .on_action(cx.listener(Self::reload_file))
             .child(self.search_view.clone())
             .child(
                 div()
                     .h_full()
                     .w_full()
                     .bg(cx.theme().editor_background)
-                    .line_height(px(28.))
-                    .text_size(px(18.))
+                    .line_height(settings.line_height)
+                    .text_size(settings.font_size)
                     .text_color(cx.theme().editor_text)
-                    .font_family(cx.settings().font_family)
-                    .child(self.text_input.clone()),
+                    .font_family(settings.font_family)
+                    .flex()
+                    .child(
+                        div()
+                            .size_full()
+                            .flex()
+                            .when_some(self.split.as_ref(), |el, split| {
+                                el.when(split.direction == SplitDirection::Down, |el| el.flex_col())
+                            })
+                            .child(self.text_input.clone())
+                            .when_some(self.split.as_ref(), |el, split| {
+                                el.child(
+                                    div()
+                                        .size_full()
+                                        .when(split.direction == SplitDirection::Right, |el| {
+                                            el.border_l_1()
+                                        })
+                                        .when(split.direction == SplitDirection::Down, |el| {
+                                            el.border_t_1()
+                                        })
+                                        .border_color(cx.theme().scroll_bar_border)
+                                        .child(split.text_input.clone()),
+                                )
+                            }),
+                    )
+                    // always beside the text, also when it is split down
+                    .when_some(self.preview.clone(), |el, preview| {
+                        el.child(
+                            div()
+                                .size_full()
+                                .border_l_1()
+                                .border_color(cx.theme().scroll_bar_border)
+                                .child(preview),
+                        )
+                    }),
             )
             .child(self.status_bar.clone())
             .child(self.modal_manager.clone())
@@ -313,3 +1023,15 @@ impl FocusableView for Editor {
         self.text_input.read(cx).focus_handle.clone()
     }
 }
+
+fn disk_modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+fn file_name(path: &Path) -> &str {
+    path.file_name()
+        .and_then(|file_name| file_name.to_str())
+        .unwrap_or_default()
+}
@@ -0,0 +1,252 @@
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use gpui::*;
+use ignore::WalkBuilder;
+
+use crate::{
+    db::DbConnection,
+    fuzzy::{fuzzy_match, recency_bonus},
+    views::match_label::MatchLabel,
+};
+
+use super::{
+    editor::{Editor, EditorEvent},
+    picker::{Picker, PickerDelegate},
+};
+
+/// Stops indexing huge folders, like a home directory without a repository.
+const MAX_INDEXED_FILES: usize = 50_000;
+
+struct FileEntry {
+    path: PathBuf,
+    /// Shown and matched against, relative to the project root.
+    label: String,
+    detail: Option<String>,
+}
+
+struct FileMatch {
+    file_idx: usize,
+    positions: Vec<usize>,
+}
+
+/// Quick open for the files of the current project or the recently opened files.
+pub struct FileFinder {
+    editor: WeakView<Editor>,
+    root: Option<PathBuf>,
+    files: Arc<Vec<FileEntry>>,
+    is_indexing: bool,
+    /// How many files were opened since, 0 for the most recent one.
+    recency: Arc<HashMap<PathBuf, usize>>,
+    matches: Vec<FileMatch>,
+    _index_task: Task<()>,
+    _match_task: Task<()>,
+}
+
+impl FileFinder {
+    /// The files of the project `file_path` is in, the recent files for an untitled file.
+    pub fn project(
+        file_path: Option<PathBuf>,
+        editor: WeakView<Editor>,
+        cx: &mut ViewContext<Picker<Self>>,
+    ) -> Self {
+        let root = file_path.as_deref().and_then(project_root);
+        Self::new(root, editor, cx)
+    }
+
+    pub fn recent(editor: WeakView<Editor>, cx: &mut ViewContext<Picker<Self>>) -> Self {
+        Self::new(None, editor, cx)
+    }
+
+    fn new(
+        root: Option<PathBuf>,
+        editor: WeakView<Editor>,
+        cx: &mut ViewContext<Picker<Self>>,
+    ) -> Self {
+        let recent_files = cx.db_connection().recent_files();
+
+        let _index_task = match root.clone() {
+            Some(root) => cx.spawn(|this, mut cx| async move {
+                let files = cx
+                    .background_executor()
+                    .spawn(async move { project_files(&root) })
+                    .await;
+                this.update(&mut cx, |picker, cx| {
+                    picker.delegate.files = Arc::new(files);
+                    picker.delegate.is_indexing = false;
+                    picker.update_matches(cx);
+                })
+                .ok();
+            }),
+            None => Task::ready(()),
+        };
+
+        // without a project the recently opened files are the list
+        let files = match root {
+            Some(_) => Vec::new(),
+            None => recent_files
+                .iter()
+                .filter(|file| file.path.is_file())
+                .map(|file| FileEntry {
+                    path: file.path.clone(),
+                    label: file.path.to_string_lossy().to_string(),
+                    detail: Some(match file.open_count {
+                        1 => "opened once".to_string(),
+                        count => format!("opened {count} times"),
+                    }),
+                })
+                .collect(),
+        };
+
+        Self {
+            editor,
+            is_indexing: root.is_some(),
+            root,
+            files: Arc::new(files),
+            recency: Arc::new(
+                recent_files
+                    .into_iter()
+                    .enumerate()
+                    .map(|(recency, file)| (file.path, recency))
+                    .collect(),
+            ),
+            matches: Vec::new(),
+            _index_task,
+            _match_task: Task::ready(()),
+        }
+    }
+}
+
+impl PickerDelegate for FileFinder {
+    const KEY_CONTEXT: &'static str = "FileFinder";
+    const WIDTH: f32 = 640.;
+
+    fn match_count(&self) -> usize {
+        self.matches.len()
+    }
+
+    /// Best matches first, with a bonus for recently opened files so they lead an empty query.
+    /// Projects can have many files, they are matched in the background.
+    fn update_matches(&mut self, query: String, cx: &mut ViewContext<Picker<Self>>) {
+        let files = self.files.clone();
+        let recency = self.recency.clone();
+
+        self._match_task = cx.spawn(|this, mut cx| async move {
+            let matches = cx
+                .background_executor()
+                .spawn(async move { rank_files(&query, &files, &recency) })
+                .await;
+            this.update(&mut cx, |picker, cx| {
+                picker.delegate.matches = matches;
+                picker.matches_updated(cx);
+            })
+            .ok();
+        });
+    }
+
+    /// Opens the selected file in a new tab, or switches to the tab that shows it.
+    fn confirm(&mut self, idx: usize, cx: &mut ViewContext<Picker<Self>>) {
+        let path = self.files[self.matches[idx].file_idx].path.clone();
+
+        cx.emit(DismissEvent);
+        self.editor
+            .update(cx, |_, cx| cx.emit(EditorEvent::OpenFile(path)))
+            .ok();
+    }
+
+    fn render_match(&self, idx: usize, _cx: &mut ViewContext<Picker<Self>>) -> AnyElement {
+        let file_match = &self.matches[idx];
+        let file = &self.files[file_match.file_idx];
+
+        div()
+            .flex()
+            .justify_between()
+            .gap_4()
+            .child(MatchLabel::new(
+                file.label.clone(),
+                file_match.positions.clone(),
+            ))
+            .children(file.detail.clone())
+            .into_any_element()
+    }
+
+    fn status(&self) -> Option<&'static str> {
+        if self.is_indexing {
+            Some("Indexing...")
+        } else if self.files.is_empty() && self.root.is_none() {
+            Some("No recent files")
+        } else if self.matches.is_empty() {
+            Some("No matching files")
+        } else {
+            None
+        }
+    }
+}
+
+fn rank_files(
+    query: &str,
+    files: &[FileEntry],
+    recency: &HashMap<PathBuf, usize>,
+) -> Vec<FileMatch> {
+    let mut ranked: Vec<(i32, usize, FileMatch)> = files
+        .iter()
+        .enumerate()
+        .filter_map(|(file_idx, file)| {
+            let fuzzy_match = fuzzy_match(query, &file.label)?;
+            let recency = recency.get(&file.path).copied();
+            Some((
+                fuzzy_match.score + recency_bonus(recency),
+                recency.unwrap_or(usize::MAX),
+                FileMatch {
+                    file_idx,
+                    positions: fuzzy_match.positions,
+                },
+            ))
+        })
+        .collect();
+    ranked.sort_by_key(|(score, recency, _)| (Reverse(*score), *recency));
+    ranked.into_iter().map(|(_, _, m)| m).collect()
+}
+
+/// The git repository `file_path` is in, or its directory outside of one.
+fn project_root(file_path: &Path) -> Option<PathBuf> {
+    let dir = file_path.parent()?;
+    let root = dir
+        .ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .unwrap_or(dir);
+    Some(root.to_path_buf())
+}
+
+/// Files below `root`, skipping hidden and git-ignored ones.
+fn project_files(root: &Path) -> Vec<FileEntry> {
+    let mut files: Vec<FileEntry> = WalkBuilder::new(root)
+        .build()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry
+                .file_type()
+                .is_some_and(|file_type| file_type.is_file())
+        })
+        .take(MAX_INDEXED_FILES)
+        .map(|entry| {
+            let path = entry.into_path();
+            let label = path
+                .strip_prefix(root)
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .to_string();
+            FileEntry {
+                path,
+                label,
+                detail: None,
+            }
+        })
+        .collect();
+    files.sort_by(|a, b| a.label.cmp(&b.label));
+    files
+}
@@ -0,0 +1,358 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+use crate::settings_manager::BackupMode;
+
+/// How many leading bytes are looked at to spot UTF-16 without a BOM.
+const SNIFF_LEN: usize = 4096;
+/// Versioned backups kept per file, older ones are removed.
+const MAX_BACKUP_VERSIONS: usize = 10;
+/// Longer chains of symlinks are most likely a loop.
+const MAX_SYMLINK_DEPTH: usize = 40;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Encoding {
+    Utf8,
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+impl Encoding {
+    pub const ALL: [Encoding; 5] = [
+        Encoding::Utf8,
+        Encoding::Utf8Bom,
+        Encoding::Utf16Le,
+        Encoding::Utf16Be,
+        Encoding::Latin1,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf8Bom => "UTF-8 BOM",
+            Encoding::Utf16Le => "UTF-16 LE",
+            Encoding::Utf16Be => "UTF-16 BE",
+            Encoding::Latin1 => "Latin-1",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        let idx = Self::ALL.iter().position(|e| *e == self).unwrap_or(0);
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn label(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::CrLf => "CRLF",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            LineEnding::Lf => LineEnding::CrLf,
+            LineEnding::CrLf => LineEnding::Lf,
+        }
+    }
+}
+
+/// File content with `\n` line endings, plus what is needed to write it back the same way.
+pub struct DecodedFile {
+    pub content: String,
+    pub encoding: Encoding,
+    pub line_ending: LineEnding,
+}
+
+pub fn read_decoded(path: &Path) -> io::Result<DecodedFile> {
+    fs::read(path).map(|bytes| decode(&bytes))
+}
+
+pub fn decode(bytes: &[u8]) -> DecodedFile {
+    let (encoding, body) = detect_encoding(bytes);
+
+    let content = match encoding {
+        Encoding::Utf8 | Encoding::Utf8Bom => String::from_utf8_lossy(body).into_owned(),
+        Encoding::Utf16Le => decode_utf16(body, u16::from_le_bytes),
+        Encoding::Utf16Be => decode_utf16(body, u16::from_be_bytes),
+        Encoding::Latin1 => body.iter().map(|&byte| byte as char).collect(),
+    };
+
+    let crlf_count = content.matches("\r\n").count();
+    let lf_count = content.matches('\n').count() - crlf_count;
+    if crlf_count > lf_count {
+        DecodedFile {
+            content: content.replace("\r\n", "\n"),
+            encoding,
+            line_ending: LineEnding::CrLf,
+        }
+    } else {
+        DecodedFile {
+            content,
+            encoding,
+            line_ending: LineEnding::Lf,
+        }
+    }
+}
+
+pub fn encode(content: &str, encoding: Encoding, line_ending: LineEnding) -> io::Result<Vec<u8>> {
+    let content = match line_ending {
+        LineEnding::Lf => content.to_string(),
+        LineEnding::CrLf => content.replace('\n', "\r\n"),
+    };
+
+    let bytes = match encoding {
+        Encoding::Utf8 => content.into_bytes(),
+        Encoding::Utf8Bom => [&[0xEF, 0xBB, 0xBF], content.as_bytes()].concat(),
+        Encoding::Utf16Le => [0xFF, 0xFE]
+            .into_iter()
+            .chain(content.encode_utf16().flat_map(u16::to_le_bytes))
+            .collect(),
+        Encoding::Utf16Be => [0xFE, 0xFF]
+            .into_iter()
+            .chain(content.encode_utf16().flat_map(u16::to_be_bytes))
+            .collect(),
+        Encoding::Latin1 => content
+            .chars()
+            .map(|c| {
+                u8::try_from(c).map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("\"{c}\" can't be saved as Latin-1"),
+                    )
+                })
+            })
+            .collect::<io::Result<Vec<u8>>>()?,
+    };
+
+    Ok(bytes)
+}
+
+fn detect_encoding(bytes: &[u8]) -> (Encoding, &[u8]) {
+    if let Some(body) = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+        return (Encoding::Utf8Bom, body);
+    }
+    if let Some(body) = bytes.strip_prefix(&[0xFF, 0xFE]) {
+        return (Encoding::Utf16Le, body);
+    }
+    if let Some(body) = bytes.strip_prefix(&[0xFE, 0xFF]) {
+        return (Encoding::Utf16Be, body);
+    }
+
+    // text in UTF-16 without a BOM has a zero byte in most ASCII code units,
+    // which plain text in UTF-8 never has
+    let sniff = &bytes[..bytes.len().min(SNIFF_LEN) & !1];
+    let pairs = sniff.len() / 2;
+    if pairs > 0 && bytes.len() & 1 == 0 {
+        let even_zeros = sniff.iter().step_by(2).filter(|&&byte| byte == 0).count();
+        let odd_zeros = sniff
+            .iter()
+            .skip(1)
+            .step_by(2)
+            .filter(|&&byte| byte == 0)
+            .count();
+        if odd_zeros * 2 > pairs && even_zeros * 10 < pairs {
+            return (Encoding::Utf16Le, bytes);
+        }
+        if even_zeros * 2 > pairs && odd_zeros * 10 < pairs {
+            return (Encoding::Utf16Be, bytes);
+        }
+    }
+
+    if std::str::from_utf8(bytes).is_ok() {
+        return (Encoding::Utf8, bytes);
+    }
+
+    (Encoding::Latin1, bytes)
+}
+
+fn decode_utf16(bytes: &[u8], to_unit: fn([u8; 2]) -> u16) -> String {
+    let units = bytes
+        .chunks_exact(2)
+        .map(|pair| to_unit([pair[0], pair[1]]));
+    char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+/// Writes `bytes` to a temp file next to `path`, fsyncs it and renames it over the target,
+/// so a crash leaves either the old or the new file but never a half written one.
+/// Symlinks are followed and the permissions of an existing file are kept.
+pub fn write_atomic(path: &Path, bytes: &[u8], backup: BackupMode) -> io::Result<()> {
+    let target = resolve_symlink(path)?;
+    let existing = fs::metadata(&target).ok();
+
+    let file_name = target
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?
+        .to_string_lossy()
+        .to_string();
+    let dir = match target.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    if existing.is_some() {
+        write_backup(&target, &file_name, backup)?;
+    }
+
+    let tmp_path = dir.join(format!(".{file_name}.{}.tmp", process::id()));
+
+    let result = (|| {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&tmp_path)?;
+        file.write_all(bytes)?;
+
+        if let Some(metadata) = &existing {
+            file.set_permissions(metadata.permissions())?;
+
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::{fchown, MetadataExt};
+                // only root may hand files to other users, so this is best effort
+                _ = fchown(&file, Some(metadata.uid()), Some(metadata.gid()));
+            }
+        }
+
+        file.sync_all()?;
+        fs::rename(&tmp_path, &target)?;
+
+        #[cfg(unix)]
+        if let Ok(dir) = File::open(&dir) {
+            _ = dir.sync_all();
+        }
+
+        Ok(())
+    })();
+
+    if result.is_err() {
+        _ = fs::remove_file(&tmp_path);
+    }
+
+    result
+}
+
+/// The file at the end of a chain of symlinks, which doesn't have to exist yet.
+fn resolve_symlink(path: &Path) -> io::Result<PathBuf> {
+    let mut path = path.to_path_buf();
+    for _ in 0..MAX_SYMLINK_DEPTH {
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                // relative targets start from the folder of the link
+                let target = fs::read_link(&path)?;
+                path = match path.parent() {
+                    Some(parent) => parent.join(target),
+                    None => target,
+                };
+            }
+            _ => return Ok(path),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "too many levels of symbolic links",
+    ))
+}
+
+fn write_backup(target: &Path, file_name: &str, backup: BackupMode) -> io::Result<()> {
+    let backup_path = match backup {
+        BackupMode::Off => return Ok(()),
+        BackupMode::Single => target.with_file_name(format!("{file_name}.bak")),
+        BackupMode::Versioned => {
+            let versioned =
+                |version: usize| target.with_file_name(format!("{file_name}.{version}.bak"));
+            // the oldest one drops out, the others move up to make room for the newest
+            for version in (1..MAX_BACKUP_VERSIONS).rev() {
+                let path = versioned(version);
+                if path.exists() {
+                    fs::rename(path, versioned(version + 1))?;
+                }
+            }
+            versioned(1)
+        }
+    };
+
+    fs::copy(target, backup_path).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_byte_order_marks() {
+        assert_eq!(decode(b"\xEF\xBB\xBFhi").encoding, Encoding::Utf8Bom);
+        assert_eq!(decode(b"\xFF\xFEh\0i\0").encoding, Encoding::Utf16Le);
+        assert_eq!(decode(b"\xFE\xFF\0h\0i").encoding, Encoding::Utf16Be);
+        assert_eq!(decode(b"\xEF\xBB\xBFhi").content, "hi");
+    }
+
+    #[test]
+    fn detects_utf16_without_a_bom() {
+        let le = decode(b"h\0e\0l\0l\0o\0");
+        assert_eq!(le.encoding, Encoding::Utf16Le);
+        assert_eq!(le.content, "hello");
+
+        let be = decode(b"\0h\0e\0l\0l\0o");
+        assert_eq!(be.encoding, Encoding::Utf16Be);
+        assert_eq!(be.content, "hello");
+    }
+
+    #[test]
+    fn falls_back_to_latin1_for_invalid_utf8() {
+        assert_eq!(decode("grün".as_bytes()).encoding, Encoding::Utf8);
+
+        let latin1 = decode(b"gr\xFCn");
+        assert_eq!(latin1.encoding, Encoding::Latin1);
+        assert_eq!(latin1.content, "grün");
+    }
+
+    #[test]
+    fn detects_the_most_common_line_ending() {
+        let crlf = decode(b"a\r\nb\r\nc\n");
+        assert_eq!(crlf.line_ending, LineEnding::CrLf);
+        assert_eq!(crlf.content, "a\nb\nc\n");
+
+        let lf = decode(b"a\nb\nc\r\n");
+        assert_eq!(lf.line_ending, LineEnding::Lf);
+        assert_eq!(lf.content, "a\nb\nc\r\n");
+    }
+
+    #[test]
+    fn encoding_round_trips() {
+        let content = "grün\nline two\n";
+        for encoding in Encoding::ALL {
+            for line_ending in [LineEnding::Lf, LineEnding::CrLf] {
+                let bytes = encode(content, encoding, line_ending).unwrap();
+                let decoded = decode(&bytes);
+                assert_eq!(decoded.content, content, "{encoding:?} {line_ending:?}");
+                assert_eq!(decoded.encoding, encoding, "{encoding:?} {line_ending:?}");
+                assert_eq!(
+                    decoded.line_ending, line_ending,
+                    "{encoding:?} {line_ending:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn latin1_rejects_chars_it_cannot_encode() {
+        assert!(encode("€", Encoding::Latin1, LineEnding::Lf).is_err());
+    }
+}
@@ -0,0 +1,204 @@
+use gpui::*;
+use prelude::FluentBuilder;
+
+use crate::{
+    settings_manager::CurrentSettings,
+    theme_manager::ActiveTheme,
+    views::text_input::{
+        selection::Selection,
+        text_input::{ContentChanged, NewLine, TextInput, TextInputMode},
+    },
+};
+
+use super::modal_manager::{Close, ModalView};
+
+/// Moves the cursor while typing, escape puts it back where it was.
+pub struct GoToLine {
+    query: View<TextInput>,
+    text_input: WeakView<TextInput>,
+    initial_selections: Vec<Selection>,
+    initial_scroll_offset: Point<Pixels>,
+    current_line: usize,
+    line_count: usize,
+    /// 0-based line and column of a query that parsed.
+    position: Option<(usize, usize)>,
+    _subscriptions: Vec<Subscription>,
+}
+
+impl GoToLine {
+    pub fn new(text_input: View<TextInput>, cx: &mut ViewContext<Self>) -> Self {
+        let query = cx.new_view(|cx| TextInput::new(TextInputMode::SingleLine, cx));
+
+        let input = text_input.read(cx);
+        let current_line = input.content.char_to_line(input.cursor_offset());
+        let line_count = input.content.len_lines();
+        let initial_selections = input.all_selections();
+        let initial_scroll_offset = input.scroll_offset(cx);
+
+        Self {
+            query: query.clone(),
+            text_input: text_input.downgrade(),
+            initial_selections,
+            initial_scroll_offset,
+            current_line,
+            line_count,
+            position: None,
+            _subscriptions: vec![
+                cx.subscribe(&query, |this, _, _: &ContentChanged, cx| this.preview(cx)),
+                cx.subscribe(&query, |this, _, _: &NewLine, cx| this.confirm(cx)),
+            ],
+        }
+    }
+
+    fn preview(&mut self, cx: &mut ViewContext<Self>) {
+        let query = self.query.read(cx).content.to_string();
+        self.position = parse_position(&query, self.line_count);
+
+        match self.position {
+            Some((line_idx, column)) => {
+                self.text_input
+                    .update(cx, |text_input, cx| {
+                        let offset = text_input.offset_for_line_column(line_idx, column);
+                        text_input.move_to(offset, cx);
+                    })
+                    .ok();
+            }
+            None if query.trim().is_empty() => self.restore(cx),
+            None => {}
+        }
+        cx.notify();
+    }
+
+    fn restore(&mut self, cx: &mut ViewContext<Self>) {
+        let selections = self.initial_selections.clone();
+        let scroll_offset = self.initial_scroll_offset;
+        self.text_input
+            .update(cx, |text_input, cx| {
+                text_input.restore_view(selections, scroll_offset, cx)
+            })
+            .ok();
+    }
+
+    fn confirm(&mut self, cx: &mut ViewContext<Self>) {
+        if self.position.is_some() {
+            cx.emit(DismissEvent);
+        }
+    }
+
+    fn close(&mut self, _: &Close, cx: &mut ViewContext<Self>) {
+        self.restore(cx);
+        cx.emit(DismissEvent);
+    }
+}
+
+impl Render for GoToLine {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let query_is_empty = self.query.read(cx).content.len_chars() == 0;
+        let (hint, is_error) = match self.position {
+            Some((line_idx, 0)) => (format!("Go to line {}", line_idx + 1), false),
+            Some((line_idx, column)) => (
+                format!("Go to line {}, column {}", line_idx + 1, column + 1),
+                false,
+            ),
+            None if query_is_empty => (
+                format!(
+                    "Line {} of {}, type line, line:column or -lines from the end",
+                    self.current_line + 1,
+                    self.line_count
+                ),
+                false,
+            ),
+            None => ("Not a line or line:column".to_string(), true),
+        };
+
+        div()
+            .w(px(480.))
+            .p_2()
+            .rounded_md()
+            .font_family(cx.settings().font_family.clone())
+            .line_height(cx.settings().line_height)
+            .text_size(cx.settings().font_size)
+            .key_context("GoToLine")
+            .on_action(cx.listener(Self::close))
+            .bg(cx.theme().background)
+            .text_color(cx.theme().editor_text)
+            .child(
+                div()
+                    .mb_1()
+                    .rounded_md()
+                    .bg(cx.theme().editor_background)
+                    .child(self.query.clone()),
+            )
+            .child(
+                div()
+                    .p_1()
+                    .text_size(px(14.))
+                    .line_height(px(20.))
+                    .when(is_error, |el| el.text_color(cx.theme().error))
+                    .child(hint),
+            )
+    }
+}
+
+impl ModalView for GoToLine {}
+impl EventEmitter<DismissEvent> for GoToLine {}
+
+impl FocusableView for GoToLine {
+    fn focus_handle(&self, cx: &AppContext) -> FocusHandle {
+        self.query.read(cx).focus_handle.clone()
+    }
+}
+
+/// `120`, `120:8`, or `-5` for the fifth line from the end. Lines and columns count from 1
+/// and are clamped to the document.
+fn parse_position(query: &str, line_count: usize) -> Option<(usize, usize)> {
+    let query = query.trim();
+    let (line, column) = match query.split_once(':') {
+        Some((line, column)) => (line.trim(), column.trim()),
+        None => (query, ""),
+    };
+
+    let line: isize = line.parse().ok()?;
+    let line_idx = match line {
+        0 => return None,
+        line if line > 0 => (line.unsigned_abs() - 1).min(line_count - 1),
+        line => line_count.saturating_sub(line.unsigned_abs()),
+    };
+    let column = match column {
+        "" => 0,
+        column => column.parse::<usize>().ok()?.checked_sub(1)?,
+    };
+
+    Some((line_idx, column))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_lines_and_columns_from_1() {
+        assert_eq!(parse_position("120", 200), Some((119, 0)));
+        assert_eq!(parse_position("120:8", 200), Some((119, 7)));
+        assert_eq!(parse_position(" 3 : 2 ", 200), Some((2, 1)));
+    }
+
+    #[test]
+    fn counts_negative_lines_from_the_end() {
+        assert_eq!(parse_position("-1", 100), Some((99, 0)));
+        assert_eq!(parse_position("-5", 100), Some((95, 0)));
+    }
+
+    #[test]
+    fn clamps_lines_to_the_document() {
+        assert_eq!(parse_position("500", 10), Some((9, 0)));
+        assert_eq!(parse_position("-50", 10), Some((0, 0)));
+    }
+
+    #[test]
+    fn rejects_invalid_positions() {
+        for query in ["", "0", "abc", "3:0", "3:x", "3:-1"] {
+            assert_eq!(parse_position(query, 10), None, "{query}");
+        }
+    }
+}
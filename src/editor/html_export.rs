@@ -0,0 +1,460 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Write,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use gpui::Rgba;
+use ropey::Rope;
+
+use crate::{
+    db::DB,
+    settings_manager::BackupMode,
+    theme_manager::{Theme, ThemeManager, ThemeMode},
+    views::text_input::markdown::{
+        self, plain_text, Alignment, Block, BlockKind, Code, Document, Inline, ListItem,
+    },
+};
+
+use super::file_io::{read_decoded, write_atomic};
+
+/// A standalone page, styled with `theme` and with local images inlined.
+/// `base_dir` is what relative image paths are resolved against.
+pub fn to_html(document: &Document, title: &str, theme: &Theme, base_dir: Option<&Path>) -> String {
+    let mut writer = HtmlWriter {
+        html: String::new(),
+        base_dir,
+        slugs: HashMap::new(),
+        highlight_names: BTreeSet::new(),
+    };
+    for block in &document.blocks {
+        writer.block(block);
+    }
+
+    let title = document
+        .blocks
+        .iter()
+        .find_map(|block| match &block.kind {
+            BlockKind::Heading { content, .. } => Some(plain_text(content)),
+            _ => None,
+        })
+        .filter(|heading| !heading.is_empty())
+        .unwrap_or_else(|| title.to_string());
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n<main>\n{}</main>\n</body>\n</html>\n",
+        escape(&title),
+        stylesheet(theme, &writer.highlight_names),
+        writer.html
+    )
+}
+
+/// `text --export-html <input> [output]`, the output defaults to the input with `.html`.
+/// Uses the theme the app would show in light mode. Returns the exit code.
+pub fn export_from_args(args: &[String]) -> i32 {
+    let (input, output) = match args {
+        [input] => (
+            PathBuf::from(input),
+            PathBuf::from(input).with_extension("html"),
+        ),
+        [input, output] => (PathBuf::from(input), PathBuf::from(output)),
+        _ => {
+            eprintln!("usage: text --export-html <input.md> [output.html]");
+            return 2;
+        }
+    };
+
+    if !markdown::is_markdown_path(&input) {
+        eprintln!("only markdown files can be exported to HTML");
+        return 1;
+    }
+
+    let settings = DB::open_read_only().ok().and_then(|db| db.theme_settings());
+    let theme = ThemeManager::with_settings(settings, ThemeMode::Light)
+        .active_theme()
+        .clone();
+
+    match export_file(&input, &output, &theme) {
+        Ok(()) => 0,
+        Err(error) => {
+            eprintln!("couldn't export {}: {error}", input.display());
+            1
+        }
+    }
+}
+
+pub fn export_file(input: &Path, output: &Path, theme: &Theme) -> io::Result<()> {
+    let content = Rope::from_str(&read_decoded(input)?.content);
+    let document = markdown::parse(&content)
+        .ok_or_else(|| io::Error::other("the markdown parser isn't available"))?;
+    let title = input
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let html = to_html(&document, &title, theme, input.parent());
+    write_atomic(output, html.as_bytes(), BackupMode::Off)
+}
+
+struct HtmlWriter<'a> {
+    html: String,
+    base_dir: Option<&'a Path>,
+    /// How often each heading id was used, repeated ones get a number.
+    slugs: HashMap<String, usize>,
+    /// Capture names used by code blocks, each gets a css class.
+    highlight_names: BTreeSet<String>,
+}
+
+impl HtmlWriter<'_> {
+    fn block(&mut self, block: &Block) {
+        match &block.kind {
+            BlockKind::Heading { level, content } => {
+                let level = (*level).clamp(1, 6);
+                let id = self.slug(&plain_text(content));
+                _ = write!(self.html, "<h{level} id=\"{}\">", escape(&id));
+                self.inlines(content);
+                _ = writeln!(self.html, "</h{level}>");
+            }
+            BlockKind::Paragraph(content) => {
+                self.html.push_str("<p>");
+                self.inlines(content);
+                self.html.push_str("</p>\n");
+            }
+            BlockKind::List { start, items } => {
+                match start {
+                    Some(1) => self.html.push_str("<ol>\n"),
+                    Some(start) => _ = writeln!(self.html, "<ol start=\"{start}\">"),
+                    None => self.html.push_str("<ul>\n"),
+                }
+                for item in items {
+                    self.list_item(item);
+                }
+                self.html.push_str(if start.is_some() {
+                    "</ol>\n"
+                } else {
+                    "</ul>\n"
+                });
+            }
+            BlockKind::Quote(blocks) => {
+                self.html.push_str("<blockquote>\n");
+                for block in blocks {
+                    self.block(block);
+                }
+                self.html.push_str("</blockquote>\n");
+            }
+            BlockKind::Code(code) => self.code(code),
+            BlockKind::Html(code) => {
+                self.html.push_str(&code.text);
+                self.html.push('\n');
+            }
+            BlockKind::Table(table) => {
+                self.html.push_str("<table>\n<thead>\n<tr>");
+                for (idx, cell) in table.header.iter().enumerate() {
+                    self.cell("th", cell, table.alignments.get(idx));
+                }
+                self.html.push_str("</tr>\n</thead>\n<tbody>\n");
+                for row in &table.rows {
+                    self.html.push_str("<tr>");
+                    for (idx, cell) in row.iter().enumerate() {
+                        self.cell("td", cell, table.alignments.get(idx));
+                    }
+                    self.html.push_str("</tr>\n");
+                }
+                self.html.push_str("</tbody>\n</table>\n");
+            }
+            BlockKind::ThematicBreak => self.html.push_str("<hr>\n"),
+        }
+    }
+
+    fn list_item(&mut self, item: &ListItem) {
+        match item.checked {
+            Some(checked) => {
+                _ = write!(
+                    self.html,
+                    "<li class=\"task\"><input type=\"checkbox\" disabled{}> ",
+                    if checked { " checked" } else { "" }
+                )
+            }
+            None => self.html.push_str("<li>"),
+        }
+
+        // a single paragraph stays inline, like in a tight list
+        match item.blocks.as_slice() {
+            [Block {
+                kind: BlockKind::Paragraph(content),
+                ..
+            }] => self.inlines(content),
+            blocks => {
+                self.html.push('\n');
+                for block in blocks {
+                    self.block(block);
+                }
+            }
+        }
+        self.html.push_str("</li>\n");
+    }
+
+    fn cell(&mut self, tag: &str, content: &[Inline], alignment: Option<&Alignment>) {
+        match alignment {
+            Some(Alignment::Left) => _ = write!(self.html, "<{tag} style=\"text-align: left\">"),
+            Some(Alignment::Center) => {
+                _ = write!(self.html, "<{tag} style=\"text-align: center\">")
+            }
+            Some(Alignment::Right) => _ = write!(self.html, "<{tag} style=\"text-align: right\">"),
+            Some(Alignment::None) | None => _ = write!(self.html, "<{tag}>"),
+        }
+        self.inlines(content);
+        _ = write!(self.html, "</{tag}>");
+    }
+
+    fn code(&mut self, code: &Code) {
+        match &code.language {
+            Some(language) => {
+                _ = write!(
+                    self.html,
+                    "<pre><code class=\"language-{}\">",
+                    escape(language)
+                )
+            }
+            None => self.html.push_str("<pre><code>"),
+        }
+
+        let mut end = 0;
+        for (range, name) in &code.highlights {
+            self.html.push_str(&escape(&code.text[end..range.start]));
+            _ = write!(
+                self.html,
+                "<span class=\"{}\">{}</span>",
+                highlight_class(name),
+                escape(&code.text[range.clone()])
+            );
+            self.highlight_names.insert(name.clone());
+            end = range.end;
+        }
+        self.html.push_str(&escape(&code.text[end..]));
+        self.html.push_str("</code></pre>\n");
+    }
+
+    fn inlines(&mut self, inlines: &[Inline]) {
+        for inline in inlines {
+            match inline {
+                Inline::Text(text) => self.html.push_str(&escape(text)),
+                Inline::Emphasis(content) => self.wrapped("em", content),
+                Inline::Strong(content) => self.wrapped("strong", content),
+                Inline::Strikethrough(content) => self.wrapped("del", content),
+                Inline::Code(text) => _ = write!(self.html, "<code>{}</code>", escape(text)),
+                Inline::Link { url, content } => {
+                    _ = write!(self.html, "<a href=\"{}\">", escape(url));
+                    self.inlines(content);
+                    self.html.push_str("</a>");
+                }
+                Inline::Image { url, alt } => {
+                    _ = write!(
+                        self.html,
+                        "<img src=\"{}\" alt=\"{}\">",
+                        escape(&self.image_source(url)),
+                        escape(alt)
+                    );
+                }
+                Inline::Html(html) => self.html.push_str(html),
+                Inline::LineBreak => self.html.push_str("<br>\n"),
+            }
+        }
+    }
+
+    fn wrapped(&mut self, tag: &str, content: &[Inline]) {
+        _ = write!(self.html, "<{tag}>");
+        self.inlines(content);
+        _ = write!(self.html, "</{tag}>");
+    }
+
+    /// Local images become data urls so the page works on its own, remote ones stay links.
+    /// Images that can't be read point to their absolute path instead.
+    fn image_source(&self, url: &str) -> String {
+        if url.contains("://") && !url.starts_with("file://") || url.starts_with("data:") {
+            return url.to_string();
+        }
+
+        let path = Path::new(url.strip_prefix("file://").unwrap_or(url));
+        let path = match self.base_dir {
+            Some(base_dir) => base_dir.join(path),
+            None => path.to_path_buf(),
+        };
+        match (image_mime_type(&path), fs::read(&path)) {
+            (Some(mime_type), Ok(bytes)) => {
+                format!("data:{mime_type};base64,{}", base64(&bytes))
+            }
+            _ => format!("file://{}", path.display()),
+        }
+    }
+
+    /// Ids like GitHub makes them, so links to `#a-heading` keep working.
+    fn slug(&mut self, heading: &str) -> String {
+        let slug: String = heading
+            .trim()
+            .to_lowercase()
+            .chars()
+            .filter_map(|c| match c {
+                ' ' | '-' => Some('-'),
+                c if c.is_alphanumeric() || c == '_' => Some(c),
+                _ => None,
+            })
+            .collect();
+
+        let count = self.slugs.entry(slug.clone()).or_insert(0);
+        *count += 1;
+        match *count {
+            1 => slug,
+            count => format!("{slug}-{}", count - 1),
+        }
+    }
+}
+
+/// The theme's colors, with print rules so the page can be saved as a pdf from a browser.
+fn stylesheet(theme: &Theme, highlight_names: &BTreeSet<String>) -> String {
+    let color = |name: &str| theme.syntax.style_for(name).and_then(|style| style.color);
+    let text = css_color(theme.editor_text);
+    let title = color("text.title").map_or(text.clone(), css_color);
+    let link = color("text.uri").map_or(css_color(theme.cursor), css_color);
+    let code = color("text.literal").map_or(text.clone(), css_color);
+    let background = css_color(theme.editor_background);
+    let code_background = css_color(theme.background);
+    let border = css_color(theme.scroll_bar_border);
+
+    let mut css = format!(
+        "\
+:root {{ color-scheme: {scheme}; }}
+html {{ background: {background}; -webkit-print-color-adjust: exact; print-color-adjust: exact; }}
+body {{ margin: 0; color: {text}; font: 16px/1.6 -apple-system, BlinkMacSystemFont, \"Segoe UI\", Helvetica, Arial, sans-serif; }}
+main {{ max-width: 48rem; margin: 0 auto; padding: 2rem; }}
+h1, h2, h3, h4, h5, h6 {{ color: {title}; line-height: 1.25; margin: 1.5em 0 0.5em; }}
+h1, h2 {{ padding-bottom: 0.3em; border-bottom: 1px solid {border}; }}
+a {{ color: {link}; }}
+img {{ max-width: 100%; }}
+code, pre {{ font-family: ui-monospace, SFMono-Regular, Menlo, Consolas, monospace; font-size: 0.9em; }}
+code {{ color: {code}; background: {code_background}; padding: 0.1em 0.3em; border-radius: 4px; }}
+pre {{ background: {code_background}; padding: 1em; border-radius: 6px; overflow-x: auto; }}
+pre code {{ color: {text}; background: none; padding: 0; font-size: 1em; }}
+blockquote {{ margin: 1em 0; padding: 0 1em; border-left: 4px solid {border}; }}
+table {{ border-collapse: collapse; }}
+th, td {{ border: 1px solid {border}; padding: 0.3em 0.8em; }}
+hr {{ border: none; border-top: 1px solid {border}; }}
+li.task {{ list-style: none; }}
+li.task input {{ margin: 0 0.4em 0 -1.4em; }}
+@page {{ margin: 2cm; }}
+@media print {{
+  main {{ max-width: none; padding: 0; }}
+  pre, blockquote, table, img {{ break-inside: avoid; }}
+  h1, h2, h3, h4, h5, h6 {{ break-after: avoid; }}
+  pre {{ white-space: pre-wrap; }}
+}}
+",
+        scheme = match theme.mode {
+            ThemeMode::Light => "light",
+            ThemeMode::Dark => "dark",
+        },
+    );
+
+    for name in highlight_names {
+        let Some(style) = theme.syntax.style_for(name) else {
+            continue;
+        };
+        let mut rules = Vec::new();
+        if let Some(color) = style.color {
+            rules.push(format!("color: {}", css_color(color)));
+        }
+        if let Some(weight) = style.font_weight {
+            rules.push(format!("font-weight: {}", weight.0 as u32));
+        }
+        if style.italic {
+            rules.push("font-style: italic".to_string());
+        }
+        let decorations: Vec<&str> = [
+            style.underline.then_some("underline"),
+            style.strikethrough.then_some("line-through"),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        if !decorations.is_empty() {
+            rules.push(format!("text-decoration: {}", decorations.join(" ")));
+        }
+
+        _ = writeln!(
+            css,
+            ".{} {{ {}; }}",
+            highlight_class(name),
+            rules.join("; ")
+        );
+    }
+
+    css
+}
+
+fn highlight_class(name: &str) -> String {
+    format!("hl-{}", name.replace('.', "-"))
+}
+
+fn css_color(color: Rgba) -> String {
+    let channel = |value: f32| (value.clamp(0., 1.) * 255.).round() as u8;
+    format!(
+        "#{:02x}{:02x}{:02x}{:02x}",
+        channel(color.r),
+        channel(color.g),
+        channel(color.b),
+        channel(color.a)
+    )
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn image_mime_type(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    Some(match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "bmp" => "image/bmp",
+        "ico" => "image/x-icon",
+        _ => return None,
+    })
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for idx in 0..4 {
+            if idx <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * idx)) as usize & 63] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
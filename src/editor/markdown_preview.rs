@@ -0,0 +1,620 @@
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use gpui::*;
+use prelude::FluentBuilder;
+
+use crate::{
+    settings_manager::CurrentSettings,
+    theme_manager::{ActiveTheme, SyntaxTheme, Theme},
+    views::text_input::{
+        buffer::BufferEdited,
+        markdown::{Alignment, Block, BlockKind, Code, Document, Inline},
+        text_input::TextInput,
+    },
+};
+
+/// Heading sizes relative to the text size, from level 1 to 6.
+const HEADING_SCALES: [f32; 6] = [2., 1.6, 1.3, 1.15, 1., 0.9];
+
+/// The rendered markdown of an editor's buffer, it scrolls along with the source.
+pub struct MarkdownPreview {
+    text_input: View<TextInput>,
+    /// None for buffers without a parse tree, see `placeholder`.
+    document: Option<Document>,
+    /// Relative links and images are resolved against it.
+    base_dir: Option<PathBuf>,
+    scroll_handle: ScrollHandle,
+    /// Source line the preview was last scrolled to, with the part of it scrolled past.
+    synced_line: Option<f32>,
+    update_task_queue: Option<Task<()>>,
+    _subscriptions: Vec<Subscription>,
+}
+
+impl MarkdownPreview {
+    pub fn new(text_input: View<TextInput>, cx: &mut ViewContext<Self>) -> Self {
+        let buffer = text_input.read(cx).buffer.clone();
+        let _subscriptions = vec![
+            cx.subscribe(&buffer, |this, _, _: &BufferEdited, cx| {
+                this.queue_update(cx)
+            }),
+            // also notified for scrolling
+            cx.observe(&text_input, |this, _, cx| this.sync_scroll(cx)),
+        ];
+
+        let mut this = Self {
+            text_input,
+            document: None,
+            base_dir: None,
+            scroll_handle: ScrollHandle::new(),
+            synced_line: None,
+            update_task_queue: None,
+            _subscriptions,
+        };
+        this.update_document(cx);
+        this
+    }
+
+    fn queue_update(&mut self, cx: &mut ViewContext<Self>) {
+        if self.update_task_queue.is_some() {
+            return;
+        }
+
+        self.update_task_queue = Some(cx.spawn(|this, mut cx| async move {
+            cx.background_executor()
+                .timer(Duration::from_millis(150))
+                .await;
+            this.update(&mut cx, |this, cx| {
+                this.update_document(cx);
+                this.update_task_queue.take();
+            })
+            .ok();
+        }));
+    }
+
+    fn update_document(&mut self, cx: &mut ViewContext<Self>) {
+        let text_input = self.text_input.read(cx);
+        let buffer = text_input.buffer.read(cx);
+        self.document = buffer
+            .parse_tree
+            .as_ref()
+            .filter(|_| text_input.is_markdown(cx))
+            .map(|tree| Document::new(&buffer.content, tree, &buffer.syntax_map));
+        self.base_dir = buffer
+            .file_path
+            .as_deref()
+            .and_then(Path::parent)
+            .map(Path::to_path_buf);
+
+        // the blocks move once the new document is laid out
+        self.synced_line = None;
+        cx.on_next_frame(|this, cx| this.sync_scroll(cx));
+        cx.notify();
+    }
+
+    /// Why there is nothing to show.
+    fn placeholder(&self, cx: &AppContext) -> &'static str {
+        let text_input = self.text_input.read(cx);
+        if !text_input.is_markdown(cx) {
+            "Only markdown files can be previewed"
+        } else if text_input.buffer.read(cx).is_large() {
+            "The file is too large to preview"
+        } else {
+            "The file couldn't be parsed"
+        }
+    }
+
+    /// Puts the block at the top line of the source at the top, interpolated between blocks.
+    fn sync_scroll(&mut self, cx: &mut ViewContext<Self>) {
+        let Some(document) = self.document.as_ref() else {
+            return;
+        };
+
+        let text_input = self.text_input.read(cx);
+        let line = source_line_at(
+            &text_input.line_heights.tops(),
+            -text_input.scroll_offset(cx).y,
+        );
+        if self.synced_line == Some(line) {
+            return;
+        }
+
+        let bounds = self.scroll_handle.bounds();
+        let mut anchors = vec![(0., px(0.))];
+        for (idx, block) in document.blocks.iter().enumerate() {
+            let Some(item) = self.scroll_handle.bounds_for_item(idx) else {
+                // not laid out yet
+                return;
+            };
+            anchors.push((block.lines.start as f32, item.top() - bounds.top()));
+            if idx + 1 == document.blocks.len() {
+                anchors.push((block.lines.end as f32, item.bottom() - bounds.top()));
+            }
+        }
+
+        let next = anchors
+            .iter()
+            .position(|(start, _)| *start > line)
+            .unwrap_or(anchors.len() - 1)
+            .max(1);
+        let (start_line, start_y) = anchors[next - 1];
+        let (end_line, end_y) = anchors[next];
+        let progress = if end_line > start_line {
+            ((line - start_line) / (end_line - start_line)).clamp(0., 1.)
+        } else {
+            0.
+        };
+
+        let content_height = anchors[anchors.len() - 1].1 + px(32.);
+        let max_y = (content_height - bounds.size.height).max(px(0.));
+        let y = (start_y + (end_y - start_y) * progress).min(max_y);
+
+        self.scroll_handle.set_offset(point(px(0.), -y));
+        self.synced_line = Some(line);
+        cx.notify();
+    }
+}
+
+impl Render for MarkdownPreview {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let theme = cx.theme();
+        let style = PreviewStyle {
+            font_family: cx.settings().font_family.clone(),
+            font_size: cx.settings().font_size,
+            text: theme.editor_text.into(),
+            title: color_for(&theme.syntax, "text.title").unwrap_or(theme.editor_text.into()),
+            link: color_for(&theme.syntax, "text.uri").unwrap_or(theme.cursor.into()),
+            code: color_for(&theme.syntax, "text.literal").unwrap_or(theme.editor_text.into()),
+            code_background: theme.background.into(),
+            border: theme.scroll_bar_border.into(),
+            theme: theme.clone(),
+        };
+        let mut renderer = Renderer {
+            style: &style,
+            base_dir: self.base_dir.as_deref(),
+            next_id: 0,
+        };
+
+        let blocks: Vec<AnyElement> = match self.document.as_ref() {
+            Some(document) => document
+                .blocks
+                .iter()
+                .map(|block| renderer.block(block))
+                .collect(),
+            None => vec![div().child(self.placeholder(cx)).into_any_element()],
+        };
+
+        div()
+            .id("markdown-preview")
+            .size_full()
+            .p_4()
+            .flex()
+            .flex_col()
+            .gap_3()
+            .overflow_y_scroll()
+            .track_scroll(&self.scroll_handle)
+            .children(blocks)
+    }
+}
+
+struct PreviewStyle {
+    font_family: SharedString,
+    font_size: Pixels,
+    text: Hsla,
+    title: Hsla,
+    link: Hsla,
+    code: Hsla,
+    code_background: Hsla,
+    border: Hsla,
+    theme: Arc<Theme>,
+}
+
+#[derive(Clone, Copy, Default)]
+struct InlineStyle {
+    title: bool,
+    strong: bool,
+    emphasis: bool,
+    strikethrough: bool,
+    code: bool,
+    link: bool,
+}
+
+/// Text laid out as one element, links are clickable ranges of it.
+#[derive(Default)]
+struct StyledRuns {
+    text: String,
+    runs: Vec<TextRun>,
+    links: Vec<(Range<usize>, String)>,
+}
+
+/// Images can't be part of a text, they split it.
+enum Segment {
+    Text(StyledRuns),
+    Image(String),
+}
+
+struct Renderer<'a> {
+    style: &'a PreviewStyle,
+    base_dir: Option<&'a Path>,
+    next_id: usize,
+}
+
+impl Renderer<'_> {
+    fn block(&mut self, block: &Block) -> AnyElement {
+        match &block.kind {
+            BlockKind::Heading { level, content } => {
+                let scale = HEADING_SCALES[(*level).clamp(1, 6) - 1];
+                div()
+                    .text_size(self.style.font_size * scale)
+                    .line_height(relative(1.4))
+                    .when(*level <= 2, |el| {
+                        el.pb_1().border_b_1().border_color(self.style.border)
+                    })
+                    .child(self.inlines(
+                        content,
+                        InlineStyle {
+                            title: true,
+                            ..Default::default()
+                        },
+                    ))
+                    .into_any_element()
+            }
+            BlockKind::Paragraph(content) => self.inlines(content, InlineStyle::default()),
+            BlockKind::List { start, items } => div()
+                .flex()
+                .flex_col()
+                .gap_1()
+                .children(items.iter().enumerate().map(|(idx, item)| {
+                    let marker = match (item.checked, start) {
+                        (Some(true), _) => "☑".to_string(),
+                        (Some(false), _) => "☐".to_string(),
+                        (None, Some(start)) => format!("{}.", start + idx),
+                        (None, None) => "•".to_string(),
+                    };
+                    div()
+                        .flex()
+                        .gap_2()
+                        .child(div().flex_none().child(marker))
+                        .child(
+                            div()
+                                .flex()
+                                .flex_col()
+                                .gap_1()
+                                .children(item.blocks.iter().map(|block| self.block(block))),
+                        )
+                }))
+                .into_any_element(),
+            BlockKind::Quote(blocks) => div()
+                .flex()
+                .flex_col()
+                .gap_2()
+                .pl_3()
+                .border_l_4()
+                .border_color(self.style.border)
+                .children(blocks.iter().map(|block| self.block(block)))
+                .into_any_element(),
+            BlockKind::Code(code) | BlockKind::Html(code) => self.code(code),
+            BlockKind::Table(table) => {
+                let column_count = table
+                    .rows
+                    .iter()
+                    .map(Vec::len)
+                    .chain([table.header.len()])
+                    .max()
+                    .unwrap_or(0);
+                let alignments = table.alignments.clone();
+                let header = self.table_row(&table.header, column_count, &alignments, true);
+                let rows: Vec<AnyElement> = table
+                    .rows
+                    .iter()
+                    .map(|row| self.table_row(row, column_count, &alignments, false))
+                    .collect();
+
+                div()
+                    .flex()
+                    .flex_col()
+                    .border_1()
+                    .border_color(self.style.border)
+                    .child(header)
+                    .children(rows)
+                    .into_any_element()
+            }
+            BlockKind::ThematicBreak => div()
+                .h(px(1.))
+                .my_2()
+                .bg(self.style.border)
+                .into_any_element(),
+        }
+    }
+
+    fn table_row(
+        &mut self,
+        cells: &[Vec<Inline>],
+        column_count: usize,
+        alignments: &[Alignment],
+        is_header: bool,
+    ) -> AnyElement {
+        let empty = Vec::new();
+        div()
+            .flex()
+            .when(is_header, |el| {
+                el.border_b_1().border_color(self.style.border)
+            })
+            .children((0..column_count).map(|idx| {
+                let alignment = alignments.get(idx).copied().unwrap_or(Alignment::None);
+                let content = cells.get(idx).unwrap_or(&empty);
+                div()
+                    .flex()
+                    .flex_1()
+                    .px_2()
+                    .py_1()
+                    .when(alignment == Alignment::Center, |el| el.justify_center())
+                    .when(alignment == Alignment::Right, |el| el.justify_end())
+                    .child(self.inlines(
+                        content,
+                        InlineStyle {
+                            strong: is_header,
+                            ..Default::default()
+                        },
+                    ))
+            }))
+            .into_any_element()
+    }
+
+    fn code(&mut self, code: &Code) -> AnyElement {
+        let base = self.run(0, InlineStyle::default());
+        let mut runs = Vec::new();
+        let mut end = 0;
+        for (range, name) in &code.highlights {
+            if range.start > end {
+                runs.push(TextRun {
+                    len: range.start - end,
+                    ..base.clone()
+                });
+            }
+            let style = self.style.theme.syntax.style_for(name);
+            let mut font = base.font.clone();
+            if let Some(weight) = style.and_then(|style| style.font_weight) {
+                font.weight = weight;
+            }
+            if style.is_some_and(|style| style.italic) {
+                font.style = FontStyle::Italic;
+            }
+            runs.push(TextRun {
+                len: range.len(),
+                font,
+                color: style
+                    .and_then(|style| style.color)
+                    .map_or(base.color, Hsla::from),
+                ..base.clone()
+            });
+            end = range.end;
+        }
+        if code.text.len() > end {
+            runs.push(TextRun {
+                len: code.text.len() - end,
+                ..base.clone()
+            });
+        }
+
+        div()
+            .p_2()
+            .rounded_md()
+            .bg(self.style.code_background)
+            .when(code.text.is_empty(), |el| el.h(px(8.)))
+            .when(!code.text.is_empty(), |el| {
+                el.child(StyledText::new(code.text.clone()).with_runs(runs))
+            })
+            .into_any_element()
+    }
+
+    fn inlines(&mut self, inlines: &[Inline], style: InlineStyle) -> AnyElement {
+        let mut segments = vec![Segment::Text(StyledRuns::default())];
+        self.push_inlines(inlines, style, &mut segments);
+
+        div()
+            .flex()
+            .flex_col()
+            .gap_1()
+            .children(segments.into_iter().filter_map(|segment| match segment {
+                Segment::Text(runs) if runs.text.is_empty() => None,
+                Segment::Text(runs) => Some(self.text(runs)),
+                Segment::Image(url) => Some(self.image(&url)),
+            }))
+            .into_any_element()
+    }
+
+    fn push_inlines(&self, inlines: &[Inline], style: InlineStyle, segments: &mut Vec<Segment>) {
+        for inline in inlines {
+            match inline {
+                Inline::Text(text) => self.push_text(text, style, segments),
+                Inline::Emphasis(content) => self.push_inlines(
+                    content,
+                    InlineStyle {
+                        emphasis: true,
+                        ..style
+                    },
+                    segments,
+                ),
+                Inline::Strong(content) => self.push_inlines(
+                    content,
+                    InlineStyle {
+                        strong: true,
+                        ..style
+                    },
+                    segments,
+                ),
+                Inline::Strikethrough(content) => self.push_inlines(
+                    content,
+                    InlineStyle {
+                        strikethrough: true,
+                        ..style
+                    },
+                    segments,
+                ),
+                Inline::Code(text) => self.push_text(
+                    text,
+                    InlineStyle {
+                        code: true,
+                        ..style
+                    },
+                    segments,
+                ),
+                Inline::Link { url, content } => {
+                    let start = current_len(segments);
+                    self.push_inlines(
+                        content,
+                        InlineStyle {
+                            link: true,
+                            ..style
+                        },
+                        segments,
+                    );
+                    if let Some(Segment::Text(runs)) = segments.last_mut() {
+                        let start = start.min(runs.text.len());
+                        if start < runs.text.len() {
+                            runs.links.push((start..runs.text.len(), url.clone()));
+                        }
+                    }
+                }
+                Inline::Image { url, .. } => {
+                    segments.push(Segment::Image(url.clone()));
+                    segments.push(Segment::Text(StyledRuns::default()));
+                }
+                Inline::LineBreak => self.push_text("\n", style, segments),
+                Inline::Html(_) => {}
+            }
+        }
+    }
+
+    fn push_text(&self, text: &str, style: InlineStyle, segments: &mut [Segment]) {
+        let Some(Segment::Text(runs)) = segments.last_mut() else {
+            return;
+        };
+        runs.text.push_str(text);
+        runs.runs.push(self.run(text.len(), style));
+    }
+
+    fn run(&self, len: usize, style: InlineStyle) -> TextRun {
+        let mut font = font(self.style.font_family.clone());
+        if style.strong || style.title {
+            font.weight = FontWeight::BOLD;
+        }
+        if style.emphasis {
+            font.style = FontStyle::Italic;
+        }
+
+        let color = if style.link {
+            self.style.link
+        } else if style.code {
+            self.style.code
+        } else if style.title {
+            self.style.title
+        } else {
+            self.style.text
+        };
+
+        TextRun {
+            len,
+            font,
+            color,
+            background_color: style.code.then_some(self.style.code_background),
+            underline: style.link.then_some(UnderlineStyle {
+                color: Some(color),
+                thickness: px(1.),
+                wavy: false,
+            }),
+            strikethrough: style.strikethrough.then_some(StrikethroughStyle {
+                color: Some(color),
+                thickness: px(1.),
+            }),
+        }
+    }
+
+    fn text(&mut self, runs: StyledRuns) -> AnyElement {
+        let text = StyledText::new(runs.text).with_runs(runs.runs);
+        if runs.links.is_empty() {
+            return text.into_any_element();
+        }
+
+        self.next_id += 1;
+        let (ranges, urls): (Vec<Range<usize>>, Vec<String>) = runs
+            .links
+            .into_iter()
+            .map(|(range, url)| (range, self.resolve(&url)))
+            .unzip();
+        InteractiveText::new(("preview-text", self.next_id), text)
+            .on_click(ranges, move |idx, cx| {
+                if !urls[idx].is_empty() {
+                    cx.open_url(&urls[idx]);
+                }
+            })
+            .into_any_element()
+    }
+
+    fn image(&self, url: &str) -> AnyElement {
+        let source: ImageSource = if is_remote(url) {
+            SharedUri::from(url.to_string()).into()
+        } else {
+            self.local_path(url).into()
+        };
+        img(source).max_w_full().into_any_element()
+    }
+
+    /// Urls to open, relative paths become file urls and anchors are dropped.
+    fn resolve(&self, url: &str) -> String {
+        if url.starts_with('#') {
+            String::new()
+        } else if is_remote(url) || url.starts_with("mailto:") {
+            url.to_string()
+        } else {
+            format!("file://{}", self.local_path(url).display())
+        }
+    }
+
+    fn local_path(&self, url: &str) -> PathBuf {
+        let path = url.strip_prefix("file://").unwrap_or(url);
+        match self.base_dir {
+            Some(base_dir) => base_dir.join(path),
+            None => PathBuf::from(path),
+        }
+    }
+}
+
+fn current_len(segments: &[Segment]) -> usize {
+    match segments.last() {
+        Some(Segment::Text(runs)) => runs.text.len(),
+        _ => 0,
+    }
+}
+
+fn is_remote(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+fn color_for(syntax: &SyntaxTheme, name: &str) -> Option<Hsla> {
+    syntax.style_for(name)?.color.map(Hsla::from)
+}
+
+/// The line at `y` in the source, with the part of it above `y` as the fraction.
+fn source_line_at(tops: &[Pixels], y: Pixels) -> f32 {
+    if tops.len() < 2 {
+        return 0.;
+    }
+
+    let line_idx = tops
+        .partition_point(|top| *top <= y)
+        .saturating_sub(1)
+        .min(tops.len() - 2);
+    let height = tops[line_idx + 1] - tops[line_idx];
+    let fraction = if height > px(0.) {
+        ((y - tops[line_idx]) / height).clamp(0., 1.)
+    } else {
+        0.
+    };
+    line_idx as f32 + fraction
+}
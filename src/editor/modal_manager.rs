@@ -1,5 +1,8 @@
 use gpui::*;
 
+// shared by the modals, bound in their own contexts
+actions!(modal, [Up, Down, Select, Close]);
+
 pub trait ModalView: ManagedView {}
 
 trait ModalViewHandle {
@@ -0,0 +1,329 @@
+use std::{cmp::Reverse, collections::HashSet};
+
+use gpui::*;
+use prelude::FluentBuilder;
+
+use crate::{
+    fuzzy::fuzzy_match,
+    theme_manager::ActiveTheme,
+    views::{
+        match_label::MatchLabel,
+        text_input::{
+            markdown::{headings, Heading},
+            text_input::TextInput,
+        },
+    },
+};
+
+use super::picker::{Picker, PickerDelegate};
+
+actions!(outline, [Fold, Unfold]);
+
+const INDENT: f32 = 16.;
+
+struct HeadingMatch {
+    heading_idx: usize,
+    positions: Vec<usize>,
+}
+
+/// The headings of the markdown buffer as a tree, filtering by name flattens it.
+pub struct Outline {
+    text_input: View<TextInput>,
+    headings: Vec<Heading>,
+    /// Headings whose sections are collapsed.
+    folded: HashSet<usize>,
+    /// The heading of the section the cursor is in.
+    current_idx: Option<usize>,
+    /// Folding only applies while the list isn't filtered.
+    is_tree: bool,
+    matches: Vec<HeadingMatch>,
+    _subscriptions: Vec<Subscription>,
+}
+
+impl Outline {
+    pub fn new(text_input: View<TextInput>, cx: &mut ViewContext<Picker<Self>>) -> Self {
+        let buffer = text_input.read(cx).buffer.read(cx);
+        // large files aren't parsed, they have no outline
+        let headings = buffer
+            .parse_tree
+            .as_ref()
+            .map(|tree| headings(&buffer.content, tree, &buffer.syntax_map))
+            .unwrap_or_default();
+
+        let mut this = Self {
+            text_input: text_input.clone(),
+            headings,
+            folded: HashSet::new(),
+            current_idx: None,
+            is_tree: true,
+            matches: Vec::new(),
+            _subscriptions: vec![cx.observe(&text_input, |picker, _, cx| {
+                picker.delegate.update_current(cx)
+            })],
+        };
+        this.update_current(cx);
+        this
+    }
+
+    /// The last heading before the cursor.
+    fn update_current(&mut self, cx: &mut ViewContext<Picker<Self>>) {
+        let text_input = self.text_input.read(cx);
+        let cursor_line = text_input.content.char_to_line(text_input.cursor_offset());
+        let current_idx = self
+            .headings
+            .partition_point(|heading| heading.line <= cursor_line)
+            .checked_sub(1);
+
+        if self.current_idx != current_idx {
+            self.current_idx = current_idx;
+            cx.notify();
+        }
+    }
+
+    /// Headings that aren't inside a folded section.
+    fn visible_headings(&self) -> Vec<usize> {
+        let mut visible = Vec::new();
+        let mut folded_level = None;
+        for (idx, heading) in self.headings.iter().enumerate() {
+            if let Some(level) = folded_level {
+                if heading.level > level {
+                    continue;
+                }
+                folded_level = None;
+            }
+            visible.push(idx);
+            if self.folded.contains(&idx) {
+                folded_level = Some(heading.level);
+            }
+        }
+        visible
+    }
+
+    /// Row of the heading, or of the folded section hiding it.
+    fn visible_idx(&self, heading_idx: usize) -> Option<usize> {
+        self.matches
+            .iter()
+            .rposition(|m| m.heading_idx <= heading_idx)
+    }
+
+    fn has_children(&self, heading_idx: usize) -> bool {
+        self.headings
+            .get(heading_idx + 1)
+            .is_some_and(|next| next.level > self.headings[heading_idx].level)
+    }
+
+    fn parent(&self, heading_idx: usize) -> Option<usize> {
+        let level = self.headings[heading_idx].level;
+        self.headings[..heading_idx]
+            .iter()
+            .rposition(|heading| heading.level < level)
+    }
+
+    fn selected_heading(picker: &Picker<Self>) -> Option<usize> {
+        let outline = &picker.delegate;
+        outline
+            .matches
+            .get(picker.selection_idx())
+            .map(|m| m.heading_idx)
+    }
+
+    /// Collapses the selected section, or moves to its parent when there is nothing to collapse.
+    fn fold(picker: &mut Picker<Self>, _: &Fold, cx: &mut ViewContext<Picker<Self>>) {
+        let Some(heading_idx) = Self::selected_heading(picker) else {
+            return;
+        };
+        let outline = &picker.delegate;
+        if !outline.is_tree {
+            return;
+        }
+
+        if outline.has_children(heading_idx) && !outline.folded.contains(&heading_idx) {
+            Self::set_folded(picker, heading_idx, true, cx);
+        } else if let Some(parent_idx) = outline.parent(heading_idx) {
+            Self::select_heading(picker, parent_idx, cx);
+        }
+    }
+
+    fn unfold(picker: &mut Picker<Self>, _: &Unfold, cx: &mut ViewContext<Picker<Self>>) {
+        if let Some(heading_idx) = Self::selected_heading(picker) {
+            if picker.delegate.is_tree {
+                Self::set_folded(picker, heading_idx, false, cx);
+            }
+        }
+    }
+
+    fn toggle_fold(
+        picker: &mut Picker<Self>,
+        heading_idx: usize,
+        cx: &mut ViewContext<Picker<Self>>,
+    ) {
+        let is_folded = picker.delegate.folded.contains(&heading_idx);
+        Self::set_folded(picker, heading_idx, !is_folded, cx);
+    }
+
+    /// Keeps the same heading selected while rows appear or disappear below it.
+    fn set_folded(
+        picker: &mut Picker<Self>,
+        heading_idx: usize,
+        folded: bool,
+        cx: &mut ViewContext<Picker<Self>>,
+    ) {
+        if folded {
+            picker.delegate.folded.insert(heading_idx);
+        } else {
+            picker.delegate.folded.remove(&heading_idx);
+        }
+        picker.update_matches(cx);
+        Self::select_heading(picker, heading_idx, cx);
+    }
+
+    fn select_heading(
+        picker: &mut Picker<Self>,
+        heading_idx: usize,
+        cx: &mut ViewContext<Picker<Self>>,
+    ) {
+        if let Some(idx) = picker.delegate.visible_idx(heading_idx) {
+            picker.select(idx, cx);
+        }
+    }
+}
+
+impl PickerDelegate for Outline {
+    const KEY_CONTEXT: &'static str = "Outline";
+    const MAX_VISIBLE_MATCHES: usize = 16;
+
+    fn match_count(&self) -> usize {
+        self.matches.len()
+    }
+
+    /// The tree in document order for an empty query, best matches first otherwise.
+    fn update_matches(&mut self, query: String, _cx: &mut ViewContext<Picker<Self>>) {
+        self.is_tree = query.is_empty();
+
+        if self.is_tree {
+            self.matches = self
+                .visible_headings()
+                .into_iter()
+                .map(|heading_idx| HeadingMatch {
+                    heading_idx,
+                    positions: Vec::new(),
+                })
+                .collect();
+        } else {
+            let mut ranked: Vec<(i32, HeadingMatch)> = self
+                .headings
+                .iter()
+                .enumerate()
+                .filter_map(|(heading_idx, heading)| {
+                    let fuzzy_match = fuzzy_match(&query, &heading.text)?;
+                    Some((
+                        fuzzy_match.score,
+                        HeadingMatch {
+                            heading_idx,
+                            positions: fuzzy_match.positions,
+                        },
+                    ))
+                })
+                .collect();
+            ranked.sort_by_key(|(score, _)| Reverse(*score));
+
+            self.matches = ranked.into_iter().map(|(_, m)| m).collect();
+        }
+    }
+
+    /// The section the cursor is in for the tree.
+    fn initial_selection(&self) -> usize {
+        if !self.is_tree {
+            return 0;
+        }
+        self.current_idx
+            .and_then(|current_idx| self.visible_idx(current_idx))
+            .unwrap_or(0)
+    }
+
+    /// Puts the cursor at the start of the selected heading.
+    fn confirm(&mut self, idx: usize, cx: &mut ViewContext<Picker<Self>>) {
+        let line = self.headings[self.matches[idx].heading_idx].line;
+
+        cx.emit(DismissEvent);
+        self.text_input.update(cx, |text_input, cx| {
+            let offset = text_input.offset_for_line_column(line, 0);
+            text_input.move_to(offset, cx);
+        });
+    }
+
+    fn render_match(&self, idx: usize, cx: &mut ViewContext<Picker<Self>>) -> AnyElement {
+        let heading_idx = self.matches[idx].heading_idx;
+        let heading = &self.headings[heading_idx];
+        let min_level = self
+            .headings
+            .iter()
+            .map(|heading| heading.level)
+            .min()
+            .unwrap_or(1);
+        // a folded section that hides the current one stands in for it
+        let current_idx = if self.is_tree {
+            self.current_idx
+                .and_then(|current_idx| self.visible_idx(current_idx))
+                .map(|idx| self.matches[idx].heading_idx)
+        } else {
+            self.current_idx
+        };
+        let indent = if self.is_tree {
+            (heading.level - min_level) as f32 * INDENT
+        } else {
+            0.
+        };
+        let chevron = if !self.is_tree || !self.has_children(heading_idx) {
+            None
+        } else if self.folded.contains(&heading_idx) {
+            Some("▸")
+        } else {
+            Some("▾")
+        };
+
+        div()
+            .flex()
+            .justify_between()
+            .gap_4()
+            .when(current_idx == Some(heading_idx), |el| {
+                el.text_color(cx.theme().cursor)
+            })
+            .child(
+                div()
+                    .flex()
+                    .pl(px(indent))
+                    .child(div().id(("fold", idx)).w(px(INDENT)).flex_none().when_some(
+                        chevron,
+                        |el, chevron| {
+                            el.child(chevron)
+                                .on_click(cx.listener(move |picker, _, cx| {
+                                    cx.stop_propagation();
+                                    Self::toggle_fold(picker, heading_idx, cx);
+                                }))
+                        },
+                    ))
+                    .child(MatchLabel::new(
+                        heading.text.clone(),
+                        self.matches[idx].positions.clone(),
+                    )),
+            )
+            .child((heading.line + 1).to_string())
+            .into_any_element()
+    }
+
+    fn status(&self) -> Option<&'static str> {
+        if self.headings.is_empty() {
+            Some("No headings")
+        } else if self.matches.is_empty() {
+            Some("No matching headings")
+        } else {
+            None
+        }
+    }
+
+    fn register_actions(el: Div, cx: &mut ViewContext<Picker<Self>>) -> Div {
+        el.on_action(cx.listener(Self::fold))
+            .on_action(cx.listener(Self::unfold))
+    }
+}
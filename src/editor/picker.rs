@@ -0,0 +1,202 @@
+use gpui::*;
+use prelude::FluentBuilder;
+
+use crate::{
+    settings_manager::CurrentSettings,
+    theme_manager::ActiveTheme,
+    views::text_input::text_input::{ContentChanged, NewLine, TextInput, TextInputMode},
+};
+
+use super::modal_manager::{Close, Down, ModalView, Up};
+
+/// What a picker lists and what picking a row does, the picker handles the query, the
+/// selection and scrolling.
+pub trait PickerDelegate: Sized + 'static {
+    /// Bindings for the picker go in this context of the keymaps.
+    const KEY_CONTEXT: &'static str;
+    const WIDTH: f32 = 560.;
+    /// Rows shown at once, the list scrolls to keep the selection in view.
+    const MAX_VISIBLE_MATCHES: usize = 12;
+
+    fn match_count(&self) -> usize;
+
+    /// Filters for `query`. Delegates that match in the background call
+    /// `Picker::matches_updated` once they are done.
+    fn update_matches(&mut self, query: String, cx: &mut ViewContext<Picker<Self>>);
+
+    /// The row selected after the matches changed.
+    fn initial_selection(&self) -> usize {
+        0
+    }
+
+    fn confirm(&mut self, idx: usize, cx: &mut ViewContext<Picker<Self>>);
+
+    /// The content of a row, the picker adds the selection, hover and click handling.
+    fn render_match(&self, idx: usize, cx: &mut ViewContext<Picker<Self>>) -> AnyElement;
+
+    /// Shown below the rows, like when nothing matched.
+    fn status(&self) -> Option<&'static str>;
+
+    /// Actions the picker handles besides moving the selection.
+    fn register_actions(el: Div, _cx: &mut ViewContext<Picker<Self>>) -> Div {
+        el
+    }
+}
+
+/// A query above a filtered list, shown as a modal.
+pub struct Picker<D: PickerDelegate> {
+    pub delegate: D,
+    query: View<TextInput>,
+    selection_idx: usize,
+    scroll_top: usize,
+    _subscriptions: Vec<Subscription>,
+}
+
+impl<D: PickerDelegate> Picker<D> {
+    pub fn new(delegate: D, cx: &mut ViewContext<Self>) -> Self {
+        let query = cx.new_view(|cx| TextInput::new(TextInputMode::SingleLine, cx));
+
+        let mut this = Self {
+            delegate,
+            query: query.clone(),
+            selection_idx: 0,
+            scroll_top: 0,
+            _subscriptions: vec![
+                cx.subscribe(&query, |this, _, _: &ContentChanged, cx| {
+                    this.update_matches(cx)
+                }),
+                cx.subscribe(&query, |this, _, _: &NewLine, cx| this.confirm(cx)),
+            ],
+        };
+        this.update_matches(cx);
+        this
+    }
+
+    pub fn query(&self, cx: &AppContext) -> String {
+        self.query.read(cx).content.to_string()
+    }
+
+    pub fn update_matches(&mut self, cx: &mut ViewContext<Self>) {
+        let query = self.query(cx);
+        self.delegate.update_matches(query, cx);
+        self.matches_updated(cx);
+    }
+
+    pub fn matches_updated(&mut self, cx: &mut ViewContext<Self>) {
+        self.selection_idx = self.delegate.initial_selection();
+        self.scroll_top = 0;
+        self.scroll_to_selection();
+        cx.notify();
+    }
+
+    pub fn selection_idx(&self) -> usize {
+        self.selection_idx
+    }
+
+    pub fn select(&mut self, idx: usize, cx: &mut ViewContext<Self>) {
+        self.selection_idx = idx;
+        self.scroll_to_selection();
+        cx.notify();
+    }
+
+    fn up(&mut self, _: &Up, cx: &mut ViewContext<Self>) {
+        let match_count = self.delegate.match_count();
+        if match_count == 0 {
+            return;
+        }
+        if self.selection_idx == 0 {
+            self.select(match_count - 1, cx);
+        } else {
+            self.select(self.selection_idx - 1, cx);
+        }
+    }
+
+    fn down(&mut self, _: &Down, cx: &mut ViewContext<Self>) {
+        let match_count = self.delegate.match_count();
+        if match_count == 0 {
+            return;
+        }
+        if self.selection_idx >= match_count - 1 {
+            self.select(0, cx);
+        } else {
+            self.select(self.selection_idx + 1, cx);
+        }
+    }
+
+    fn scroll_to_selection(&mut self) {
+        if self.selection_idx < self.scroll_top {
+            self.scroll_top = self.selection_idx;
+        } else if self.selection_idx >= self.scroll_top + D::MAX_VISIBLE_MATCHES {
+            self.scroll_top = self.selection_idx + 1 - D::MAX_VISIBLE_MATCHES;
+        }
+    }
+
+    fn confirm(&mut self, cx: &mut ViewContext<Self>) {
+        if self.selection_idx < self.delegate.match_count() {
+            self.delegate.confirm(self.selection_idx, cx);
+        }
+    }
+
+    fn close(&mut self, _: &Close, cx: &mut ViewContext<Self>) {
+        cx.emit(DismissEvent);
+    }
+}
+
+impl<D: PickerDelegate> Render for Picker<D> {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let match_count = self.delegate.match_count();
+        let visible = self.scroll_top.min(match_count)
+            ..(self.scroll_top + D::MAX_VISIBLE_MATCHES).min(match_count);
+
+        let el = div()
+            .w(px(D::WIDTH))
+            .p_2()
+            .rounded_md()
+            .font_family(cx.settings().font_family.clone())
+            .line_height(cx.settings().line_height)
+            .text_size(cx.settings().font_size)
+            .key_context(D::KEY_CONTEXT)
+            .on_action(cx.listener(Self::up))
+            .on_action(cx.listener(Self::down))
+            .on_action(cx.listener(Self::close));
+
+        D::register_actions(el, cx)
+            .bg(cx.theme().background)
+            .text_color(cx.theme().editor_text)
+            .child(
+                div()
+                    .mb_1()
+                    .rounded_md()
+                    .bg(cx.theme().editor_background)
+                    .child(self.query.clone()),
+            )
+            .children(visible.map(|idx| {
+                div()
+                    .id(idx)
+                    .p_1()
+                    .rounded_md()
+                    .cursor_pointer()
+                    .when(idx == self.selection_idx, |el| {
+                        el.bg(cx.theme().editor_background)
+                    })
+                    .hover(|el| el.bg(cx.theme().hover_bg))
+                    .on_click(cx.listener(move |this, _, cx| {
+                        this.selection_idx = idx;
+                        this.confirm(cx);
+                    }))
+                    .child(self.delegate.render_match(idx, cx))
+            }))
+            .when_some(self.delegate.status(), |el, status| {
+                el.child(div().p_1().child(status))
+            })
+    }
+}
+
+impl<D: PickerDelegate> ModalView for Picker<D> {}
+impl<D: PickerDelegate> EventEmitter<DismissEvent> for Picker<D> {}
+
+impl<D: PickerDelegate> FocusableView for Picker<D> {
+    fn focus_handle(&self, cx: &AppContext) -> FocusHandle {
+        self.query.read(cx).focus_handle.clone()
+    }
+}
@@ -2,6 +2,7 @@ use std::ops::Range;
 
 use aho_corasick::AhoCorasickBuilder;
 use gpui::*;
+use regex::{Regex, RegexBuilder};
 
 use crate::{
     settings_manager::CurrentSettings,
@@ -17,34 +18,60 @@ actions!(search, [Close]);
 pub struct SearchView {
     show: bool,
     view: View<TextInput>,
+    replace_view: View<TextInput>,
     text_input: WeakView<TextInput>,
     last_term: Option<String>,
     last_highlight_idx: Option<usize>,
     last_matches: Option<Vec<Range<usize>>>,
+    /// Buffer version the matches were found in.
+    last_version: Option<usize>,
     case_insensitive: bool,
+    use_regex: bool,
     _subscriptions: Vec<Subscription>,
 }
 
 impl SearchView {
     pub fn new(text_input: WeakView<TextInput>, cx: &mut ViewContext<Self>) -> Self {
         let search_view = cx.new_view(|cx| TextInput::new(TextInputMode::SingleLine, cx));
-
-        cx.bind_keys([KeyBinding::new("escape", Close, None)]);
+        let replace_view = cx.new_view(|cx| TextInput::new(TextInputMode::SingleLine, cx));
 
         Self {
             show: false,
             view: search_view.clone(),
+            replace_view: replace_view.clone(),
             text_input,
             last_term: None,
             last_highlight_idx: None,
             last_matches: None,
+            last_version: None,
             case_insensitive: true,
+            use_regex: false,
             _subscriptions: vec![
-                cx.subscribe(&search_view, |this, _, _: &NewLine, cx| this.search(cx))
+                cx.subscribe(&search_view, |this, _, _: &NewLine, cx| this.search(cx)),
+                cx.subscribe(&replace_view, |this, _, _: &NewLine, cx| {
+                    this.replace_next(cx)
+                }),
             ],
         }
     }
 
+    pub fn set_text_input(&mut self, text_input: WeakView<TextInput>, cx: &mut ViewContext<Self>) {
+        if self.text_input == text_input {
+            return;
+        }
+
+        if let Some(text_input) = self.text_input.upgrade() {
+            text_input.update(cx, |text_input, cx| text_input.clear_highlights(cx));
+        }
+
+        self.text_input = text_input;
+        self.last_term = None;
+        self.last_highlight_idx = None;
+        self.last_matches = None;
+        self.last_version = None;
+        cx.notify();
+    }
+
     pub fn show(&mut self, cx: &mut ViewContext<Self>) {
         self.show = true;
         cx.focus_view(&self.view);
@@ -72,8 +99,7 @@ impl SearchView {
             return;
         }
 
-        // this can't be right?
-        if self.last_term.clone().is_some_and(|t| t == *term) {
+        if self.last_term.clone().is_some_and(|t| t == *term) && !self.is_outdated(cx) {
             if let Some(last_idx) = self.last_highlight_idx {
                 if let Some(last_matches) = &self.last_matches {
                     let new_idx = if !last_matches.is_empty() && last_idx < last_matches.len() - 1 {
@@ -93,28 +119,177 @@ impl SearchView {
             return;
         }
 
+        self.search_from(0, cx);
+    }
+
+    /// Runs a fresh search and selects the first match at or after `byte_idx`.
+    fn search_from(&mut self, byte_idx: usize, cx: &mut ViewContext<Self>) {
+        let Some(text_input) = self.text_input.upgrade() else {
+            return;
+        };
+
+        let term = self.view.read(cx).content.to_string();
+
+        if term.is_empty() {
+            return;
+        }
+
+        let haystack = text_input.read(cx).content.to_string();
+        let matches = self.find_matches(&term, &haystack);
+
+        let highlight_idx = matches
+            .iter()
+            .position(|m| m.start >= byte_idx)
+            .unwrap_or(0);
+
+        self.last_term = Some(term);
+        self.last_highlight_idx = Some(highlight_idx);
+        self.last_matches = Some(matches.clone());
+        self.last_version = Some(text_input.read(cx).buffer.read(cx).version);
+
+        text_input.update(cx, |text_input, cx| {
+            text_input.highlight(matches.clone(), cx);
+            if let Some(current) = matches.get(highlight_idx) {
+                text_input.update_selected_range_bytes(current, cx);
+            }
+        });
+        cx.notify();
+    }
+
+    fn find_matches(&self, term: &str, haystack: &str) -> Vec<Range<usize>> {
+        if self.use_regex {
+            let Some(regex) = self.build_regex(term) else {
+                return vec![];
+            };
+
+            return regex
+                .find_iter(haystack)
+                .filter(|mat| !mat.is_empty())
+                .map(|mat| mat.range())
+                .collect();
+        }
+
         let ac = AhoCorasickBuilder::new()
             .ascii_case_insensitive(self.case_insensitive)
             .build([term])
             .unwrap();
 
+        ac.find_iter(haystack)
+            .map(|mat| mat.span().range())
+            .collect()
+    }
+
+    fn build_regex(&self, term: &str) -> Option<Regex> {
+        RegexBuilder::new(term)
+            .case_insensitive(self.case_insensitive)
+            .multi_line(true)
+            .build()
+            .ok()
+    }
+
+    /// The buffer was edited since the last search, its match ranges can't be used anymore.
+    fn is_outdated(&self, cx: &AppContext) -> bool {
+        let Some(text_input) = self.text_input.upgrade() else {
+            return true;
+        };
+        self.last_version != Some(text_input.read(cx).buffer.read(cx).version)
+    }
+
+    /// Expands `$1` / `${name}` capture references when in regex mode.
+    fn replacement_for(&self, haystack: &str, range: &Range<usize>, replace: &str) -> String {
+        if !self.use_regex {
+            return replace.to_string();
+        }
+
+        let Some(regex) = self
+            .last_term
+            .as_ref()
+            .and_then(|term| self.build_regex(term))
+        else {
+            return replace.to_string();
+        };
+
+        let mut replacement = String::new();
+        if let Some(captures) = regex.captures_at(haystack, range.start) {
+            captures.expand(replace, &mut replacement);
+        }
+        replacement
+    }
+
+    fn replace_next(&mut self, cx: &mut ViewContext<Self>) {
+        let Some(text_input) = self.text_input.upgrade() else {
+            return;
+        };
+
+        // edits move the matches, search again from the selection
+        if self.last_matches.is_none() || self.is_outdated(cx) {
+            let input = text_input.read(cx);
+            let byte_idx = input.content.char_to_byte(input.selected_range.start);
+            self.search_from(byte_idx, cx);
+        }
+
+        let (Some(idx), Some(matches)) = (self.last_highlight_idx, &self.last_matches) else {
+            return;
+        };
+        let Some(range) = matches.get(idx).cloned() else {
+            return;
+        };
+
         let haystack = text_input.read(cx).content.to_string();
+        let replace = self.replace_view.read(cx).content.to_string();
+        let replacement = self.replacement_for(&haystack, &range, &replace);
+        let next_start = range.start + replacement.len();
+
+        text_input.update(cx, |text_input, cx| {
+            text_input.replace_ranges_bytes(vec![(range, replacement)], cx);
+        });
+
+        self.reset();
+        self.search_from(next_start, cx);
+    }
 
-        let mut matches = vec![];
-        for mat in ac.find_iter(&haystack) {
-            matches.push(mat.span().range());
+    fn replace_all(&mut self, cx: &mut ViewContext<Self>) {
+        let Some(text_input) = self.text_input.upgrade() else {
+            return;
+        };
+
+        let term = self.view.read(cx).content.to_string();
+        if term.is_empty() {
+            return;
         }
 
-        self.last_term = Some(term.to_string());
-        self.last_highlight_idx = Some(0);
-        self.last_matches = Some(matches.clone());
+        let haystack = text_input.read(cx).content.to_string();
+        let replace = self.replace_view.read(cx).content.to_string();
+
+        let replacements: Vec<(Range<usize>, String)> = if self.use_regex {
+            let Some(regex) = self.build_regex(&term) else {
+                return;
+            };
+            regex
+                .captures_iter(&haystack)
+                .filter_map(|captures| {
+                    let range = captures.get(0)?.range();
+                    if range.is_empty() {
+                        return None;
+                    }
+                    let mut replacement = String::new();
+                    captures.expand(&replace, &mut replacement);
+                    Some((range, replacement))
+                })
+                .collect()
+        } else {
+            self.find_matches(&term, &haystack)
+                .into_iter()
+                .map(|range| (range, replace.clone()))
+                .collect()
+        };
 
         text_input.update(cx, |text_input, cx| {
-            text_input.highlight(matches.clone(), cx);
-            if let Some(first) = matches.first() {
-                text_input.update_selected_range_bytes(first, cx);
-            }
+            text_input.replace_ranges_bytes(replacements, cx);
         });
+
+        self.reset();
+        self.search_from(0, cx);
     }
 
     fn close(&mut self, _: &Close, cx: &mut ViewContext<Self>) {
@@ -131,10 +306,25 @@ impl SearchView {
         self.search(cx);
     }
 
+    fn toggle_regex(&mut self, _: &ClickEvent, cx: &mut ViewContext<Self>) {
+        self.use_regex = !self.use_regex;
+        self.reset();
+        self.search(cx);
+    }
+
+    fn replace_next_handler(&mut self, _: &ClickEvent, cx: &mut ViewContext<Self>) {
+        self.replace_next(cx);
+    }
+
+    fn replace_all_handler(&mut self, _: &ClickEvent, cx: &mut ViewContext<Self>) {
+        self.replace_all(cx);
+    }
+
     fn reset(&mut self) {
         self.last_term = None;
         self.last_highlight_idx = None;
         self.last_matches = None;
+        self.last_version = None;
     }
 }
 
@@ -152,36 +342,76 @@ impl Render for SearchView {
 
         div()
             .flex()
-            .flex_row()
+            .flex_col()
             .key_context("search")
             .on_action(cx.listener(Self::close))
             .bg(cx.theme().background)
-            .line_height(px(28.))
-            .text_size(px(18.))
-            .text_color(if has_error {
-                cx.theme().error
-            } else {
-                cx.theme().editor_text
-            })
-            .font_family(cx.settings().font_family)
-            .child(self.view.clone())
+            .line_height(cx.settings().line_height)
+            .text_size(cx.settings().font_size)
+            .font_family(cx.settings().font_family.clone())
             .child(
                 div()
                     .flex()
                     .flex_row()
-                    .px(px(4.))
-                    .items_center()
+                    .text_color(if has_error {
+                        cx.theme().error
+                    } else {
+                        cx.theme().editor_text
+                    })
+                    .child(self.view.clone())
                     .child(
                         div()
-                            .id("toggle_case")
-                            .on_click(cx.listener(Self::toggle_case))
-                            .child(Icons::CharacterSentenceCase.as_button(!self.case_insensitive)),
-                    )
+                            .flex()
+                            .flex_row()
+                            .px(px(4.))
+                            .items_center()
+                            .child(
+                                div()
+                                    .id("toggle_regex")
+                                    .on_click(cx.listener(Self::toggle_regex))
+                                    .child(Icons::Regex.as_button(self.use_regex)),
+                            )
+                            .child(
+                                div()
+                                    .id("toggle_case")
+                                    .on_click(cx.listener(Self::toggle_case))
+                                    .child(
+                                        Icons::CharacterSentenceCase
+                                            .as_button(!self.case_insensitive),
+                                    ),
+                            )
+                            .child(
+                                div()
+                                    .id("close")
+                                    .on_click(cx.listener(Self::close_handler))
+                                    .child(Icons::Close.as_button(false)),
+                            ),
+                    ),
+            )
+            .child(
+                div()
+                    .flex()
+                    .flex_row()
+                    .text_color(cx.theme().editor_text)
+                    .child(self.replace_view.clone())
                     .child(
                         div()
-                            .id("close")
-                            .on_click(cx.listener(Self::close_handler))
-                            .child(Icons::Close.as_button(false)),
+                            .flex()
+                            .flex_row()
+                            .px(px(4.))
+                            .items_center()
+                            .child(
+                                div()
+                                    .id("replace_next")
+                                    .on_click(cx.listener(Self::replace_next_handler))
+                                    .child(Icons::Replace.as_button(false)),
+                            )
+                            .child(
+                                div()
+                                    .id("replace_all")
+                                    .on_click(cx.listener(Self::replace_all_handler))
+                                    .child(Icons::ReplaceAll.as_button(false)),
+                            ),
                     ),
             )
     }
@@ -1,6 +1,9 @@
 use gpui::*;
+use prelude::FluentBuilder;
 
+use super::editor::Editor;
 use crate::{
+    keymap::KeymapManager,
     settings_manager::CurrentSettings,
     theme_manager::ActiveTheme,
     views::{icons::Icons, text_input::text_input::TextInput, tooltip::Tooltip},
@@ -8,11 +11,17 @@ use crate::{
 
 pub struct StatusBar {
     text_input: WeakView<TextInput>,
+    editor: WeakView<Editor>,
 }
 
 impl StatusBar {
-    pub fn new(text_input: WeakView<TextInput>) -> Self {
-        Self { text_input }
+    pub fn new(text_input: WeakView<TextInput>, editor: WeakView<Editor>) -> Self {
+        Self { text_input, editor }
+    }
+
+    pub fn set_text_input(&mut self, text_input: WeakView<TextInput>, cx: &mut ViewContext<Self>) {
+        self.text_input = text_input;
+        cx.notify();
     }
 
     fn toggle_soft_wrap(&mut self, _: &ClickEvent, cx: &mut ViewContext<Self>) {
@@ -25,6 +34,28 @@ impl StatusBar {
         });
     }
 
+    fn cycle_encoding(&mut self, _: &ClickEvent, cx: &mut ViewContext<Self>) {
+        self.editor
+            .update(cx, |editor, cx| {
+                editor.set_encoding(editor.encoding().next(), cx)
+            })
+            .ok();
+    }
+
+    fn toggle_line_ending(&mut self, _: &ClickEvent, cx: &mut ViewContext<Self>) {
+        self.editor
+            .update(cx, |editor, cx| {
+                editor.set_line_ending(editor.line_ending().next(), cx)
+            })
+            .ok();
+    }
+
+    fn go_to_line(&mut self, _: &ClickEvent, cx: &mut ViewContext<Self>) {
+        self.editor
+            .update(cx, |editor, cx| editor.toggle_go_to_line(cx))
+            .ok();
+    }
+
     fn soft_wrap_status(&self, cx: &mut ViewContext<Self>) -> bool {
         let Some(text_input) = self.text_input.upgrade() else {
             return false;
@@ -32,6 +63,16 @@ impl StatusBar {
         text_input.read(cx).soft_wrap_enabled()
     }
 
+    /// Problems with the user's settings and keymap and the project file of the shown file.
+    fn settings_errors(&self, cx: &mut ViewContext<Self>) -> Vec<String> {
+        let mut errors = cx.settings_errors().to_vec();
+        errors.extend(cx.global::<KeymapManager>().errors().iter().cloned());
+        if let Some(text_input) = self.text_input.upgrade() {
+            errors.extend(text_input.read(cx).file_settings.errors.iter().cloned());
+        }
+        errors
+    }
+
     fn selection_format(&self, cx: &mut ViewContext<Self>) -> String {
         let Some(text_input) = self.text_input.upgrade() else {
             return String::new();
@@ -43,7 +84,9 @@ impl StatusBar {
         let line_char_idx = text_input.content.line_to_char(line_idx);
         let char_idx_in_line = text_input.cursor_offset() - line_char_idx;
 
-        let selection = if text_input.selected_range.is_empty() {
+        let selection = if !text_input.other_selections.is_empty() {
+            format!(" ({} cursors)", text_input.other_selections.len() + 1)
+        } else if text_input.selected_range.is_empty() {
             String::new()
         } else {
             let start_line_idx = text_input
@@ -77,13 +120,15 @@ impl StatusBar {
 
 impl Render for StatusBar {
     fn render(&mut self, cx: &mut gpui::ViewContext<Self>) -> impl IntoElement {
+        let settings_errors = self.settings_errors(cx);
+
         div()
             .flex()
             .w_full()
             .px(px(8.))
             .pt(px(2.))
             .pb(px(4.))
-            .font_family(cx.settings().font_family)
+            .font_family(cx.settings().font_family.clone())
             .text_sm()
             .text_color(cx.theme().editor_text)
             .child(
@@ -114,7 +159,51 @@ impl Render for StatusBar {
                     .hover(|style| style.rounded(px(6.)).bg(cx.theme().hover_bg))
                     .tooltip(|cx| Tooltip::text("Toggle Soft Wrap", cx)),
             )
+            .when(!settings_errors.is_empty(), |el| {
+                let errors = settings_errors.join("\n");
+                el.child(
+                    div()
+                        .id("settings_errors")
+                        .px(px(4.))
+                        .text_color(cx.theme().error)
+                        .child("Settings Error")
+                        .tooltip(move |cx| Tooltip::text(errors.clone(), cx)),
+                )
+            })
             .child(div().flex_grow())
-            .child(self.selection_format(cx))
+            .child(
+                div()
+                    .id("selection")
+                    .px(px(4.))
+                    .child(self.selection_format(cx))
+                    .on_click(cx.listener(Self::go_to_line))
+                    .cursor(CursorStyle::PointingHand)
+                    .hover(|style| style.rounded(px(6.)).bg(cx.theme().hover_bg))
+                    .tooltip(|cx| Tooltip::text("Go to Line", cx)),
+            )
+            .when_some(self.editor.upgrade(), |el, editor| {
+                let editor = editor.read(cx);
+                el.child(
+                    div()
+                        .id("line_ending")
+                        .ml_2()
+                        .px(px(4.))
+                        .child(editor.line_ending().label())
+                        .on_click(cx.listener(Self::toggle_line_ending))
+                        .cursor(CursorStyle::PointingHand)
+                        .hover(|style| style.rounded(px(6.)).bg(cx.theme().hover_bg))
+                        .tooltip(|cx| Tooltip::text("Convert Line Endings", cx)),
+                )
+                .child(
+                    div()
+                        .id("encoding")
+                        .px(px(4.))
+                        .child(editor.encoding().label())
+                        .on_click(cx.listener(Self::cycle_encoding))
+                        .cursor(CursorStyle::PointingHand)
+                        .hover(|style| style.rounded(px(6.)).bg(cx.theme().hover_bg))
+                        .tooltip(|cx| Tooltip::text("Change Encoding", cx)),
+                )
+            })
     }
 }
@@ -3,35 +3,41 @@ use prelude::FluentBuilder;
 
 use crate::{
     settings_manager::CurrentSettings,
-    theme_manager::{ActiveTheme, Theme},
+    theme_manager::{ActiveTheme, AppearanceMode, Theme, ThemeManager},
     views::icons::Icons,
 };
 
-use super::modal_manager::ModalView;
+use super::modal_manager::{Close, Down, ModalView, Select, Up};
 
-actions!(modal, [Up, Down, Select, Close]);
+actions!(modal, [NextMode]);
 
 pub struct ThemeSelector {
     focus_handle: FocusHandle,
     themes: Vec<Theme>,
+    errors: Vec<String>,
     selection_idx: usize,
     selection_length: usize,
+    _subscription: Subscription,
 }
 
 impl ThemeSelector {
     pub fn new(cx: &mut ViewContext<Self>) -> Self {
-        cx.bind_keys([
-            KeyBinding::new("up", Up, None),
-            KeyBinding::new("down", Down, None),
-            KeyBinding::new("enter", Select, None),
-            KeyBinding::new("escape", Close, None),
-        ]);
+        // the list follows edits in the themes folder while it is open
+        let _subscription = cx.observe_global::<ThemeManager>(|this, cx| {
+            this.themes = cx.themes().to_vec();
+            this.errors = cx.theme_errors().to_vec();
+            this.selection_length = this.themes.len();
+            this.selection_idx = this.selection_idx.min(this.selection_length - 1);
+            cx.notify();
+        });
 
         Self {
             focus_handle: cx.focus_handle(),
-            themes: cx.themes().clone().into_vec(),
+            themes: cx.themes().to_vec(),
+            errors: cx.theme_errors().to_vec(),
             selection_idx: 0,
             selection_length: cx.themes().len(),
+            _subscription,
         }
     }
 
@@ -67,6 +73,18 @@ impl ThemeSelector {
         cx.refresh();
     }
 
+    fn next_mode(&mut self, _: &NextMode, cx: &mut ViewContext<Self>) {
+        let modes = AppearanceMode::ALL;
+        let idx = modes.iter().position(|mode| *mode == cx.theme_mode());
+        let next = modes[idx.map_or(0, |idx| (idx + 1) % modes.len())];
+        self.set_mode(next, cx);
+    }
+
+    fn set_mode(&mut self, mode: AppearanceMode, cx: &mut ViewContext<Self>) {
+        cx.change_theme_mode(mode);
+        cx.refresh();
+    }
+
     fn close(&mut self, _: &Close, cx: &mut ViewContext<Self>) {
         cx.emit(DismissEvent);
     }
@@ -77,17 +95,37 @@ impl Render for ThemeSelector {
         div()
             .p_2()
             .rounded_md()
-            .font_family(cx.settings().font_family)
-            .line_height(px(28.))
-            .text_size(px(18.))
+            .font_family(cx.settings().font_family.clone())
+            .line_height(cx.settings().line_height)
+            .text_size(cx.settings().font_size)
             .key_context("ThemeSelector")
             .track_focus(&self.focus_handle)
             .on_action(cx.listener(Self::up))
             .on_action(cx.listener(Self::down))
             .on_action(cx.listener(Self::select))
             .on_action(cx.listener(Self::close))
+            .on_action(cx.listener(Self::next_mode))
             .bg(cx.theme().background)
             .text_color(cx.theme().editor_text)
+            .child(
+                div()
+                    .flex()
+                    .gap_1()
+                    .pb_1()
+                    .children(AppearanceMode::ALL.into_iter().map(|mode| {
+                        div()
+                            .id(mode.label())
+                            .px_2()
+                            .rounded_md()
+                            .cursor_pointer()
+                            .when(cx.theme_mode() == mode, |el| {
+                                el.bg(cx.theme().editor_background)
+                            })
+                            .hover(|el| el.bg(cx.theme().hover_bg))
+                            .on_click(cx.listener(move |this, _, cx| this.set_mode(mode, cx)))
+                            .child(mode.label())
+                    })),
+            )
             .children(self.themes.iter().enumerate().map(|(idx, theme)| {
                 div()
                     .p_1()
@@ -104,7 +142,7 @@ impl Render for ThemeSelector {
                                 svg()
                                     .size(px(12.))
                                     .flex_none()
-                                    .path(if cx.theme().id == theme.id {
+                                    .path(if cx.is_preferred_theme(theme) {
                                         Icons::RadioButtonChecked.path()
                                     } else {
                                         Icons::RadioButton.path()
@@ -118,6 +156,15 @@ impl Render for ThemeSelector {
                             }),
                     )
             }))
+            .children(self.errors.iter().map(|error| {
+                div()
+                    .p_1()
+                    .max_w(px(480.))
+                    .text_size(px(14.))
+                    .line_height(px(20.))
+                    .text_color(cx.theme().error)
+                    .child(error.clone())
+            }))
     }
 }
 
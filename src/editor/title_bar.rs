@@ -1,22 +1,26 @@
 use std::path::PathBuf;
 
 use gpui::*;
+use prelude::FluentBuilder;
 
-use crate::{
-    settings_manager::CurrentSettings, theme_manager::ActiveTheme,
-    views::text_input::text_input::TextInput,
-};
+use crate::{settings_manager::CurrentSettings, theme_manager::ActiveTheme, views::icons::Icons};
+
+use super::workspace::Workspace;
 
 pub struct TitleBar {
-    text_input: WeakView<TextInput>,
+    workspace: WeakView<Workspace>,
+    dragged_tab: Option<usize>,
 }
 
 impl TitleBar {
-    pub fn new(text_input: WeakView<TextInput>) -> Self {
-        Self { text_input }
+    pub fn new(workspace: WeakView<Workspace>) -> Self {
+        Self {
+            workspace,
+            dragged_tab: None,
+        }
     }
 
-    fn file_name<'a>(&self, path: &'a Option<PathBuf>) -> &'a str {
+    fn file_name(path: &Option<PathBuf>) -> &str {
         if let Some(path) = path {
             if let Some(file_name) = path.file_name() {
                 if let Some(file_name) = file_name.to_str() {
@@ -25,40 +29,110 @@ impl TitleBar {
             }
         }
 
-        ""
+        "untitled"
     }
 
-    fn title(&self, cx: &ViewContext<Self>) -> String {
-        let Some(text_input) = self.text_input.upgrade() else {
-            return String::new();
+    fn tabs(&self, cx: &ViewContext<Self>) -> (Vec<(String, bool)>, usize) {
+        let Some(workspace) = self.workspace.upgrade() else {
+            return (Vec::new(), 0);
         };
 
-        let text_input = text_input.read(cx);
+        let workspace = workspace.read(cx);
+        let tabs = workspace
+            .tabs()
+            .map(|editor| {
+                let editor = editor.read(cx);
+                (
+                    Self::file_name(&editor.file_path(cx)).to_string(),
+                    editor.is_dirty(cx),
+                )
+            })
+            .collect();
+
+        (tabs, workspace.active_idx())
+    }
+
+    fn activate_tab(&mut self, idx: usize, cx: &mut ViewContext<Self>) {
+        self.workspace
+            .update(cx, |workspace, cx| workspace.activate_tab(idx, cx))
+            .ok();
+    }
+
+    fn close_tab(&mut self, idx: usize, cx: &mut ViewContext<Self>) {
+        self.workspace
+            .update(cx, |workspace, cx| workspace.close_tab(idx, cx))
+            .ok();
+    }
 
-        format!(
-            "{}{}",
-            if text_input.is_dirty() { "🞄" } else { "" },
-            self.file_name(text_input.file_path())
-        )
+    fn drop_tab(&mut self, idx: usize, cx: &mut ViewContext<Self>) {
+        if let Some(from) = self.dragged_tab.take() {
+            self.workspace
+                .update(cx, |workspace, cx| workspace.move_tab(from, idx, cx))
+                .ok();
+        }
     }
 }
 
 impl Render for TitleBar {
     fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let (tabs, active_idx) = self.tabs(cx);
+
         div()
             .h(px(32.))
             .flex_none()
-            .font_family(cx.settings().font_family)
+            .flex()
+            .flex_row()
+            .pl(px(72.))
+            .font_family(cx.settings().font_family.clone())
             .text_sm()
             .text_color(cx.theme().editor_text)
-            .child(
-                div()
-                    .flex()
-                    .h_full()
-                    .w_full()
-                    .justify_center()
-                    .items_center()
-                    .child(self.title(cx)),
+            // a drag that isn't dropped on a tab is cancelled
+            .on_mouse_up(
+                MouseButton::Left,
+                cx.listener(|this, _, _| this.dragged_tab = None),
+            )
+            .on_mouse_up_out(
+                MouseButton::Left,
+                cx.listener(|this, _, _| this.dragged_tab = None),
+            )
+            .children(
+                tabs.into_iter()
+                    .enumerate()
+                    .map(|(idx, (title, is_dirty))| {
+                        div()
+                            .id(("tab", idx))
+                            .flex()
+                            .flex_row()
+                            .items_center()
+                            .gap_1()
+                            .h_full()
+                            .pl_3()
+                            .pr_1()
+                            .cursor(CursorStyle::PointingHand)
+                            .when(idx == active_idx, |el| el.bg(cx.theme().editor_background))
+                            .when(idx != active_idx, |el| {
+                                el.hover(|el| el.bg(cx.theme().background.blend(rgba(0x00000044))))
+                            })
+                            .on_mouse_down(
+                                MouseButton::Left,
+                                cx.listener(move |this, _, _| this.dragged_tab = Some(idx)),
+                            )
+                            .on_mouse_up(
+                                MouseButton::Left,
+                                cx.listener(move |this, _, cx| this.drop_tab(idx, cx)),
+                            )
+                            .on_click(cx.listener(move |this, _, cx| this.activate_tab(idx, cx)))
+                            .child(format!("{}{}", if is_dirty { "🞄" } else { "" }, title))
+                            .child(
+                                div()
+                                    .id(("close-tab", idx))
+                                    .on_click(cx.listener(move |this, _, cx| {
+                                        cx.stop_propagation();
+                                        this.close_tab(idx, cx);
+                                    }))
+                                    .child(Icons::Close.as_button(false)),
+                            )
+                    }),
             )
     }
 }
@@ -0,0 +1,332 @@
+use std::path::PathBuf;
+
+use gpui::*;
+use prelude::FluentBuilder;
+
+use crate::db::{DbConnection, MyUuid};
+
+use super::{
+    editor::{Editor, EditorEvent},
+    title_bar::TitleBar,
+};
+
+actions!(
+    workspace,
+    [
+        ActivateTab1,
+        ActivateTab2,
+        ActivateTab3,
+        ActivateTab4,
+        ActivateTab5,
+        ActivateTab6,
+        ActivateTab7,
+        ActivateTab8,
+        ActivateLastTab,
+        ActivateNextTab,
+        ActivatePrevTab,
+        MoveTabLeft,
+        MoveTabRight
+    ]
+);
+
+struct Tab {
+    editor: View<Editor>,
+    _subscription: Subscription,
+}
+
+pub struct Workspace {
+    window_id: MyUuid,
+    focus_handle: FocusHandle,
+    title_bar: View<TitleBar>,
+    tabs: Vec<Tab>,
+    active_idx: usize,
+    is_closing: bool,
+}
+
+impl Workspace {
+    pub fn new(window_id: Option<MyUuid>, cx: &mut ViewContext<Self>) -> Self {
+        let handle = cx.view().downgrade();
+        cx.on_window_should_close(move |cx| {
+            handle
+                .update(cx, |this, cx| this.allowed_to_close_window(cx))
+                .unwrap_or(true)
+        });
+
+        let weak_handle = cx.view().downgrade();
+
+        Self {
+            window_id: window_id.unwrap_or_else(MyUuid::new_v4),
+            focus_handle: cx.focus_handle(),
+            title_bar: cx.new_view(|_| TitleBar::new(weak_handle)),
+            tabs: Vec::new(),
+            active_idx: 0,
+            is_closing: false,
+        }
+    }
+
+    /// Opens a new tab, or activates the tab that already shows `file_path`.
+    pub fn add_tab(
+        &mut self,
+        file_id: Option<MyUuid>,
+        file_path: Option<&PathBuf>,
+        cx: &mut ViewContext<Self>,
+    ) {
+        if let Some(path) = file_path {
+            if let Some(idx) = self
+                .tabs
+                .iter()
+                .position(|tab| tab.editor.read(cx).file_path(cx).as_ref() == Some(path))
+            {
+                self.activate_tab(idx, cx);
+                return;
+            }
+        }
+
+        let editor = cx.new_view(|cx| {
+            let file_id = cx.db_connection().open_windows_add(file_id, file_path);
+            let unsaved_content = cx.db_connection().tmp_file_load(file_id);
+
+            let mut element = Editor::new(file_id, cx);
+
+            if let Some(path) = file_path {
+                element.read_file(path, unsaved_content, cx);
+            }
+
+            element
+        });
+
+        let _subscription =
+            cx.subscribe(
+                &editor,
+                |this, editor, event: &EditorEvent, cx| match event {
+                    EditorEvent::Closed => {
+                        this.remove_tab(&editor, cx);
+                        if this.is_closing {
+                            this.close_next_tab(cx);
+                        } else if this.tabs.is_empty() {
+                            cx.remove_window();
+                        }
+                    }
+                    EditorEvent::CloseAborted => this.is_closing = false,
+                    EditorEvent::ShowDiff(diff) => this.add_scratch_tab(diff, cx),
+                    EditorEvent::OpenFile(path) => {
+                        crate::add_recent_file(path, cx);
+                        this.add_tab(None, Some(path), cx);
+                    }
+                },
+            );
+
+        self.tabs.push(Tab {
+            editor,
+            _subscription,
+        });
+        self.persist_tabs(cx);
+        self.activate_tab(self.tabs.len() - 1, cx);
+    }
+
+    /// Opens an untitled tab showing `content`, e.g. a diff.
+    pub fn add_scratch_tab(&mut self, content: &str, cx: &mut ViewContext<Self>) {
+        self.add_tab(None, None, cx);
+        if let Some(editor) = self.active_editor() {
+            editor.update(cx, |editor, cx| editor.set_content(content, cx));
+        }
+    }
+
+    pub fn tabs(&self) -> impl Iterator<Item = &View<Editor>> {
+        self.tabs.iter().map(|tab| &tab.editor)
+    }
+
+    pub fn active_idx(&self) -> usize {
+        self.active_idx
+    }
+
+    pub fn active_editor(&self) -> Option<&View<Editor>> {
+        self.tabs.get(self.active_idx).map(|tab| &tab.editor)
+    }
+
+    pub fn activate_tab(&mut self, idx: usize, cx: &mut ViewContext<Self>) {
+        let Some(tab) = self.tabs.get(idx) else {
+            return;
+        };
+
+        self.active_idx = idx;
+        cx.focus_view(&tab.editor);
+        cx.notify();
+    }
+
+    pub fn close_tab(&mut self, idx: usize, cx: &mut ViewContext<Self>) {
+        if let Some(tab) = self.tabs.get(idx) {
+            tab.editor.update(cx, |editor, cx| editor.request_close(cx));
+        }
+    }
+
+    pub fn move_tab(&mut self, from: usize, to: usize, cx: &mut ViewContext<Self>) {
+        if from >= self.tabs.len() || to >= self.tabs.len() || from == to {
+            return;
+        }
+
+        let tab = self.tabs.remove(from);
+        self.tabs.insert(to, tab);
+        self.active_idx = to;
+        self.persist_tabs(cx);
+        cx.notify();
+    }
+
+    fn remove_tab(&mut self, editor: &View<Editor>, cx: &mut ViewContext<Self>) {
+        let Some(idx) = self.tabs.iter().position(|tab| tab.editor == *editor) else {
+            return;
+        };
+
+        let tab = self.tabs.remove(idx);
+        let file_id = tab.editor.read(cx).file_id();
+        cx.db_connection().open_windows_remove(file_id);
+        cx.db_connection().undo_history_delete(file_id);
+
+        if self.active_idx >= idx && self.active_idx > 0 {
+            self.active_idx -= 1;
+        }
+        self.persist_tabs(cx);
+        self.activate_tab(self.active_idx, cx);
+    }
+
+    fn persist_tabs(&self, cx: &mut ViewContext<Self>) {
+        let file_ids: Vec<MyUuid> = self
+            .tabs
+            .iter()
+            .map(|tab| tab.editor.read(cx).file_id())
+            .collect();
+        cx.db_connection()
+            .open_windows_update_tabs(self.window_id, &file_ids);
+    }
+
+    fn allowed_to_close_window(&mut self, cx: &mut ViewContext<Self>) -> bool {
+        if self.tabs.iter().any(|tab| tab.editor.read(cx).is_dirty(cx)) {
+            self.is_closing = true;
+            self.close_next_tab(cx);
+            return false;
+        }
+
+        for tab in self.tabs.drain(..) {
+            let file_id = tab.editor.read(cx).file_id();
+            cx.db_connection().open_windows_remove(file_id);
+            cx.db_connection().undo_history_delete(file_id);
+        }
+
+        true
+    }
+
+    /// Asks the dirty tabs one after another; the window goes away once all are closed.
+    fn close_next_tab(&mut self, cx: &mut ViewContext<Self>) {
+        if let Some(idx) = self
+            .tabs
+            .iter()
+            .position(|tab| tab.editor.read(cx).is_dirty(cx))
+        {
+            self.activate_tab(idx, cx);
+            self.close_tab(idx, cx);
+            return;
+        }
+
+        if self.allowed_to_close_window(cx) {
+            cx.remove_window();
+        }
+    }
+
+    fn activate_tab_1(&mut self, _: &ActivateTab1, cx: &mut ViewContext<Self>) {
+        self.activate_tab(0, cx);
+    }
+
+    fn activate_tab_2(&mut self, _: &ActivateTab2, cx: &mut ViewContext<Self>) {
+        self.activate_tab(1, cx);
+    }
+
+    fn activate_tab_3(&mut self, _: &ActivateTab3, cx: &mut ViewContext<Self>) {
+        self.activate_tab(2, cx);
+    }
+
+    fn activate_tab_4(&mut self, _: &ActivateTab4, cx: &mut ViewContext<Self>) {
+        self.activate_tab(3, cx);
+    }
+
+    fn activate_tab_5(&mut self, _: &ActivateTab5, cx: &mut ViewContext<Self>) {
+        self.activate_tab(4, cx);
+    }
+
+    fn activate_tab_6(&mut self, _: &ActivateTab6, cx: &mut ViewContext<Self>) {
+        self.activate_tab(5, cx);
+    }
+
+    fn activate_tab_7(&mut self, _: &ActivateTab7, cx: &mut ViewContext<Self>) {
+        self.activate_tab(6, cx);
+    }
+
+    fn activate_tab_8(&mut self, _: &ActivateTab8, cx: &mut ViewContext<Self>) {
+        self.activate_tab(7, cx);
+    }
+
+    fn activate_last_tab(&mut self, _: &ActivateLastTab, cx: &mut ViewContext<Self>) {
+        self.activate_tab(self.tabs.len().saturating_sub(1), cx);
+    }
+
+    fn activate_next_tab(&mut self, _: &ActivateNextTab, cx: &mut ViewContext<Self>) {
+        if self.tabs.is_empty() {
+            return;
+        }
+        self.activate_tab((self.active_idx + 1) % self.tabs.len(), cx);
+    }
+
+    fn activate_prev_tab(&mut self, _: &ActivatePrevTab, cx: &mut ViewContext<Self>) {
+        if self.tabs.is_empty() {
+            return;
+        }
+        self.activate_tab(
+            (self.active_idx + self.tabs.len() - 1) % self.tabs.len(),
+            cx,
+        );
+    }
+
+    fn move_tab_left(&mut self, _: &MoveTabLeft, cx: &mut ViewContext<Self>) {
+        if self.active_idx > 0 {
+            self.move_tab(self.active_idx, self.active_idx - 1, cx);
+        }
+    }
+
+    fn move_tab_right(&mut self, _: &MoveTabRight, cx: &mut ViewContext<Self>) {
+        self.move_tab(self.active_idx, self.active_idx + 1, cx);
+    }
+}
+
+impl Render for Workspace {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        div()
+            .flex()
+            .flex_col()
+            .size_full()
+            .key_context("Workspace")
+            .track_focus(&self.focus_handle)
+            .on_action(cx.listener(Self::activate_tab_1))
+            .on_action(cx.listener(Self::activate_tab_2))
+            .on_action(cx.listener(Self::activate_tab_3))
+            .on_action(cx.listener(Self::activate_tab_4))
+            .on_action(cx.listener(Self::activate_tab_5))
+            .on_action(cx.listener(Self::activate_tab_6))
+            .on_action(cx.listener(Self::activate_tab_7))
+            .on_action(cx.listener(Self::activate_tab_8))
+            .on_action(cx.listener(Self::activate_last_tab))
+            .on_action(cx.listener(Self::activate_next_tab))
+            .on_action(cx.listener(Self::activate_prev_tab))
+            .on_action(cx.listener(Self::move_tab_left))
+            .on_action(cx.listener(Self::move_tab_right))
+            .child(self.title_bar.clone())
+            .when_some(self.active_editor().cloned(), |el, editor| el.child(editor))
+    }
+}
+
+impl FocusableView for Workspace {
+    fn focus_handle(&self, cx: &AppContext) -> FocusHandle {
+        match self.active_editor() {
+            Some(editor) => editor.focus_handle(cx),
+            None => self.focus_handle.clone(),
+        }
+    }
+}
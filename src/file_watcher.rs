@@ -0,0 +1,167 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, SystemTime},
+};
+
+use gpui::*;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What a path looked like at the last poll, a folder changes with its entries.
+#[derive(PartialEq)]
+enum Stamp {
+    Missing,
+    File(Option<SystemTime>),
+    Folder(Vec<(PathBuf, Option<SystemTime>)>),
+}
+
+/// A watched file or folder was created, written or removed.
+pub struct PathChanged(pub PathBuf);
+
+/// The paths one owner wants to hear about, they leave the poll when it is dropped.
+pub struct Watch(Arc<Mutex<Vec<PathBuf>>>);
+
+impl Watch {
+    pub fn set_paths(&self, paths: Vec<PathBuf>) {
+        *self.0.lock().unwrap() = paths;
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.0.lock().unwrap().iter().any(|watched| watched == path)
+    }
+}
+
+/// Stats every watched path from one background task and emits what changed.
+pub struct FileWatcher {
+    watches: Vec<Weak<Mutex<Vec<PathBuf>>>>,
+    stamps: HashMap<PathBuf, Stamp>,
+    _poll_task: Task<()>,
+}
+
+impl EventEmitter<PathChanged> for FileWatcher {}
+
+struct GlobalFileWatcher(Model<FileWatcher>);
+
+impl Global for GlobalFileWatcher {}
+
+impl FileWatcher {
+    pub fn init(cx: &mut AppContext) {
+        let watcher = cx.new_model(Self::new);
+        cx.set_global(GlobalFileWatcher(watcher));
+    }
+
+    pub fn global(cx: &AppContext) -> Model<Self> {
+        cx.global::<GlobalFileWatcher>().0.clone()
+    }
+
+    /// Calls `on_change` whenever one of `paths` changes, for as long as the app runs.
+    pub fn on_change(
+        paths: Vec<PathBuf>,
+        cx: &mut AppContext,
+        mut on_change: impl FnMut(&mut AppContext) + 'static,
+    ) {
+        let watcher = Self::global(cx);
+        let watch = watcher.update(cx, |watcher, _| watcher.watch(paths));
+        cx.subscribe(&watcher, move |_, event: &PathChanged, cx| {
+            if watch.contains(&event.0) {
+                on_change(cx);
+            }
+        })
+        .detach();
+    }
+
+    fn new(cx: &mut ModelContext<Self>) -> Self {
+        let _poll_task = cx.spawn(|this, mut cx| async move {
+            loop {
+                cx.background_executor().timer(POLL_INTERVAL).await;
+
+                let Ok(paths) = this.update(&mut cx, |this, _| this.watched_paths()) else {
+                    break;
+                };
+                let stamps = cx
+                    .background_executor()
+                    .spawn(async move {
+                        paths
+                            .into_iter()
+                            .map(|path| {
+                                let stamp = stamp(&path);
+                                (path, stamp)
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                    .await;
+                if this
+                    .update(&mut cx, |this, cx| this.update_stamps(stamps, cx))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            watches: Vec::new(),
+            stamps: HashMap::new(),
+            _poll_task,
+        }
+    }
+
+    pub fn watch(&mut self, paths: Vec<PathBuf>) -> Watch {
+        let paths = Arc::new(Mutex::new(paths));
+        self.watches.push(Arc::downgrade(&paths));
+        Watch(paths)
+    }
+
+    fn watched_paths(&mut self) -> Vec<PathBuf> {
+        self.watches.retain(|watch| watch.strong_count() > 0);
+        let mut paths: Vec<PathBuf> = self
+            .watches
+            .iter()
+            .filter_map(Weak::upgrade)
+            .flat_map(|paths| paths.lock().unwrap().clone())
+            .collect();
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+
+    /// The first poll of a path only records it, owners read the path when they start watching.
+    fn update_stamps(&mut self, stamps: Vec<(PathBuf, Stamp)>, cx: &mut ModelContext<Self>) {
+        let old_stamps = std::mem::take(&mut self.stamps);
+        for (path, stamp) in stamps {
+            if old_stamps
+                .get(&path)
+                .is_some_and(|old_stamp| *old_stamp != stamp)
+            {
+                cx.emit(PathChanged(path.clone()));
+            }
+            self.stamps.insert(path, stamp);
+        }
+    }
+}
+
+fn stamp(path: &Path) -> Stamp {
+    let Ok(metadata) = fs::metadata(path) else {
+        return Stamp::Missing;
+    };
+    if !metadata.is_dir() {
+        return Stamp::File(metadata.modified().ok());
+    }
+
+    let mut entries: Vec<(PathBuf, Option<SystemTime>)> = fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| {
+                    let modified = entry.metadata().and_then(|m| m.modified()).ok();
+                    (entry.path(), modified)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    entries.sort();
+    Stamp::Folder(entries)
+}
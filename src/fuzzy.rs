@@ -0,0 +1,172 @@
+/// How well a candidate matched a query, higher scores are better.
+#[derive(Debug, Clone)]
+pub struct FuzzyMatch {
+    pub score: i32,
+    /// Byte offsets of the matched chars in the candidate, used to highlight them.
+    pub positions: Vec<usize>,
+}
+
+const WORD_START_BONUS: i32 = 8;
+const CONSECUTIVE_BONUS: i32 = 5;
+/// Gaps cost one point per skipped char, up to this many.
+const MAX_GAP_PENALTY: i32 = 3;
+
+/// Matches the query's chars in order anywhere in `candidate`, ignoring case and the query's
+/// whitespace. Matches at the start of words and runs of consecutive chars score higher,
+/// gaps between matches score lower. Of all the ways the query fits, the best one is used.
+pub fn fuzzy_match(query: &str, candidate: &str) -> Option<FuzzyMatch> {
+    let query: Vec<char> = query.chars().filter(|c| !c.is_whitespace()).collect();
+    let chars: Vec<(usize, char)> = candidate.char_indices().collect();
+    if query.is_empty() {
+        return Some(FuzzyMatch {
+            score: 0,
+            positions: Vec::new(),
+        });
+    }
+    if query.len() > chars.len() {
+        return None;
+    }
+
+    let char_scores: Vec<i32> = chars
+        .iter()
+        .enumerate()
+        .map(|(idx, &(_, c))| {
+            let starts_word = match idx.checked_sub(1).map(|previous| chars[previous].1) {
+                None => true,
+                Some(previous) => {
+                    !previous.is_alphanumeric() || (previous.is_lowercase() && c.is_uppercase())
+                }
+            };
+            1 + if starts_word { WORD_START_BONUS } else { 0 }
+        })
+        .collect();
+
+    // scores[i][j] is the best score for the query up to i with query[i] on char j,
+    // previous[i][j] the char query[i - 1] is on for that score
+    let mut scores: Vec<Vec<Option<i32>>> = vec![vec![None; chars.len()]; query.len()];
+    let mut previous = vec![vec![0; chars.len()]; query.len()];
+
+    for (i, &query_char) in query.iter().enumerate() {
+        // the best of the previous row far enough back to get the full gap penalty
+        let mut far_best: Option<(i32, usize)> = None;
+
+        for j in i..chars.len() {
+            if i > 0 && j > MAX_GAP_PENALTY as usize {
+                let k = j - MAX_GAP_PENALTY as usize - 1;
+                match (scores[i - 1][k], far_best) {
+                    (Some(score), Some((best, _))) if score <= best => {}
+                    (Some(score), _) => far_best = Some((score, k)),
+                    (None, _) => {}
+                }
+            }
+
+            if !chars[j].1.to_lowercase().eq(query_char.to_lowercase()) {
+                continue;
+            }
+
+            let best = if i == 0 {
+                Some((-(j.min(MAX_GAP_PENALTY as usize) as i32), 0))
+            } else {
+                let mut best = far_best.map(|(score, k)| (score - MAX_GAP_PENALTY, k));
+                for gap in 0..MAX_GAP_PENALTY as usize {
+                    let Some(k) = j.checked_sub(gap + 1) else {
+                        break;
+                    };
+                    let Some(score) = scores[i - 1][k] else {
+                        continue;
+                    };
+                    let score = match gap {
+                        0 => score + CONSECUTIVE_BONUS,
+                        gap => score - gap as i32,
+                    };
+                    match best {
+                        Some((best, _)) if score <= best => {}
+                        _ => best = Some((score, k)),
+                    }
+                }
+                best
+            };
+
+            if let Some((score, k)) = best {
+                scores[i][j] = Some(score + char_scores[j]);
+                previous[i][j] = k;
+            }
+        }
+    }
+
+    let (mut j, score) = scores[query.len() - 1]
+        .iter()
+        .enumerate()
+        .filter_map(|(j, score)| Some((j, (*score)?)))
+        .max_by_key(|&(j, score)| (score, std::cmp::Reverse(j)))?;
+
+    let mut positions = vec![0; query.len()];
+    for i in (0..query.len()).rev() {
+        positions[i] = chars[j].0;
+        j = previous[i][j];
+    }
+
+    Some(FuzzyMatch { score, positions })
+}
+
+/// Added to the match score of recently used candidates, `recency` is 0 for the most recent.
+/// It fades out after the ten most recent ones.
+pub fn recency_bonus(recency: Option<usize>) -> i32 {
+    match recency {
+        Some(recency) => 3 * (10 - recency.min(10)) as i32,
+        None => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_in_order_ignoring_case_and_whitespace() {
+        let m = fuzzy_match("g tl", "Go To Line").unwrap();
+        assert_eq!(m.positions, vec![0, 3, 6]);
+        assert!(fuzzy_match("lg", "Go To Line").is_none());
+        assert!(fuzzy_match("toolong", "tool").is_none());
+    }
+
+    #[test]
+    fn empty_query_matches_everything() {
+        let m = fuzzy_match("", "anything").unwrap();
+        assert_eq!(m.score, 0);
+        assert!(m.positions.is_empty());
+    }
+
+    #[test]
+    fn prefers_word_starts_over_the_first_occurrence() {
+        let m = fuzzy_match("tab", "Workspace: Activate Tab").unwrap();
+        assert_eq!(m.positions, vec![20, 21, 22]);
+    }
+
+    #[test]
+    fn prefers_camel_case_word_starts() {
+        let m = fuzzy_match("fb", "fooBar").unwrap();
+        assert_eq!(m.positions, vec![0, 3]);
+    }
+
+    #[test]
+    fn positions_are_byte_offsets() {
+        let m = fuzzy_match("ü", "grün").unwrap();
+        assert_eq!(m.positions, vec![2]);
+    }
+
+    #[test]
+    fn consecutive_and_word_start_matches_score_higher() {
+        let word_start = fuzzy_match("line", "Go To Line").unwrap().score;
+        let scattered = fuzzy_match("line", "lazy inline").unwrap().score;
+        assert!(word_start > scattered);
+    }
+
+    #[test]
+    fn recency_bonus_fades_out() {
+        assert!(recency_bonus(Some(0)) > recency_bonus(Some(1)));
+        assert_eq!(recency_bonus(Some(10)), 0);
+        assert_eq!(recency_bonus(Some(500)), 0);
+        assert_eq!(recency_bonus(None), 0);
+    }
+}
@@ -0,0 +1,349 @@
+use std::{collections::HashMap, fmt, fs, io};
+
+use gpui::{AppContext, Global, KeyBinding, Keystroke};
+use serde::{
+    de::{MapAccess, Visitor},
+    Deserialize, Deserializer,
+};
+
+use crate::{file_watcher::FileWatcher, paths::keymap_path};
+
+#[cfg(target_os = "macos")]
+const DEFAULT_KEYMAP: &str = include_str!("keymaps/macos.json");
+#[cfg(not(target_os = "macos"))]
+const DEFAULT_KEYMAP: &str = include_str!("keymaps/linux.json");
+
+/// One block of a keymap file, see the readme for the format.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeymapSection {
+    context: Option<String>,
+    bindings: Bindings,
+}
+
+/// Keystrokes to action names in file order, `null` removes a default binding.
+/// Kept as a list so a key that appears twice can be reported.
+struct Bindings(Vec<(String, Option<String>)>);
+
+impl<'de> Deserialize<'de> for Bindings {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct BindingsVisitor;
+
+        impl<'de> Visitor<'de> for BindingsVisitor {
+            type Value = Bindings;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a map of keystrokes to action names")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Bindings, A::Error> {
+                let mut bindings = Vec::new();
+                while let Some(entry) = map.next_entry()? {
+                    bindings.push(entry);
+                }
+                Ok(Bindings(bindings))
+            }
+        }
+
+        deserializer.deserialize_map(BindingsVisitor)
+    }
+}
+
+struct Entry {
+    context: Option<String>,
+    /// Normalized, so `shift-cmd-s` and `cmd-shift-s` are the same key.
+    keystrokes: Vec<String>,
+    action: Option<String>,
+}
+
+#[derive(Default)]
+pub struct KeymapManager {
+    /// Unknown actions, bad keystrokes and conflicts, shown in the status bar.
+    errors: Vec<String>,
+}
+
+impl KeymapManager {
+    /// Binds the default keymap with the user's keymap on top and reloads it when it changes.
+    pub fn init(cx: &mut AppContext) {
+        cx.set_global(Self::default());
+        Self::reload(cx);
+        Self::watch(cx);
+    }
+
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+
+    fn reload(cx: &mut AppContext) {
+        let mut errors = Vec::new();
+        let mut entries = Vec::new();
+
+        if let Err(error) = merge(DEFAULT_KEYMAP, &mut entries, &mut errors) {
+            errors.push(format!("default keymap: {error}"));
+        }
+
+        if let Ok(path) = keymap_path() {
+            match fs::read_to_string(path) {
+                Ok(text) if text.trim().is_empty() => {}
+                Ok(text) => {
+                    let mut user_errors = Vec::new();
+                    if let Err(error) = merge(&text, &mut entries, &mut user_errors) {
+                        user_errors.push(error);
+                    }
+                    errors.extend(user_errors.into_iter().map(|e| format!("keymap.json: {e}")));
+                }
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+                Err(error) => errors.push(format!("keymap.json: {error}")),
+            }
+        }
+
+        errors.extend(prefix_conflicts(&entries));
+
+        let mut bindings = Vec::new();
+        for entry in &entries {
+            let Some(action_name) = &entry.action else {
+                continue;
+            };
+            let keystrokes = entry.keystrokes.join(" ");
+            let binding = cx
+                .build_action(action_name, None)
+                .map_err(|_| format!("unknown action `{action_name}` for `{keystrokes}`"))
+                .and_then(|action| {
+                    KeyBinding::load(&keystrokes, action, entry.context.as_deref())
+                        .map_err(|error| format!("`{keystrokes}`: {error}"))
+                });
+            match binding {
+                Ok(binding) => bindings.push(binding),
+                Err(error) => errors.push(error),
+            }
+        }
+
+        for error in &errors {
+            eprintln!("keymap: {error}");
+        }
+
+        cx.clear_key_bindings();
+        cx.bind_keys(bindings);
+        // menus show the shortcuts they had when they were set
+        cx.set_menus(crate::app_menus(cx));
+
+        cx.global_mut::<Self>().errors = errors;
+    }
+
+    fn watch(cx: &mut AppContext) {
+        let Ok(path) = keymap_path() else {
+            return;
+        };
+        FileWatcher::on_change(vec![path], cx, |cx| {
+            Self::reload(cx);
+            cx.refresh();
+        });
+    }
+}
+
+impl Global for KeymapManager {}
+
+/// Adds the bindings of one keymap file, replacing earlier ones for the same keys and context.
+fn merge(text: &str, entries: &mut Vec<Entry>, errors: &mut Vec<String>) -> Result<(), String> {
+    let sections: Vec<KeymapSection> =
+        serde_json::from_str(text).map_err(|error| error.to_string())?;
+
+    let mut seen: HashMap<(Option<String>, Vec<String>), Option<String>> = HashMap::new();
+    for section in sections {
+        let context = section.context.map(|context| context.trim().to_string());
+        let context_name = context.as_deref().unwrap_or("any context");
+
+        for (keystrokes, action) in section.bindings.0 {
+            let normalized = match normalize(&keystrokes) {
+                Ok(normalized) => normalized,
+                Err(error) => {
+                    errors.push(format!("`{keystrokes}`: {error}"));
+                    continue;
+                }
+            };
+
+            let key = (context.clone(), normalized.clone());
+            if let Some(previous) = seen.insert(key, action.clone()) {
+                if previous != action {
+                    errors.push(format!(
+                        "`{keystrokes}` in {context_name} is bound to both {} and {}",
+                        action_label(&previous),
+                        action_label(&action)
+                    ));
+                }
+            }
+
+            entries.retain(|entry| entry.context != context || entry.keystrokes != normalized);
+            entries.push(Entry {
+                context: context.clone(),
+                keystrokes: normalized,
+                action,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// A binding that is also the start of a longer one in the same context only fires after
+/// the pending sequence times out, which is rarely what was meant.
+fn prefix_conflicts(entries: &[Entry]) -> Vec<String> {
+    let mut conflicts = Vec::new();
+    for short in entries.iter().filter(|entry| entry.action.is_some()) {
+        for long in entries.iter().filter(|entry| entry.action.is_some()) {
+            if short.context == long.context
+                && short.keystrokes.len() < long.keystrokes.len()
+                && long.keystrokes.starts_with(&short.keystrokes)
+            {
+                conflicts.push(format!(
+                    "`{}` in {} is also the start of `{}` and only fires after a pause",
+                    short.keystrokes.join(" "),
+                    short.context.as_deref().unwrap_or("any context"),
+                    long.keystrokes.join(" ")
+                ));
+            }
+        }
+    }
+    conflicts
+}
+
+fn normalize(keystrokes: &str) -> Result<Vec<String>, String> {
+    let keystrokes: Vec<String> = keystrokes
+        .split_whitespace()
+        .map(|source| {
+            let keystroke = Keystroke::parse(source).map_err(|error| error.to_string())?;
+            let modifiers = keystroke.modifiers;
+            let mut normalized = String::new();
+            for (set, name) in [
+                (modifiers.control, "ctrl-"),
+                (modifiers.alt, "alt-"),
+                (modifiers.shift, "shift-"),
+                (modifiers.platform, "cmd-"),
+                (modifiers.function, "fn-"),
+            ] {
+                if set {
+                    normalized.push_str(name);
+                }
+            }
+            normalized.push_str(&keystroke.key);
+            Ok(normalized)
+        })
+        .collect::<Result<_, String>>()?;
+
+    if keystrokes.is_empty() {
+        return Err("no keystrokes".into());
+    }
+    Ok(keystrokes)
+}
+
+fn action_label(action: &Option<String>) -> String {
+    match action {
+        Some(action) => format!("`{action}`"),
+        None => "null".into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merged(texts: &[&str]) -> (Vec<Entry>, Vec<String>) {
+        let mut entries = Vec::new();
+        let mut errors = Vec::new();
+        for text in texts {
+            merge(text, &mut entries, &mut errors).unwrap();
+        }
+        (entries, errors)
+    }
+
+    fn action<'a>(
+        entries: &'a [Entry],
+        context: Option<&str>,
+        keystrokes: &str,
+    ) -> Option<&'a str> {
+        entries
+            .iter()
+            .find(|entry| {
+                entry.context.as_deref() == context && entry.keystrokes.join(" ") == keystrokes
+            })
+            .and_then(|entry| entry.action.as_deref())
+    }
+
+    #[test]
+    fn normalizes_modifier_order() {
+        assert_eq!(normalize("shift-cmd-s"), normalize("cmd-shift-s"));
+        assert_eq!(normalize("cmd-shift-s").unwrap(), vec!["shift-cmd-s"]);
+        assert_eq!(
+            normalize(" ctrl-k   alt-ctrl-c ").unwrap(),
+            vec!["ctrl-k", "ctrl-alt-c"]
+        );
+        assert!(normalize("  ").is_err());
+    }
+
+    #[test]
+    fn later_files_replace_bindings_for_the_same_keys_and_context() {
+        let defaults = r#"[
+            { "bindings": { "cmd-s": "editor::Save", "cmd-o": "editor::Open" } },
+            { "context": "Editor", "bindings": { "cmd-s": "editor::SaveAll" } }
+        ]"#;
+        let user = r#"[{ "bindings": { "shift-cmd-s": "editor::SaveAs", "cmd-shift-s": "editor::SaveAs", "cmd-o": null } }]"#;
+        let (entries, errors) = merged(&[defaults, user]);
+
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(action(&entries, None, "cmd-s"), Some("editor::Save"));
+        assert_eq!(
+            action(&entries, None, "shift-cmd-s"),
+            Some("editor::SaveAs")
+        );
+        assert_eq!(
+            action(&entries, Some("Editor"), "cmd-s"),
+            Some("editor::SaveAll")
+        );
+        // `null` stays as an entry that unbinds the default
+        assert!(entries
+            .iter()
+            .any(|entry| entry.keystrokes == ["cmd-o"] && entry.action.is_none()));
+        assert_eq!(
+            entries
+                .iter()
+                .filter(|entry| entry.keystrokes == ["cmd-o"])
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn reports_keys_bound_twice_in_one_file() {
+        let (_, errors) = merged(&[
+            r#"[{ "bindings": { "cmd-s": "editor::Save", "cmd-s ": "editor::SaveAs" } }]"#,
+        ]);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("bound to both"), "{}", errors[0]);
+    }
+
+    #[test]
+    fn reports_invalid_keystrokes_and_keeps_the_rest() {
+        let mut entries = Vec::new();
+        let mut errors = Vec::new();
+        merge(
+            r#"[{ "bindings": { "": "editor::Nothing", "cmd-s": "editor::Save" } }]"#,
+            &mut entries,
+            &mut errors,
+        )
+        .unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(action(&entries, None, "cmd-s"), Some("editor::Save"));
+
+        assert!(merge("[{", &mut entries, &mut errors).is_err());
+    }
+
+    #[test]
+    fn finds_bindings_that_start_longer_ones() {
+        let (entries, _) = merged(&[
+            r#"[{ "bindings": { "ctrl-k": "editor::Kill", "ctrl-k ctrl-c": "editor::Comment" } }]"#,
+        ]);
+        let conflicts = prefix_conflicts(&entries);
+        assert_eq!(conflicts.len(), 1);
+        assert!(conflicts[0].starts_with("`ctrl-k` in any context"));
+    }
+}
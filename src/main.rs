@@ -1,25 +1,46 @@
 use assets::Assets;
 use db::{DbConnection, MyUuid, DB};
-use editor::editor::*;
+use editor::{editor::*, workspace::Workspace};
+use file_watcher::FileWatcher;
 use futures::channel::mpsc;
 use futures::channel::mpsc::{UnboundedReceiver, UnboundedSender};
 use futures::StreamExt;
 use gpui::*;
+use keymap::KeymapManager;
+use serde::Deserialize;
 use settings_manager::SettingsManager;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use views::text_input::text_input::*;
 
 mod assets;
 mod db;
 mod editor;
+mod file_watcher;
+mod fuzzy;
+mod keymap;
 mod paths;
+mod settings_file;
 mod settings_manager;
+mod theme_file;
 mod theme_manager;
 mod views;
 
-use crate::theme_manager::ActiveTheme;
+use crate::theme_manager::{ActiveTheme, ThemeManager};
 
-actions!(set_menus, [Quit, Hide, HideOthers, ShowAll, FileNew, Open]);
+actions!(
+    set_menus,
+    [Quit, Hide, HideOthers, ShowAll, FileNew, NewWindow, Open]
+);
+
+#[derive(Clone, PartialEq, Deserialize)]
+struct OpenRecent {
+    path: PathBuf,
+}
+
+impl_actions!(set_menus, [OpenRecent]);
+
+/// Entries of the "Open Recent" menu.
+const RECENT_MENU_LENGTH: usize = 10;
 
 fn bounds_for_path(path: Option<&PathBuf>, cx: &AppContext) -> WindowBounds {
     if let Some(path) = path {
@@ -43,7 +64,12 @@ fn bounds_for_path(path: Option<&PathBuf>, cx: &AppContext) -> WindowBounds {
     WindowBounds::Windowed(Bounds::centered(None, size(px(400.), px(320.)), cx))
 }
 
-fn open_window(file_id: Option<MyUuid>, file_path: Option<&PathBuf>, cx: &mut AppContext) {
+fn open_window(
+    window_id: Option<MyUuid>,
+    tabs: Vec<(Option<MyUuid>, Option<PathBuf>)>,
+    cx: &mut AppContext,
+) {
+    let first_path = tabs.first().and_then(|(_, file_path)| file_path.clone());
     let window = cx
         .open_window(
             WindowOptions {
@@ -52,37 +78,68 @@ fn open_window(file_id: Option<MyUuid>, file_path: Option<&PathBuf>, cx: &mut Ap
                     appears_transparent: true,
                     traffic_light_position: Some(point(px(9.0), px(9.0))),
                 }),
-                window_bounds: Some(bounds_for_path(file_path, cx)),
+                window_bounds: Some(bounds_for_path(first_path.as_ref(), cx)),
                 window_min_size: Some(size(px(200.), px(160.))),
                 ..Default::default()
             },
             |cx| {
-                let editor = cx.new_view(|cx| {
-                    let file_id = cx.db_connection().open_windows_add(file_id, file_path);
-                    let unsaved_content = cx.db_connection().tmp_file_load(file_id);
-
-                    let mut element = Editor::new(file_id, cx);
+                let workspace = cx.new_view(|cx| {
+                    let mut workspace = Workspace::new(window_id, cx);
 
-                    if let Some(path) = file_path {
-                        element.read_file(path, unsaved_content, cx);
+                    for (file_id, file_path) in &tabs {
+                        workspace.add_tab(*file_id, file_path.as_ref(), cx);
                     }
+                    if tabs.is_empty() {
+                        workspace.add_tab(None, None, cx);
+                    }
+                    workspace.activate_tab(0, cx);
 
-                    element
+                    workspace
                 });
 
-                cx.new_view(|cx| TextEditor::new(editor, cx.focus_handle(), cx))
+                cx.new_view(|cx| TextEditor::new(workspace, cx.focus_handle(), cx))
             },
         )
         .unwrap();
 
     window
         .update(cx, |view, cx| {
-            cx.focus_view(&view.editor);
+            cx.focus_view(&view.workspace);
             cx.activate(true);
         })
         .unwrap();
 }
 
+/// Opens `path` as a tab of the active window, or in a new window if there is none.
+fn open_path(path: Option<PathBuf>, cx: &mut AppContext) {
+    if let Some(path) = &path {
+        add_recent_file(path, cx);
+    }
+
+    if let Some(window) = cx
+        .active_window()
+        .and_then(|window| window.downcast::<TextEditor>())
+    {
+        let opened = window.update(cx, |view, cx| {
+            view.workspace.update(cx, |workspace, cx| {
+                workspace.add_tab(None, path.as_ref(), cx)
+            })
+        });
+        if opened.is_ok() {
+            return;
+        }
+    }
+
+    open_window(None, vec![(None, path)], cx);
+}
+
+/// Counts an opened file for the recent files list, the menu and the OS.
+fn add_recent_file(path: &Path, cx: &mut AppContext) {
+    cx.add_recent_document(path);
+    cx.db_connection().recent_file_opened(path);
+    cx.set_menus(app_menus(cx));
+}
+
 fn open_file(cx: &mut AppContext) {
     let paths = cx.prompt_for_paths(PathPromptOptions {
         files: true,
@@ -93,11 +150,7 @@ fn open_file(cx: &mut AppContext) {
     cx.spawn(|cx| async move {
         if let Ok(Some(paths)) = Flatten::flatten(paths.await.map_err(|e| e.into())) {
             if let Some(path) = paths.first() {
-                cx.update(|cx| {
-                    cx.add_recent_document(path);
-                    open_window(None, Some(&path.clone()), cx);
-                })
-                .ok();
+                cx.update(|cx| open_path(Some(path.clone()), cx)).ok();
             }
         }
     })
@@ -105,17 +158,31 @@ fn open_file(cx: &mut AppContext) {
 }
 
 struct TextEditor {
-    editor: View<Editor>,
+    workspace: View<Workspace>,
     focus_handle: FocusHandle,
     _settings_manager: SettingsManager,
+    _appearance_subscription: Subscription,
 }
 
 impl TextEditor {
-    fn new(editor: View<Editor>, focus_handle: FocusHandle, cx: &mut AppContext) -> Self {
+    fn new(
+        workspace: View<Workspace>,
+        focus_handle: FocusHandle,
+        cx: &mut ViewContext<Self>,
+    ) -> Self {
+        // the auto theme mode follows the system switching between light and dark
+        let _appearance_subscription = cx.observe_window_appearance(|_, cx| {
+            let appearance = cx.appearance();
+            cx.global_mut::<ThemeManager>()
+                .set_system_appearance(appearance);
+            cx.refresh();
+        });
+
         Self {
-            editor,
+            workspace,
             focus_handle,
             _settings_manager: SettingsManager::new(cx),
+            _appearance_subscription,
         }
     }
 }
@@ -133,10 +200,88 @@ impl Render for TextEditor {
             .flex()
             .flex_col()
             .size_full()
-            .child(self.editor.clone())
+            .child(self.workspace.clone())
     }
 }
 
+/// Rebuilt when the keymap changes, so the menus show the current shortcuts.
+fn app_menus(cx: &AppContext) -> Vec<Menu> {
+    let recent_items = cx
+        .db_connection()
+        .recent_files()
+        .into_iter()
+        .take(RECENT_MENU_LENGTH)
+        .map(|file| {
+            let label = file.path.to_string_lossy().to_string();
+            MenuItem::action(label, OpenRecent { path: file.path })
+        })
+        .collect();
+
+    vec![
+        Menu {
+            name: "Text".into(),
+            items: vec![
+                MenuItem::action("About text...", About),
+                MenuItem::separator(),
+                MenuItem::action("Hide", Hide),
+                MenuItem::action("Hide Others", HideOthers),
+                MenuItem::action("Show All", ShowAll),
+                MenuItem::action("Quit", Quit),
+            ],
+        },
+        Menu {
+            name: "File".into(),
+            items: vec![
+                MenuItem::action("New", FileNew),
+                MenuItem::action("New Window", NewWindow),
+                MenuItem::separator(),
+                MenuItem::action("Open", Open),
+                MenuItem::submenu(Menu {
+                    name: "Open Recent".into(),
+                    items: recent_items,
+                }),
+                MenuItem::action("Go to File...", ToggleFileFinder),
+                MenuItem::separator(),
+                MenuItem::action("Save", Save),
+                MenuItem::action("Save As", SaveAs),
+                MenuItem::action("Export as HTML...", ExportHtml),
+                MenuItem::separator(),
+                MenuItem::action("Reload from Disk", ReloadFile),
+            ],
+        },
+        Menu {
+            name: "Edit".into(),
+            items: vec![
+                MenuItem::os_action("Undo", Undo, OsAction::Undo),
+                MenuItem::os_action("Redo", Redo, OsAction::Redo),
+                MenuItem::separator(),
+                MenuItem::os_action("Cut", Cut, OsAction::Cut),
+                MenuItem::os_action("Copy", Copy, OsAction::Copy),
+                MenuItem::os_action("Paste", Paste, OsAction::Paste),
+            ],
+        },
+        Menu {
+            name: "Selection".into(),
+            items: vec![MenuItem::os_action(
+                "Select All",
+                SelectAll,
+                OsAction::SelectAll,
+            )],
+        },
+        Menu {
+            name: "View".into(),
+            items: vec![
+                MenuItem::action("Markdown Preview", TogglePreview),
+                MenuItem::action("Outline...", ToggleOutline),
+            ],
+        },
+        Menu {
+            name: "Window".into(),
+            items: vec![MenuItem::action("Minimize", Minimize)],
+        },
+    ]
+}
+
 #[derive(Clone)]
 struct OpenListener(UnboundedSender<Vec<String>>);
 
@@ -157,6 +302,11 @@ impl OpenListener {
 }
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("--export-html") {
+        std::process::exit(editor::html_export::export_from_args(&args[2..]));
+    }
+
     let app = App::new().with_assets(Assets {});
 
     let (open_listener, mut open_rx) = OpenListener::new();
@@ -168,101 +318,57 @@ fn main() {
 
     app.run(move |cx: &mut AppContext| {
         _ = DB::register_global(cx);
-
-        cx.bind_keys([
-            KeyBinding::new("cmd-q", Quit, None),
-            KeyBinding::new("cmd-o", Open, None),
-            KeyBinding::new("cmd-s", Save, None),
-            KeyBinding::new("shift-cmd-s", SaveAs, None),
-            KeyBinding::new("cmd-h", Hide, None),
-            KeyBinding::new("alt-cmd-h", HideOthers, None),
-            KeyBinding::new("cmd-n", FileNew, None),
-            KeyBinding::new("cmd-w", WindowClose, None),
-            KeyBinding::new("cmd-m", Minimize, None),
-        ]);
+        FileWatcher::init(cx);
+        KeymapManager::init(cx);
 
         cx.on_action(|_: &Quit, cx| cx.quit());
         cx.on_action(|_: &Hide, cx| cx.hide());
         cx.on_action(|_: &HideOthers, cx| cx.hide_other_apps());
         cx.on_action(|_: &ShowAll, cx| cx.unhide_other_apps());
-        cx.on_action(|_: &FileNew, cx| open_window(None, None, cx));
+        cx.on_action(|_: &FileNew, cx| open_path(None, cx));
+        cx.on_action(|_: &NewWindow, cx| open_window(None, Vec::new(), cx));
         cx.on_action(|_: &Open, cx| open_file(cx));
-
-        cx.set_menus(vec![
-            Menu {
-                name: "Text".into(),
-                items: vec![
-                    MenuItem::action("About text...", About),
-                    MenuItem::separator(),
-                    MenuItem::action("Hide", Hide),
-                    MenuItem::action("Hide Others", HideOthers),
-                    MenuItem::action("Show All", ShowAll),
-                    MenuItem::action("Quit", Quit),
-                ],
-            },
-            Menu {
-                name: "File".into(),
-                items: vec![
-                    MenuItem::action("New", FileNew),
-                    MenuItem::separator(),
-                    MenuItem::action("Open", Open),
-                    MenuItem::separator(),
-                    MenuItem::action("Save", Save),
-                    MenuItem::action("Save As", SaveAs),
-                ],
-            },
-            Menu {
-                name: "Edit".into(),
-                items: vec![
-                    MenuItem::os_action("Undo", Undo, OsAction::Undo),
-                    MenuItem::os_action("Redo", Redo, OsAction::Redo),
-                    MenuItem::separator(),
-                    MenuItem::os_action("Cut", Cut, OsAction::Cut),
-                    MenuItem::os_action("Copy", Copy, OsAction::Copy),
-                    MenuItem::os_action("Paste", Paste, OsAction::Paste),
-                ],
-            },
-            Menu {
-                name: "Selection".into(),
-                items: vec![MenuItem::os_action(
-                    "Select All",
-                    SelectAll,
-                    OsAction::SelectAll,
-                )],
-            },
-            Menu {
-                name: "Window".into(),
-                items: vec![MenuItem::action("Minimize", Minimize)],
-            },
-        ]);
+        cx.on_action(|action: &OpenRecent, cx| open_path(Some(action.path.clone()), cx));
 
         let mut opened_a_window = false;
 
         if let Some(open_windows) = cx.db_connection().open_windows() {
+            let mut windows: Vec<(MyUuid, Vec<(Option<MyUuid>, Option<PathBuf>)>)> = Vec::new();
+
             for old_window in open_windows {
+                let window_id = old_window.window_id.unwrap_or(old_window.file_id);
                 let file_path = old_window
                     .file_path
                     .as_ref()
                     .map(|f| std::path::Path::new(f).to_path_buf());
-                open_window(Some(old_window.file_id), file_path.as_ref(), cx);
+                let tab = (Some(old_window.file_id), file_path);
+
+                match windows.iter_mut().find(|(id, _)| *id == window_id) {
+                    Some((_, tabs)) => tabs.push(tab),
+                    None => windows.push((window_id, vec![tab])),
+                }
+            }
+
+            for (window_id, tabs) in windows {
+                open_window(Some(window_id), tabs, cx);
                 opened_a_window = true;
             }
         }
 
         if let Some(urls) = open_rx.try_next().ok().flatten() {
             for url in urls {
-                open_window(None, Some(&PathBuf::from(url)), cx);
+                open_window(None, vec![(None, Some(PathBuf::from(url)))], cx);
             }
         } else if !opened_a_window {
             #[cfg(debug_assertions)]
             {
                 let path = PathBuf::from("/Users/philipwagner/Documents/rust/text/test/test.md");
-                open_window(None, Some(&path), cx);
+                open_window(None, vec![(None, Some(path))], cx);
             }
 
             #[cfg(not(debug_assertions))]
             {
-                open_window(None, None, cx);
+                open_window(None, Vec::new(), cx);
             }
         }
 
@@ -270,7 +376,7 @@ fn main() {
             while let Some(urls) = open_rx.next().await {
                 cx.update(|cx| {
                     for url in urls {
-                        open_window(None, Some(&PathBuf::from(url)), cx);
+                        open_path(Some(PathBuf::from(url)), cx);
                     }
                 })
                 .ok();
@@ -25,3 +25,24 @@ pub fn app_data_path() -> Result<PathBuf> {
 
     Ok(app_data_path)
 }
+
+/// Where user themes are read from, the format is described in the readme.
+pub fn themes_path() -> Result<PathBuf> {
+    let themes_path = app_data_path()?.join("themes");
+
+    if !themes_path.exists() {
+        _ = fs::create_dir(&themes_path);
+    }
+
+    Ok(themes_path)
+}
+
+/// The user's settings file, it doesn't have to exist.
+pub fn settings_path() -> Result<PathBuf> {
+    Ok(app_data_path()?.join("settings.json"))
+}
+
+/// The user's keymap, it doesn't have to exist.
+pub fn keymap_path() -> Result<PathBuf> {
+    Ok(app_data_path()?.join("keymap.json"))
+}
@@ -0,0 +1,174 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use gpui::px;
+use serde::Deserialize;
+
+use crate::settings_manager::{BackupMode, Settings};
+
+/// One file's worth of settings, every key is optional, see the readme for the format.
+/// `languages` holds the same keys again, keyed by `LanguageConfig::language_id`.
+#[derive(Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SettingsLayer {
+    font_family: Option<String>,
+    font_size: Option<f32>,
+    line_height: Option<f32>,
+    tab_width: Option<usize>,
+    soft_wrap: Option<bool>,
+    cursor_blink: Option<bool>,
+    scroll_padding: Option<f32>,
+    backup: Option<BackupModeFile>,
+    #[serde(default)]
+    languages: HashMap<String, SettingsLayer>,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum BackupModeFile {
+    Off,
+    Single,
+    Versioned,
+}
+
+impl From<BackupModeFile> for BackupMode {
+    fn from(mode: BackupModeFile) -> Self {
+        match mode {
+            BackupModeFile::Off => BackupMode::Off,
+            BackupModeFile::Single => BackupMode::Single,
+            BackupModeFile::Versioned => BackupMode::Versioned,
+        }
+    }
+}
+
+fn in_range<T: PartialOrd + std::fmt::Display + Copy>(
+    key: &str,
+    value: Option<T>,
+    min: T,
+    max: T,
+    errors: &mut Vec<String>,
+) -> Option<T> {
+    let value = value?;
+    if value < min || value > max {
+        errors.push(format!(
+            "`{key}` must be between {min} and {max}, got {value}"
+        ));
+        return None;
+    }
+    Some(value)
+}
+
+/// Looked up in the directory of an opened file and all of its parents.
+pub const PROJECT_SETTINGS_FILE: &str = ".text-settings.json";
+
+impl SettingsLayer {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        if text.trim().is_empty() {
+            return Ok(Self::default());
+        }
+
+        let layer: Self = serde_json::from_str(text).map_err(|error| error.to_string())?;
+        for (language_id, language) in &layer.languages {
+            if !language.languages.is_empty() {
+                return Err(format!(
+                    "`languages.{language_id}` can't have languages itself"
+                ));
+            }
+        }
+        Ok(layer)
+    }
+
+    /// A missing file is an empty layer, a file that doesn't parse is ignored as a whole.
+    pub fn load(path: &Path) -> (Self, Vec<String>) {
+        match fs::read_to_string(path) {
+            Ok(text) => match Self::parse(&text) {
+                Ok(layer) => (layer, Vec::new()),
+                Err(error) => (Self::default(), vec![error]),
+            },
+            Err(error) if error.kind() == io::ErrorKind::NotFound => (Self::default(), Vec::new()),
+            Err(error) => (Self::default(), vec![error.to_string()]),
+        }
+    }
+
+    pub fn language(&self, language_id: &str) -> Option<&SettingsLayer> {
+        self.languages.get(language_id)
+    }
+
+    /// Validates every value of this layer and its languages against the defaults.
+    pub fn errors(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        self.apply(&mut Settings::default(), &mut errors);
+
+        let mut language_ids: Vec<&String> = self.languages.keys().collect();
+        language_ids.sort();
+        for language_id in language_ids {
+            let mut language_errors = Vec::new();
+            self.languages[language_id].apply(&mut Settings::default(), &mut language_errors);
+            errors.extend(
+                language_errors
+                    .into_iter()
+                    .map(|error| format!("languages.{language_id}: {error}")),
+            );
+        }
+        errors
+    }
+
+    /// Overwrites what this layer sets. Values that don't validate are skipped with one
+    /// message each.
+    pub fn apply(&self, settings: &mut Settings, errors: &mut Vec<String>) {
+        if let Some(font_family) = &self.font_family {
+            if font_family.trim().is_empty() {
+                errors.push("`font_family` must not be empty".into());
+            } else {
+                settings.font_family = font_family.clone().into();
+            }
+        }
+        if let Some(font_size) = in_range("font_size", self.font_size, 6., 72., errors) {
+            settings.font_size = px(font_size);
+            if self.line_height.is_none() {
+                // keeps the default spacing when only the font size was changed
+                settings.line_height = (settings.font_size * (28. / 18.)).round();
+            }
+        }
+        if let Some(line_height) = in_range("line_height", self.line_height, 8., 144., errors) {
+            settings.line_height = px(line_height);
+        }
+        let sets_size = self.font_size.is_some() || self.line_height.is_some();
+        if sets_size && settings.line_height < settings.font_size {
+            errors.push(format!(
+                "`line_height` ({}) must not be smaller than `font_size` ({})",
+                settings.line_height.0, settings.font_size.0
+            ));
+            settings.line_height = settings.font_size;
+        }
+        if let Some(tab_width) = in_range("tab_width", self.tab_width, 1, 16, errors) {
+            settings.tab_width = tab_width;
+        }
+        if let Some(scroll_padding) =
+            in_range("scroll_padding", self.scroll_padding, 0., 20., errors)
+        {
+            settings.scroll_padding = scroll_padding;
+        }
+        if let Some(soft_wrap) = self.soft_wrap {
+            settings.soft_wrap = soft_wrap;
+        }
+        if let Some(cursor_blink) = self.cursor_blink {
+            settings.cursor_blink = cursor_blink;
+        }
+        if let Some(backup) = self.backup {
+            settings.backup = backup.into();
+        }
+    }
+}
+
+/// Where project settings files for `file_path` can be, closest first.
+pub fn project_file_paths(file_path: &Path) -> Vec<PathBuf> {
+    file_path
+        .ancestors()
+        .skip(1)
+        .map(|dir| dir.join(PROJECT_SETTINGS_FILE))
+        .collect()
+}
@@ -1,43 +1,216 @@
-use gpui::{AppContext, Global};
-use smallvec::SmallVec;
+use std::path::{Path, PathBuf};
 
-use crate::theme_manager::{Theme, ThemeManager};
+use gpui::{px, AppContext, Global, Pixels, SharedString};
+
+use crate::{
+    file_watcher::FileWatcher,
+    paths::settings_path,
+    settings_file::{self, SettingsLayer},
+    theme_manager::{AppearanceMode, Theme, ThemeManager},
+};
+
+/// Language defaults, applied before the user's own language settings.
+const DEFAULT_SETTINGS: &str = r#"{
+    "languages": {
+        "markdown": { "soft_wrap": true },
+        "yml": { "tab_width": 2 }
+    }
+}"#;
+
+/// What to keep of the previous version when a file gets saved.
+#[derive(Clone, Copy, PartialEq)]
+pub enum BackupMode {
+    Off,
+    /// `file.md.bak`, replaced on every save
+    Single,
+    /// `file.md.1.bak` for the previous version, `file.md.2.bak` for the one before, and so
+    /// on for the last ten
+    Versioned,
+}
 
 #[derive(Clone)]
 pub struct Settings {
-    pub font_family: &'static str,
+    pub font_family: SharedString,
+    pub font_size: Pixels,
+    pub line_height: Pixels,
+    pub tab_width: usize,
+    /// Used for files that were never wrapped or unwrapped by hand.
+    pub soft_wrap: bool,
+    pub cursor_blink: bool,
+    /// Lines kept visible above and below the cursor while scrolling.
+    pub scroll_padding: f32,
+    pub backup: BackupMode,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            font_family: "Iosevka".into(),
+            font_size: px(18.),
+            line_height: px(28.),
+            tab_width: 4,
+            soft_wrap: false,
+            cursor_blink: true,
+            scroll_padding: 2.,
+            backup: BackupMode::Off,
+        }
+    }
+}
+
+/// The settings of one file: defaults, user, language and project file, layered in that order.
+/// A wrap toggled by hand is stored in `db::PathSettings` and applies on top.
+#[derive(Clone, Default)]
+pub struct FileSettings {
+    pub settings: Settings,
+    /// Problems with the project file, the user file's are in `settings_errors`.
+    pub errors: Vec<String>,
+    /// Where project files were looked for, up to the one that was used. Creating, removing
+    /// or writing any of them changes these settings.
+    project_file_paths: Vec<PathBuf>,
+}
+
+impl FileSettings {
+    pub fn project_file_paths(&self) -> &[PathBuf] {
+        &self.project_file_paths
+    }
 }
 
 #[derive(Clone)]
 pub struct SettingsManager {
     settings: Settings,
+    defaults: SettingsLayer,
+    user: SettingsLayer,
+    /// Why parts of the settings file were ignored, shown in the status bar.
+    errors: Vec<String>,
     _theme_manager: ThemeManager,
 }
 
 impl SettingsManager {
     pub fn new(cx: &mut AppContext) -> Self {
-        let theme_manager = ThemeManager::new();
-        cx.set_global::<ThemeManager>(theme_manager.clone());
+        // every window creates a settings manager, the themes are only loaded once
+        let theme_manager = if cx.has_global::<ThemeManager>() {
+            cx.global::<ThemeManager>().clone()
+        } else {
+            let theme_manager = ThemeManager::new(cx);
+            cx.set_global::<ThemeManager>(theme_manager.clone());
+            ThemeManager::watch(cx);
+            theme_manager
+        };
 
-        let this = Self {
-            settings: Settings {
-                font_family: "Iosevka",
-            },
+        if cx.has_global::<SettingsManager>() {
+            return cx.global::<SettingsManager>().clone();
+        }
+
+        let mut this = Self {
+            settings: Settings::default(),
+            defaults: SettingsLayer::parse(DEFAULT_SETTINGS)
+                .expect("default settings should parse"),
+            user: SettingsLayer::default(),
+            errors: Vec::new(),
             _theme_manager: theme_manager,
         };
+        this.reload();
 
         cx.set_global::<SettingsManager>(this.clone());
+        Self::watch(cx);
 
         this
     }
+
+    pub fn reload(&mut self) {
+        let Ok(path) = settings_path() else {
+            return;
+        };
+
+        let (user, mut errors) = SettingsLayer::load(&path);
+        errors.extend(user.errors());
+        for error in &errors {
+            eprintln!("settings: {error}");
+        }
+
+        let mut settings = Settings::default();
+        self.defaults.apply(&mut settings, &mut Vec::new());
+        user.apply(&mut settings, &mut Vec::new());
+
+        self.settings = settings;
+        self.user = user;
+        self.errors = errors;
+    }
+
+    /// Layers the language settings and the closest project file over the user's settings.
+    pub fn settings_for(
+        &self,
+        file_path: Option<&Path>,
+        language_id: Option<&str>,
+    ) -> FileSettings {
+        let mut settings = self.settings.clone();
+
+        if let Some(language_id) = language_id {
+            for layer in [&self.defaults, &self.user] {
+                if let Some(language) = layer.language(language_id) {
+                    language.apply(&mut settings, &mut Vec::new());
+                }
+            }
+        }
+
+        let mut errors = Vec::new();
+        let mut project_file_paths = file_path
+            .map(settings_file::project_file_paths)
+            .unwrap_or_default();
+        let project_file = project_file_paths
+            .iter()
+            .position(|path| path.is_file())
+            .map(|idx| {
+                project_file_paths.truncate(idx + 1);
+                project_file_paths[idx].clone()
+            });
+        if let Some(path) = &project_file {
+            let (project, load_errors) = SettingsLayer::load(path);
+            errors.extend(load_errors);
+            project.apply(&mut settings, &mut errors);
+            if let Some(language) = language_id.and_then(|id| project.language(id)) {
+                language.apply(&mut settings, &mut errors);
+            }
+
+            let name = path.display();
+            errors = errors
+                .into_iter()
+                .map(|error| format!("{name}: {error}"))
+                .collect();
+        }
+
+        FileSettings {
+            settings,
+            errors,
+            project_file_paths,
+        }
+    }
+
+    /// Reloads the settings file when it changes, open windows pick that up through
+    /// `observe_global`.
+    fn watch(cx: &mut AppContext) {
+        let Ok(path) = settings_path() else {
+            return;
+        };
+        FileWatcher::on_change(vec![path], cx, |cx| {
+            cx.update_global::<SettingsManager, _>(|this, _| this.reload());
+            cx.refresh();
+        });
+    }
 }
 
 impl Global for SettingsManager {}
 
 pub trait CurrentSettings {
     fn settings(&self) -> &Settings;
-    fn themes(&self) -> &SmallVec<[Theme; 2]>;
+    fn settings_errors(&self) -> &[String];
+    fn settings_for(&self, file_path: Option<&Path>, language_id: Option<&str>) -> FileSettings;
+    fn themes(&self) -> &[Theme];
+    fn theme_errors(&self) -> &[String];
+    fn theme_mode(&self) -> AppearanceMode;
+    fn is_preferred_theme(&self, theme: &Theme) -> bool;
     fn change_theme(&mut self, new_theme: &Theme);
+    fn change_theme_mode(&mut self, mode: AppearanceMode);
 }
 
 impl CurrentSettings for AppContext {
@@ -45,11 +218,38 @@ impl CurrentSettings for AppContext {
         &self.global::<SettingsManager>().settings
     }
 
-    fn themes(&self) -> &SmallVec<[Theme; 2]> {
+    fn settings_errors(&self) -> &[String] {
+        &self.global::<SettingsManager>().errors
+    }
+
+    fn settings_for(&self, file_path: Option<&Path>, language_id: Option<&str>) -> FileSettings {
+        self.global::<SettingsManager>()
+            .settings_for(file_path, language_id)
+    }
+
+    fn themes(&self) -> &[Theme] {
         self.global::<ThemeManager>().themes()
     }
 
+    fn theme_errors(&self) -> &[String] {
+        self.global::<ThemeManager>().errors()
+    }
+
+    fn theme_mode(&self) -> AppearanceMode {
+        self.global::<ThemeManager>().mode()
+    }
+
+    fn is_preferred_theme(&self, theme: &Theme) -> bool {
+        self.global::<ThemeManager>().is_preferred(theme)
+    }
+
     fn change_theme(&mut self, new_theme: &Theme) {
-        let () = &self.global_mut::<ThemeManager>().set_theme(new_theme);
+        self.global_mut::<ThemeManager>().set_theme(new_theme);
+        self.global::<ThemeManager>().save(self);
+    }
+
+    fn change_theme_mode(&mut self, mode: AppearanceMode) {
+        self.global_mut::<ThemeManager>().set_mode(mode);
+        self.global::<ThemeManager>().save(self);
     }
 }
@@ -0,0 +1,173 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use gpui::{rgb, rgba, FontWeight, Rgba};
+use serde::Deserialize;
+
+use crate::theme_manager::{SyntaxStyle, SyntaxTheme, Theme, ThemeMode};
+
+/// A theme as written in a `.json` or `.toml` file, see the readme for the format.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThemeFile {
+    name: Option<String>,
+    mode: ThemeMode,
+    background: HexColor,
+    editor_text: HexColor,
+    editor_background: HexColor,
+    cursor: HexColor,
+    selection_bg: HexColor,
+    hover_bg: HexColor,
+    scroll_bar_bg: HexColor,
+    scroll_bar_border: HexColor,
+    scroll_bar_handle_bg: HexColor,
+    scroll_bar_cursor_highlight: HexColor,
+    error: HexColor,
+    #[serde(default)]
+    syntax: HashMap<String, SyntaxStyleFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SyntaxStyleFile {
+    color: Option<HexColor>,
+    weight: Option<Weight>,
+    #[serde(default)]
+    italic: bool,
+    #[serde(default)]
+    underline: bool,
+    #[serde(default)]
+    strikethrough: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(try_from = "String")]
+struct HexColor(Rgba);
+
+impl TryFrom<String> for HexColor {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let hex = value.strip_prefix('#').unwrap_or(&value);
+        let color = u32::from_str_radix(hex, 16).ok();
+        match (hex.len(), color) {
+            (6, Some(color)) => Ok(Self(rgb(color))),
+            (8, Some(color)) => Ok(Self(rgba(color))),
+            _ => Err(format!(
+                "`{value}` is not a color, expected #rrggbb or #rrggbbaa"
+            )),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(try_from = "f32")]
+struct Weight(f32);
+
+impl TryFrom<f32> for Weight {
+    type Error = String;
+
+    fn try_from(value: f32) -> Result<Self, Self::Error> {
+        if (100. ..=900.).contains(&value) {
+            Ok(Self(value))
+        } else {
+            Err(format!(
+                "`{value}` is not a font weight, expected 100 to 900"
+            ))
+        }
+    }
+}
+
+impl From<ThemeFile> for Theme {
+    fn from(file: ThemeFile) -> Self {
+        Theme {
+            id: file.name.unwrap_or_default(),
+            mode: file.mode,
+            background: file.background.0,
+            editor_text: file.editor_text.0,
+            editor_background: file.editor_background.0,
+            cursor: file.cursor.0,
+            selection_bg: file.selection_bg.0,
+            hover_bg: file.hover_bg.0,
+            scroll_bar_bg: file.scroll_bar_bg.0,
+            scroll_bar_border: file.scroll_bar_border.0,
+            scroll_bar_handle_bg: file.scroll_bar_handle_bg.0,
+            scroll_bar_cursor_highlight: file.scroll_bar_cursor_highlight.0,
+            error: file.error.0,
+            syntax: SyntaxTheme::new(file.syntax.iter().map(|(name, style)| {
+                (
+                    name.as_str(),
+                    SyntaxStyle {
+                        color: style.color.as_ref().map(|color| color.0),
+                        font_weight: style.weight.as_ref().map(|weight| FontWeight(weight.0)),
+                        italic: style.italic,
+                        underline: style.underline,
+                        strikethrough: style.strikethrough,
+                    },
+                )
+            })),
+        }
+    }
+}
+
+/// Parses a theme, `path` picks the format and names the theme if the file doesn't.
+pub fn parse_theme(path: &Path, text: &str) -> Result<Theme, String> {
+    let file: ThemeFile = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => serde_json::from_str(text).map_err(|error| error.to_string())?,
+        Some("toml") => toml::from_str(text).map_err(|error| error.to_string())?,
+        _ => return Err("only .json and .toml files are read".into()),
+    };
+
+    let mut theme = Theme::from(file);
+    if theme.id.is_empty() {
+        theme.id = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+    }
+    Ok(theme)
+}
+
+/// All themes in `dir`, plus one message per file that couldn't be read.
+pub fn load_themes(dir: &Path) -> (Vec<Theme>, Vec<String>) {
+    let mut themes = Vec::new();
+    let mut errors = Vec::new();
+
+    for path in theme_files(dir) {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        match fs::read_to_string(&path) {
+            Ok(text) => match parse_theme(&path, &text) {
+                Ok(theme) => themes.push(theme),
+                Err(error) => errors.push(format!("{name}: {error}")),
+            },
+            Err(error) => errors.push(format!("{name}: {error}")),
+        }
+    }
+
+    (themes, errors)
+}
+
+fn theme_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            matches!(
+                path.extension().and_then(|ext| ext.to_str()),
+                Some("json" | "toml")
+            )
+        })
+        .collect();
+    paths.sort();
+    paths
+}
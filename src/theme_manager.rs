@@ -1,14 +1,129 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
-use gpui::{rgb, rgba, AppContext, Global, Rgba};
-use smallvec::{smallvec, SmallVec};
+use gpui::{rgb, rgba, AppContext, FontWeight, Global, Rgba, WindowAppearance};
+use serde::Deserialize;
 
-#[derive(Clone)]
+use crate::{
+    db::{DbConnection, ThemeSettings},
+    file_watcher::FileWatcher,
+    paths::themes_path,
+    theme_file,
+};
+
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ThemeMode {
     Light,
     Dark,
 }
 
+impl From<WindowAppearance> for ThemeMode {
+    fn from(appearance: WindowAppearance) -> Self {
+        match appearance {
+            WindowAppearance::Light | WindowAppearance::VibrantLight => Self::Light,
+            WindowAppearance::Dark | WindowAppearance::VibrantDark => Self::Dark,
+        }
+    }
+}
+
+/// Which of the two preferred themes is shown, `Auto` follows the system appearance.
+#[derive(Clone, Copy, PartialEq)]
+pub enum AppearanceMode {
+    Light,
+    Dark,
+    Auto,
+}
+
+impl AppearanceMode {
+    pub const ALL: [Self; 3] = [Self::Light, Self::Dark, Self::Auto];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Light => "Light",
+            Self::Dark => "Dark",
+            Self::Auto => "Auto",
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Light => "light",
+            Self::Dark => "dark",
+            Self::Auto => "auto",
+        }
+    }
+
+    fn from_str(mode: &str) -> Option<Self> {
+        match mode {
+            "light" => Some(Self::Light),
+            "dark" => Some(Self::Dark),
+            "auto" => Some(Self::Auto),
+            _ => None,
+        }
+    }
+}
+
+/// How text captured by a syntax query is drawn, unset parts come from the base text style.
+#[derive(Clone, Default)]
+pub struct SyntaxStyle {
+    pub color: Option<Rgba>,
+    pub font_weight: Option<FontWeight>,
+    pub italic: bool,
+    pub underline: bool,
+    pub strikethrough: bool,
+}
+
+impl SyntaxStyle {
+    pub fn color(color: u32) -> Self {
+        Self {
+            color: Some(rgb(color)),
+            ..Default::default()
+        }
+    }
+
+    pub fn bold(self) -> Self {
+        Self {
+            font_weight: Some(FontWeight::BOLD),
+            ..self
+        }
+    }
+
+    pub fn italic(self) -> Self {
+        Self {
+            italic: true,
+            ..self
+        }
+    }
+}
+
+/// Capture names like `text.title` to styles.
+#[derive(Clone, Default)]
+pub struct SyntaxTheme {
+    styles: HashMap<String, SyntaxStyle>,
+}
+
+impl SyntaxTheme {
+    pub fn new<'a>(styles: impl IntoIterator<Item = (&'a str, SyntaxStyle)>) -> Self {
+        Self {
+            styles: styles
+                .into_iter()
+                .map(|(name, style)| (name.to_string(), style))
+                .collect(),
+        }
+    }
+
+    /// Falls back to the parent name, so `text.title.1` uses the style of `text.title`.
+    pub fn style_for(&self, name: &str) -> Option<&SyntaxStyle> {
+        let mut name = name;
+        loop {
+            if let Some(style) = self.styles.get(name) {
+                return Some(style);
+            }
+            name = &name[..name.rfind('.')?];
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Theme {
     pub id: String,
@@ -24,16 +139,58 @@ pub struct Theme {
     pub scroll_bar_handle_bg: Rgba,
     pub scroll_bar_cursor_highlight: Rgba,
     pub error: Rgba,
+    pub syntax: SyntaxTheme,
 }
 
 #[derive(Clone)]
 pub struct ThemeManager {
     active_theme: Arc<Theme>,
-    themes: SmallVec<[Theme; 2]>,
+    themes: Vec<Theme>,
+    /// Why files in the themes folder couldn't be loaded, shown in the theme selector.
+    errors: Vec<String>,
+    mode: AppearanceMode,
+    light_theme: String,
+    dark_theme: String,
+    system_appearance: ThemeMode,
 }
 
 impl ThemeManager {
-    pub fn new() -> Self {
+    /// Restores the mode and the preferred themes from the db.
+    pub fn new(cx: &AppContext) -> Self {
+        Self::with_settings(
+            cx.db_connection().theme_settings(),
+            cx.window_appearance().into(),
+        )
+    }
+
+    /// Without an app, e.g. for exports from the command line.
+    pub fn with_settings(settings: Option<ThemeSettings>, system_appearance: ThemeMode) -> Self {
+        let built_in = Self::built_in_themes();
+        let mut this = Self {
+            active_theme: Arc::new(built_in[0].clone()),
+            themes: built_in,
+            errors: Vec::new(),
+            mode: AppearanceMode::Dark,
+            light_theme: "Other theme".into(),
+            dark_theme: "Default".into(),
+            system_appearance,
+        };
+
+        if let Some(settings) = settings {
+            this.mode = AppearanceMode::from_str(&settings.mode).unwrap_or(this.mode);
+            this.light_theme = settings.light_theme;
+            this.dark_theme = settings.dark_theme;
+        }
+
+        this.reload();
+        this
+    }
+
+    pub fn active_theme(&self) -> &Arc<Theme> {
+        &self.active_theme
+    }
+
+    fn built_in_themes() -> Vec<Theme> {
         let default_theme = Theme {
             id: "Default".into(),
             mode: ThemeMode::Dark,
@@ -48,6 +205,24 @@ impl ThemeManager {
             scroll_bar_handle_bg: rgba(0xaeaecd44),
             scroll_bar_cursor_highlight: rgba(0x89b4fadd),
             error: rgb(0xf38ba8),
+            syntax: SyntaxTheme::new([
+                ("text.strong", SyntaxStyle::default().bold()),
+                ("text.emphasis", SyntaxStyle::default().italic()),
+                ("text.title", SyntaxStyle::color(0xcccccc).bold()),
+                ("punctuation.special", SyntaxStyle::default().bold()),
+                ("punctuation.delimiter", SyntaxStyle::color(0x666666)),
+                ("punctuation.bracket", SyntaxStyle::color(0x666666)),
+                ("property", SyntaxStyle::color(0xfab387)),
+                ("tag", SyntaxStyle::color(0xfab387)),
+                ("text.literal", SyntaxStyle::color(0xfab387)),
+                ("text.uri", SyntaxStyle::color(0xfab387)),
+                ("text.reference", SyntaxStyle::color(0xfab387)),
+                ("string", SyntaxStyle::color(0xa6e3a1)),
+                ("comment", SyntaxStyle::color(0x6c7086).italic()),
+                ("keyword", SyntaxStyle::color(0xcba6f7)),
+                ("number", SyntaxStyle::color(0xeba0ac)),
+                ("boolean", SyntaxStyle::color(0xfa8585)),
+            ]),
         };
 
         let other_theme = Theme {
@@ -64,20 +239,139 @@ impl ThemeManager {
             scroll_bar_handle_bg: rgb(0x5c5f77),
             scroll_bar_cursor_highlight: rgb(0xd20f39),
             error: rgb(0xd20f39),
+            syntax: SyntaxTheme::new([
+                ("text.strong", SyntaxStyle::default().bold()),
+                ("text.emphasis", SyntaxStyle::default().italic()),
+                ("text.title", SyntaxStyle::color(0x4c4f69).bold()),
+                ("punctuation.special", SyntaxStyle::default().bold()),
+                ("punctuation.delimiter", SyntaxStyle::color(0x8c8fa1)),
+                ("punctuation.bracket", SyntaxStyle::color(0x8c8fa1)),
+                ("property", SyntaxStyle::color(0xfe640b)),
+                ("tag", SyntaxStyle::color(0xfe640b)),
+                ("text.literal", SyntaxStyle::color(0xfe640b)),
+                ("text.uri", SyntaxStyle::color(0xfe640b)),
+                ("text.reference", SyntaxStyle::color(0xfe640b)),
+                ("string", SyntaxStyle::color(0x40a02b)),
+                ("comment", SyntaxStyle::color(0x8c8fa1).italic()),
+                ("keyword", SyntaxStyle::color(0x8839ef)),
+                ("number", SyntaxStyle::color(0xe64553)),
+                ("boolean", SyntaxStyle::color(0xd20f39)),
+            ]),
         };
 
-        Self {
-            active_theme: Arc::new(default_theme.clone()),
-            themes: smallvec![default_theme, other_theme],
+        vec![default_theme, other_theme]
+    }
+
+    /// Reads the themes folder again, a file can replace a built-in theme by using its name.
+    /// The active theme is swapped for its new version so edits show up right away.
+    pub fn reload(&mut self) {
+        let Ok(dir) = themes_path() else {
+            return;
+        };
+
+        let (user_themes, errors) = theme_file::load_themes(&dir);
+        let mut themes = Self::built_in_themes();
+        for theme in user_themes {
+            match themes.iter_mut().find(|existing| existing.id == theme.id) {
+                Some(existing) => *existing = theme,
+                None => themes.push(theme),
+            }
         }
+
+        for error in &errors {
+            eprintln!("theme {error}");
+        }
+
+        self.themes = themes;
+        self.errors = errors;
+        self.update_active_theme();
+    }
+
+    /// Shows the preferred theme of the current mode, or any theme of that mode when it is gone.
+    fn update_active_theme(&mut self) {
+        let mode = match self.mode {
+            AppearanceMode::Light => ThemeMode::Light,
+            AppearanceMode::Dark => ThemeMode::Dark,
+            AppearanceMode::Auto => self.system_appearance,
+        };
+        let preferred = match mode {
+            ThemeMode::Light => &self.light_theme,
+            ThemeMode::Dark => &self.dark_theme,
+        };
+
+        let Some(theme) = self
+            .themes
+            .iter()
+            .find(|theme| theme.id == *preferred)
+            .or_else(|| self.themes.iter().find(|theme| theme.mode == mode))
+        else {
+            return;
+        };
+        self.active_theme = Arc::new(theme.clone());
     }
 
-    pub fn themes(&self) -> &SmallVec<[Theme; 2]> {
+    /// Reloads when a file in the themes folder was added, removed or changed.
+    pub fn watch(cx: &mut AppContext) {
+        let Ok(dir) = themes_path() else {
+            return;
+        };
+        FileWatcher::on_change(vec![dir], cx, |cx| {
+            cx.update_global::<ThemeManager, _>(|this, _| this.reload());
+            cx.refresh();
+        });
+    }
+
+    pub fn themes(&self) -> &[Theme] {
         &self.themes
     }
 
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+
+    pub fn mode(&self) -> AppearanceMode {
+        self.mode
+    }
+
+    pub fn is_preferred(&self, theme: &Theme) -> bool {
+        match theme.mode {
+            ThemeMode::Light => self.light_theme == theme.id,
+            ThemeMode::Dark => self.dark_theme == theme.id,
+        }
+    }
+
+    /// Makes `new_theme` the preferred theme of its mode. Outside of auto mode this also
+    /// switches to that mode, so the theme shows up right away.
     pub fn set_theme(&mut self, new_theme: &Theme) {
-        self.active_theme = Arc::new(new_theme.clone());
+        match new_theme.mode {
+            ThemeMode::Light => self.light_theme = new_theme.id.clone(),
+            ThemeMode::Dark => self.dark_theme = new_theme.id.clone(),
+        }
+        if self.mode != AppearanceMode::Auto {
+            self.mode = match new_theme.mode {
+                ThemeMode::Light => AppearanceMode::Light,
+                ThemeMode::Dark => AppearanceMode::Dark,
+            };
+        }
+        self.update_active_theme();
+    }
+
+    pub fn set_mode(&mut self, mode: AppearanceMode) {
+        self.mode = mode;
+        self.update_active_theme();
+    }
+
+    pub fn set_system_appearance(&mut self, appearance: WindowAppearance) {
+        self.system_appearance = appearance.into();
+        self.update_active_theme();
+    }
+
+    pub fn save(&self, cx: &AppContext) {
+        cx.db_connection().update_theme_settings(&ThemeSettings {
+            mode: self.mode.as_str().into(),
+            light_theme: self.light_theme.clone(),
+            dark_theme: self.dark_theme.clone(),
+        });
     }
 }
 
@@ -1,4 +1,5 @@
 pub mod icon_button;
 pub mod icons;
+pub mod match_label;
 pub mod text_input;
 pub mod tooltip;
@@ -10,6 +10,9 @@ pub enum Icons {
     CharacterSentenceCase,
     RadioButton,
     RadioButtonChecked,
+    Regex,
+    Replace,
+    ReplaceAll,
 }
 
 impl Icons {
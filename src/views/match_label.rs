@@ -0,0 +1,47 @@
+use gpui::*;
+use prelude::FluentBuilder;
+
+use crate::theme_manager::ActiveTheme;
+
+/// Text with the chars of a fuzzy match emphasized.
+#[derive(IntoElement)]
+pub struct MatchLabel {
+    text: String,
+    /// Byte offsets into `text`.
+    positions: Vec<usize>,
+}
+
+impl MatchLabel {
+    pub fn new(text: impl Into<String>, positions: Vec<usize>) -> Self {
+        Self {
+            text: text.into(),
+            positions,
+        }
+    }
+}
+
+impl RenderOnce for MatchLabel {
+    fn render(self, cx: &mut WindowContext) -> impl IntoElement {
+        let mut segments: Vec<(String, bool)> = Vec::new();
+        for (byte_idx, c) in self.text.char_indices() {
+            let matched = self.positions.contains(&byte_idx);
+            match segments.last_mut() {
+                Some((text, last_matched)) if *last_matched == matched => text.push(c),
+                _ => segments.push((c.to_string(), matched)),
+            }
+        }
+
+        div()
+            .flex()
+            .overflow_hidden()
+            .children(segments.into_iter().map(|(text, matched)| {
+                div()
+                    .flex_none()
+                    .when(matched, |el| {
+                        el.font_weight(FontWeight::BOLD)
+                            .text_color(cx.theme().cursor)
+                    })
+                    .child(text)
+            }))
+    }
+}
@@ -1,8 +1,13 @@
 mod blink_manager;
+pub mod buffer;
 mod char_kind;
 mod command;
+pub mod history;
 pub mod lines;
+pub mod markdown;
 mod scroll_manager;
+pub mod selection;
 mod syntax;
+mod syntax_map;
 mod text_element;
 pub mod text_input;
@@ -4,6 +4,8 @@ use gpui::{ModelContext, Timer};
 
 pub struct BlinkManager {
     blink_interval: Duration,
+    /// Off means the cursor stays visible while enabled.
+    blinking: bool,
     enabled: bool,
     paused: bool,
     show: bool,
@@ -15,6 +17,7 @@ impl BlinkManager {
     pub fn new() -> Self {
         Self {
             blink_interval: Duration::from_millis(500),
+            blinking: true,
             enabled: false,
             paused: false,
             show: false,
@@ -23,7 +26,7 @@ impl BlinkManager {
     }
 
     fn blink(&mut self, epoch: usize, cx: &mut ModelContext<Self>) {
-        if epoch == self.epoch && self.enabled && !self.paused {
+        if epoch == self.epoch && self.enabled && !self.paused && self.blinking {
             self.show = !self.show;
             cx.notify();
 
@@ -45,10 +48,21 @@ impl BlinkManager {
         }
 
         self.enabled = true;
-        self.show = false;
+        self.show = !self.blinking;
         self.blink(self.epoch, cx);
     }
 
+    pub fn set_blinking(&mut self, blinking: bool, cx: &mut ModelContext<Self>) {
+        if self.blinking == blinking {
+            return;
+        }
+
+        self.blinking = blinking;
+        if self.enabled {
+            self.pause(cx);
+        }
+    }
+
     pub fn disable(&mut self) {
         self.show = false;
         self.enabled = false;
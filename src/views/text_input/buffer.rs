@@ -0,0 +1,267 @@
+use std::{ops::Range, path::PathBuf, sync::Arc};
+
+use gpui::*;
+use ropey::Rope;
+use tree_sitter::{InputEdit, Parser, Tree};
+
+use super::{
+    command::{Command, GroupCommand},
+    syntax::{LanguageConfig, LanguageConfigManager},
+    syntax_map::SyntaxMap,
+};
+
+/// Above this size the document isn't parsed anymore and is shown as plain text.
+pub const LARGE_FILE_BYTES: usize = 4 * 1024 * 1024;
+
+/// `range` (in chars) was replaced by `new_len` chars.
+#[derive(Clone, Debug)]
+pub struct BufferEdit {
+    pub range: Range<usize>,
+    pub new_len: usize,
+}
+
+impl BufferEdit {
+    /// Where a char offset from before the edit ends up after it.
+    pub fn adjust(&self, offset: usize) -> usize {
+        if offset <= self.range.start {
+            offset
+        } else if offset >= self.range.end {
+            offset - self.range.len() + self.new_len
+        } else {
+            self.range.start
+        }
+    }
+}
+
+pub struct BufferEdited {
+    pub edits: Vec<BufferEdit>,
+    pub origin: EntityId,
+}
+
+impl BufferEdited {
+    /// The chars of the new content covered by any of the edits.
+    pub fn changed_range(&self) -> Option<Range<usize>> {
+        self.edits.iter().fold(None, |changed, edit| {
+            let range = edit.range.start..edit.range.start + edit.new_len;
+            Some(match changed {
+                Some(changed) => {
+                    edit.adjust(changed.start).min(range.start)
+                        ..edit.adjust(changed.end).max(range.end)
+                }
+                None => range,
+            })
+        })
+    }
+}
+
+/// The text, parse tree and undo history of a document. Every `TextInput` showing
+/// the document holds the same buffer and keeps its own selections and scroll state.
+pub struct Buffer {
+    pub content: Rope,
+    pub parse_tree: Option<Tree>,
+    pub syntax_map: SyntaxMap,
+    parser: Parser,
+    markdown: Arc<LanguageConfig>,
+    language_configs: LanguageConfigManager,
+    pub undo_stack: Vec<GroupCommand>,
+    pub redo_stack: Vec<GroupCommand>,
+    pub file_path: Option<PathBuf>,
+    /// Counts the edits, so state computed from the content can tell it is outdated.
+    pub version: usize,
+    /// Depth of the undo stack when the content was saved, `None` once undo and redo
+    /// can't get back to it.
+    save_point: Option<usize>,
+}
+
+impl EventEmitter<BufferEdited> for Buffer {}
+
+impl Buffer {
+    pub fn new(language_configs: &LanguageConfigManager) -> Self {
+        let markdown = language_configs
+            .language_config_for_language_id("markdown")
+            .expect("Markdown should always be there");
+
+        let mut parser = Parser::new();
+        parser
+            .set_language(&markdown.language)
+            .expect("Error Loading MD Grammar");
+
+        let parse_tree = parser.parse("", None);
+
+        Self {
+            content: "".into(),
+            parse_tree,
+            syntax_map: SyntaxMap::default(),
+            parser,
+            markdown,
+            language_configs: language_configs.clone(),
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            file_path: None,
+            version: 0,
+            save_point: Some(0),
+        }
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.save_point != Some(self.undo_stack.len())
+    }
+
+    pub fn set_dirty(&mut self, is_dirty: bool) {
+        self.save_point = if is_dirty {
+            None
+        } else {
+            Some(self.undo_stack.len())
+        };
+    }
+
+    /// Adds an undo step, `merge` folds it into the last one instead. Either way the redo
+    /// stack is gone, and with it a save point on it.
+    pub fn push_undo(&mut self, group: GroupCommand, merge: impl FnOnce(&GroupCommand) -> bool) {
+        let depth = self.undo_stack.len();
+        match self.undo_stack.last_mut() {
+            Some(last) if merge(last) => {
+                last.merge(group);
+                if self.save_point == Some(depth) {
+                    self.save_point = None;
+                }
+            }
+            _ => {
+                self.undo_stack.push(group);
+                if self.save_point.is_some_and(|save_point| save_point > depth) {
+                    self.save_point = None;
+                }
+            }
+        }
+        self.redo_stack.clear();
+    }
+
+    /// Applies `command` and tells every view about the edit; `origin` is the view that made it.
+    pub fn execute(
+        &mut self,
+        command: &dyn Command,
+        origin: EntityId,
+        cx: &mut ModelContext<Self>,
+    ) {
+        let mut edits = Vec::new();
+        self.execute_leaves(command, &mut edits);
+        self.reparse();
+        self.version += 1;
+        cx.emit(BufferEdited { edits, origin });
+    }
+
+    pub fn undo(&mut self, command: &dyn Command, origin: EntityId, cx: &mut ModelContext<Self>) {
+        let mut edits = Vec::new();
+        self.undo_leaves(command, &mut edits);
+        self.reparse();
+        self.version += 1;
+        cx.emit(BufferEdited { edits, origin });
+    }
+
+    pub fn is_large(&self) -> bool {
+        self.content.len_bytes() > LARGE_FILE_BYTES
+    }
+
+    /// Runs `apply` on the content and sends the same `InputEdit` to the root tree and the
+    /// injection layers. They are parsed again once the whole command is applied.
+    /// `range` is inserted by `apply`, or removed by it when `inserted` is false.
+    fn edit_content(&mut self, range: Range<usize>, inserted: bool, apply: impl FnOnce(&mut Rope)) {
+        let (old_end, new_end) = if inserted {
+            (range.start, range.end)
+        } else {
+            (range.end, range.start)
+        };
+        let start_byte = self.content.char_to_byte(range.start);
+        let old_end_byte = self.content.char_to_byte(old_end);
+        let start_position = point_for_byte(&self.content, start_byte);
+        let old_end_position = point_for_byte(&self.content, old_end_byte);
+
+        apply(&mut self.content);
+
+        if let Some(tree) = self.parse_tree.as_mut() {
+            let new_end_byte = self.content.char_to_byte(new_end);
+            let edit = InputEdit {
+                start_byte,
+                old_end_byte,
+                new_end_byte,
+                start_position,
+                old_end_position,
+                new_end_position: point_for_byte(&self.content, new_end_byte),
+            };
+            tree.edit(&edit);
+            self.syntax_map.edit(&edit);
+        }
+    }
+
+    /// Parses the edited trees, a multi-cursor edit or a replace all is one parse.
+    fn reparse(&mut self) {
+        if self.is_large() {
+            self.parse_tree = None;
+            self.syntax_map.clear();
+            return;
+        }
+
+        // without an old tree, e.g. back below the limit, this parses from scratch
+        let old_tree = self.parse_tree.take();
+        self.parse_tree = self.parser.parse_with(
+            &mut |byte, _| {
+                let (chunk, start_byte, _, _) = self.content.chunk_at_byte(byte);
+                &chunk.as_bytes()[byte - start_byte..]
+            },
+            old_tree.as_ref(),
+        );
+
+        if let Some(tree) = &self.parse_tree {
+            self.syntax_map
+                .reparse(tree, &self.content, &self.markdown, &self.language_configs);
+        }
+    }
+
+    fn execute_leaves(&mut self, command: &dyn Command, edits: &mut Vec<BufferEdit>) {
+        if let Some(children) = command.children() {
+            for child in children {
+                self.execute_leaves(child.as_ref(), edits);
+            }
+            return;
+        }
+
+        let r = command.char_range();
+        let inserted = !command.update_tree_before();
+        self.edit_content(r.clone(), inserted, |content| {
+            command.execute(content);
+        });
+        edits.push(edit_for(r, inserted));
+    }
+
+    fn undo_leaves(&mut self, command: &dyn Command, edits: &mut Vec<BufferEdit>) {
+        if let Some(children) = command.children() {
+            for child in children.iter().rev() {
+                self.undo_leaves(child.as_ref(), edits);
+            }
+            return;
+        }
+
+        let r = command.char_range();
+        let inserted = command.update_tree_before();
+        self.edit_content(r.clone(), inserted, |content| {
+            command.undo(content);
+        });
+        edits.push(edit_for(r, inserted));
+    }
+}
+
+fn edit_for(range: Range<usize>, inserted: bool) -> BufferEdit {
+    if inserted {
+        BufferEdit {
+            range: range.start..range.start,
+            new_len: range.len(),
+        }
+    } else {
+        BufferEdit { range, new_len: 0 }
+    }
+}
+
+fn point_for_byte(content: &Rope, byte: usize) -> tree_sitter::Point {
+    let row = content.byte_to_line(byte);
+    tree_sitter::Point::new(row, byte - content.line_to_byte(row))
+}
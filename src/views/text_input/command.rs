@@ -1,12 +1,66 @@
 use std::ops::Range;
 
 use ropey::Rope;
+use serde::{Deserialize, Serialize};
+
+use super::selection::Selection;
 
 pub trait Command {
     fn execute(&self, content: &mut Rope) -> Range<usize>;
     fn undo(&self, content: &mut Rope) -> Range<usize>;
     fn char_range(&self) -> Range<usize>;
     fn update_tree_before(&self) -> bool;
+
+    fn children(&self) -> Option<&[Box<dyn Command>]> {
+        None
+    }
+
+    fn to_record(&self) -> CommandRecord;
+}
+
+/// Plain data form of a command, used to persist the undo history.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum CommandRecord {
+    Insert {
+        position: usize,
+        text: String,
+        old_selection: Range<usize>,
+    },
+    Delete {
+        position: usize,
+        text: String,
+        old_selection: Range<usize>,
+    },
+    Group {
+        commands: Vec<CommandRecord>,
+    },
+}
+
+impl CommandRecord {
+    pub fn into_command(self) -> Box<dyn Command> {
+        match self {
+            CommandRecord::Insert {
+                position,
+                text,
+                old_selection,
+            } => Box::new(InsertCommand::new(position, text, old_selection)),
+            CommandRecord::Delete {
+                position,
+                text,
+                old_selection,
+            } => Box::new(DeleteCommand::new(position, text, old_selection)),
+            CommandRecord::Group { commands } => Box::new(GroupCommand::new(
+                commands
+                    .into_iter()
+                    .map(CommandRecord::into_command)
+                    .collect(),
+                Vec::new(),
+                Vec::new(),
+                None,
+            )),
+        }
+    }
 }
 
 pub struct InsertCommand {
@@ -44,6 +98,14 @@ impl Command for InsertCommand {
     fn update_tree_before(&self) -> bool {
         false
     }
+
+    fn to_record(&self) -> CommandRecord {
+        CommandRecord::Insert {
+            position: self.position,
+            text: self.text.clone(),
+            old_selection: self.old_selection.clone(),
+        }
+    }
 }
 
 pub struct DeleteCommand {
@@ -80,4 +142,87 @@ impl Command for DeleteCommand {
     fn update_tree_before(&self) -> bool {
         true
     }
+
+    fn to_record(&self) -> CommandRecord {
+        CommandRecord::Delete {
+            position: self.position,
+            text: self.text.clone(),
+            old_selection: self.old_selection.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum EditKind {
+    Insert,
+    Delete,
+}
+
+/// One undo step: every command of a transaction plus the selections around it.
+pub struct GroupCommand {
+    commands: Vec<Box<dyn Command>>,
+    pub old_selections: Vec<Selection>,
+    pub new_selections: Vec<Selection>,
+    pub kind: Option<EditKind>,
+}
+
+impl GroupCommand {
+    pub fn new(
+        commands: Vec<Box<dyn Command>>,
+        old_selections: Vec<Selection>,
+        new_selections: Vec<Selection>,
+        kind: Option<EditKind>,
+    ) -> Self {
+        Self {
+            commands,
+            old_selections,
+            new_selections,
+            kind,
+        }
+    }
+
+    pub fn merge(&mut self, other: GroupCommand) {
+        self.commands.extend(other.commands);
+        self.new_selections = other.new_selections;
+    }
+}
+
+impl Command for GroupCommand {
+    fn execute(&self, content: &mut Rope) -> Range<usize> {
+        let mut selection = 0..0;
+        for command in &self.commands {
+            selection = command.execute(content);
+        }
+        selection
+    }
+
+    fn undo(&self, content: &mut Rope) -> Range<usize> {
+        let mut selection = 0..0;
+        for command in self.commands.iter().rev() {
+            selection = command.undo(content);
+        }
+        selection
+    }
+
+    fn char_range(&self) -> Range<usize> {
+        self.commands
+            .iter()
+            .map(|command| command.char_range())
+            .reduce(|a, b| a.start.min(b.start)..a.end.max(b.end))
+            .unwrap_or(0..0)
+    }
+
+    fn update_tree_before(&self) -> bool {
+        false
+    }
+
+    fn children(&self) -> Option<&[Box<dyn Command>]> {
+        Some(&self.commands)
+    }
+
+    fn to_record(&self) -> CommandRecord {
+        CommandRecord::Group {
+            commands: self.commands.iter().map(|c| c.to_record()).collect(),
+        }
+    }
 }
@@ -0,0 +1,119 @@
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    command::{Command, CommandRecord, EditKind, GroupCommand},
+    selection::Selection,
+};
+
+const MAX_HISTORY_STEPS: usize = 500;
+const MAX_HISTORY_BYTES: usize = 2 * 1024 * 1024;
+
+#[derive(Serialize, Deserialize)]
+pub struct HistoryStep {
+    commands: Vec<CommandRecord>,
+    old_selections: Vec<Selection>,
+    new_selections: Vec<Selection>,
+    kind: Option<EditKind>,
+}
+
+impl From<&GroupCommand> for HistoryStep {
+    fn from(group: &GroupCommand) -> Self {
+        Self {
+            commands: group
+                .children()
+                .unwrap_or_default()
+                .iter()
+                .map(|command| command.to_record())
+                .collect(),
+            old_selections: group.old_selections.clone(),
+            new_selections: group.new_selections.clone(),
+            kind: group.kind,
+        }
+    }
+}
+
+impl From<HistoryStep> for GroupCommand {
+    fn from(step: HistoryStep) -> Self {
+        GroupCommand::new(
+            step.commands
+                .into_iter()
+                .map(CommandRecord::into_command)
+                .collect(),
+            step.old_selections,
+            step.new_selections,
+            step.kind,
+        )
+    }
+}
+
+/// Undo/redo stacks plus the selections, tied to the content they were recorded on.
+#[derive(Serialize, Deserialize)]
+pub struct History {
+    content_len: usize,
+    checksum: u64,
+    pub undo_stack: Vec<HistoryStep>,
+    pub redo_stack: Vec<HistoryStep>,
+    pub selections: Vec<Selection>,
+}
+
+impl History {
+    pub fn new(
+        content: &Rope,
+        undo_stack: &[GroupCommand],
+        redo_stack: &[GroupCommand],
+        selections: Vec<Selection>,
+    ) -> Self {
+        let newest = |stack: &[GroupCommand]| -> Vec<HistoryStep> {
+            let skip = stack.len().saturating_sub(MAX_HISTORY_STEPS);
+            stack.iter().skip(skip).map(HistoryStep::from).collect()
+        };
+
+        Self {
+            content_len: content.len_chars(),
+            checksum: checksum(content),
+            undo_stack: newest(undo_stack),
+            redo_stack: newest(redo_stack),
+            selections,
+        }
+    }
+
+    pub fn matches(&self, content: &Rope) -> bool {
+        self.content_len == content.len_chars() && self.checksum == checksum(content)
+    }
+
+    /// Serializes the history, dropping the oldest steps until it fits the size cap.
+    pub fn into_json(mut self) -> Option<String> {
+        loop {
+            let json = serde_json::to_string(&self).ok()?;
+            if json.len() <= MAX_HISTORY_BYTES {
+                return Some(json);
+            }
+
+            if !self.redo_stack.is_empty() {
+                self.redo_stack.clear();
+            } else if !self.undo_stack.is_empty() {
+                let half = self.undo_stack.len().div_ceil(2);
+                self.undo_stack.drain(..half);
+            } else {
+                return None;
+            }
+        }
+    }
+
+    pub fn from_json(json: &str) -> Option<Self> {
+        serde_json::from_str(json).ok()
+    }
+}
+
+/// FNV-1a over the content, stable across builds unlike `DefaultHasher`.
+fn checksum(content: &Rope) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for chunk in content.chunks() {
+        for byte in chunk.bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
@@ -1,71 +1,213 @@
+use std::{ops::Range, rc::Rc};
+
 use gpui::*;
+use ropey::Rope;
 use smallvec::SmallVec;
 
+/// Heights of all logical lines, measured where they were shaped and estimated elsewhere.
+/// Kept across frames so only the visible lines have to be laid out.
+#[derive(Default)]
+pub struct LineHeights {
+    heights: Vec<Pixels>,
+    /// `tops[idx]` is the y of line `idx`, the last entry is the total height.
+    tops: Rc<[Pixels]>,
+    /// Lines from here on have to be summed up again before `tops` can be used.
+    dirty_from: Option<usize>,
+    wrap_width: Option<Pixels>,
+    line_height: Pixels,
+    em_width: Pixels,
+}
+
+impl LineHeights {
+    /// Starts over when the layout parameters changed or the line count went out of sync.
+    pub fn sync(
+        &mut self,
+        content: &Rope,
+        wrap_width: Option<Pixels>,
+        line_height: Pixels,
+        em_width: Pixels,
+    ) {
+        if self.wrap_width != wrap_width
+            || self.line_height != line_height
+            || self.em_width != em_width
+            || self.heights.len() != content.len_lines()
+        {
+            self.wrap_width = wrap_width;
+            self.line_height = line_height;
+            self.em_width = em_width;
+            self.heights = (0..content.len_lines())
+                .map(|idx| self.estimate(content, idx))
+                .collect();
+            self.dirty_from = Some(0);
+        }
+        self.update_tops();
+    }
+
+    /// Re-estimates the lines touched by `changed` (chars in the new content) and keeps the rest.
+    pub fn edited(&mut self, content: &Rope, changed: Range<usize>) {
+        if self.heights.is_empty() {
+            return;
+        }
+
+        let len_chars = content.len_chars();
+        let first_line = content.char_to_line(changed.start.min(len_chars));
+        let last_line = content.char_to_line(changed.end.min(len_chars));
+        let lines_after = content.len_lines() - last_line - 1;
+
+        let old_end = self
+            .heights
+            .len()
+            .saturating_sub(lines_after)
+            .max(first_line)
+            .min(self.heights.len());
+        let first_line = first_line.min(old_end);
+        let new_heights: Vec<Pixels> = (first_line..=last_line)
+            .map(|idx| self.estimate(content, idx))
+            .collect();
+        self.heights.splice(first_line..old_end, new_heights);
+        self.mark_dirty(first_line);
+    }
+
+    /// Replaces the estimates of the lines shaped this frame with their real heights.
+    pub fn measured(&mut self, first_line: usize, lines: &[WrappedLine]) {
+        for (idx, line) in lines.iter().enumerate() {
+            let height = line.size(self.line_height).height;
+            if let Some(old) = self.heights.get_mut(first_line + idx) {
+                if *old != height {
+                    *old = height;
+                    self.mark_dirty(first_line + idx);
+                }
+            }
+        }
+        self.update_tops();
+    }
+
+    pub fn tops(&self) -> Rc<[Pixels]> {
+        self.tops.clone()
+    }
+
+    fn mark_dirty(&mut self, from: usize) {
+        self.dirty_from = Some(self.dirty_from.map_or(from, |dirty| dirty.min(from)));
+    }
+
+    fn update_tops(&mut self) {
+        let Some(from) = self.dirty_from.take() else {
+            return;
+        };
+
+        let mut tops = Vec::with_capacity(self.heights.len() + 1);
+        let from = from.min(self.tops.len().saturating_sub(1));
+        tops.extend_from_slice(&self.tops[..from]);
+        let mut y = tops.last().copied().unwrap_or(px(0.));
+        if from > 0 {
+            y += self.heights[from - 1];
+        }
+        for height in &self.heights[from..] {
+            tops.push(y);
+            y += *height;
+        }
+        tops.push(y);
+        self.tops = tops.into();
+    }
+
+    fn estimate(&self, content: &Rope, line_idx: usize) -> Pixels {
+        let Some(wrap_width) = self.wrap_width else {
+            return self.line_height;
+        };
+
+        let width = self.em_width * content.line(line_idx).len_chars() as f32;
+        let rows = (width / wrap_width).ceil().max(1.);
+        self.line_height * rows
+    }
+}
+
+/// The layout of one frame: the lines around the viewport are shaped, the positions of all
+/// others come from the height index.
 pub struct Lines {
+    /// Shaped lines, starting at logical line `first_line`.
     pub lines: SmallVec<[WrappedLine; 1]>,
+    pub first_line: usize,
+    /// Lines shaped outside of `lines`, e.g. the cursor's line while it is scrolled away.
+    pub extra_lines: Vec<(usize, WrappedLine)>,
     pub line_height: Pixels,
+    tops: Rc<[Pixels]>,
 }
 
 impl Lines {
-    pub fn new(lines: SmallVec<[WrappedLine; 1]>, line_height: Pixels) -> Self {
-        Self { lines, line_height }
+    pub fn new(
+        lines: SmallVec<[WrappedLine; 1]>,
+        first_line: usize,
+        extra_lines: Vec<(usize, WrappedLine)>,
+        tops: Rc<[Pixels]>,
+        line_height: Pixels,
+    ) -> Self {
+        Self {
+            lines,
+            first_line,
+            extra_lines,
+            line_height,
+            tops,
+        }
     }
 
     pub fn position_for_byte_idx_in_line(&self, byte_idx: usize, line_idx: usize) -> Point<Pixels> {
         let previous_heights = self.height_till_line_idx(line_idx);
-        let line = self.lines.get(line_idx).unwrap();
-        let position_in_line = line.position_for_index(byte_idx, self.line_height).unwrap();
+        let position_in_line = self
+            .line(line_idx)
+            .and_then(|line| line.position_for_index(byte_idx, self.line_height))
+            .unwrap_or_default();
 
         point(position_in_line.x, position_in_line.y + previous_heights)
     }
 
+    pub fn position_for_char_idx(&self, char_idx: usize, content: &Rope) -> Point<Pixels> {
+        let line_idx = content.char_to_line(char_idx);
+        let line_byte_idx = content.line_to_byte(line_idx);
+        let byte_idx = content.char_to_byte(char_idx);
+
+        self.position_for_byte_idx_in_line(byte_idx - line_byte_idx, line_idx)
+    }
+
     pub fn height_till_line_idx(&self, line_idx: usize) -> Pixels {
-        self.lines
-            .iter()
-            .enumerate()
-            .take_while(|(idx, _)| *idx < line_idx)
-            .fold(px(0.), |total_height, (_, line)| {
-                total_height + line.size(self.line_height).height
-            })
+        self.tops
+            .get(line_idx)
+            .or(self.tops.last())
+            .copied()
+            .unwrap_or_default()
     }
 
     pub fn byte_index_for_position(&self, position: Point<Pixels>) -> Option<(usize, usize)> {
-        let mut previous_heights = px(0.);
-        for (line_idx, line) in self.lines.iter().enumerate() {
-            let size = line.size(self.line_height);
-            let temp_pos = point(position.x, position.y - previous_heights);
-
-            if temp_pos.y < px(0.) {
-                return None;
-            }
-
-            if let Ok(index) = line.index_for_position(temp_pos, self.line_height) {
-                return Some((line_idx, index));
-            }
+        let line_idx = self.line_idx_for_y(position.y)?;
+        let line = self.line(line_idx)?;
+        let temp_pos = point(position.x, position.y - self.height_till_line_idx(line_idx));
 
-            previous_heights += size.height;
-        }
-
-        None
+        line.index_for_position(temp_pos, self.line_height)
+            .ok()
+            .map(|index| (line_idx, index))
     }
 
     pub fn line_idx_for_y(&self, y: Pixels) -> Option<usize> {
-        let mut previous_heights = px(0.);
-        for (idx, line) in self.lines.iter().enumerate() {
-            let size = line.size(self.line_height);
-
-            if y >= previous_heights && y <= previous_heights + size.height {
-                return Some(idx);
-            }
-
-            previous_heights += size.height;
+        if y < px(0.) || y > self.height() {
+            return None;
         }
 
-        None
+        let line_count = self.tops.len().saturating_sub(1);
+        let idx = self.tops.partition_point(|top| *top <= y);
+        Some(idx.saturating_sub(1).min(line_count.saturating_sub(1)))
+    }
+
+    /// The logical lines overlapping `top..bottom`.
+    pub fn line_range_for_y(&self, top: Pixels, bottom: Pixels) -> Range<usize> {
+        let line_count = self.tops.len().saturating_sub(1);
+        let start = self.line_idx_for_y(top.max(px(0.))).unwrap_or(line_count);
+        let end = self
+            .line_idx_for_y(bottom.min(self.height()))
+            .map_or(line_count, |idx| idx + 1);
+        start..end.max(start)
     }
 
     pub fn wrapped_line_end_idx(&self, line_idx: usize, byte_idx_in_line: usize) -> Option<usize> {
-        let line = self.lines.get(line_idx)?;
+        let line = self.line(line_idx)?;
         let end_idx = line
             .wrap_boundaries()
             .iter()
@@ -83,24 +225,36 @@ impl Lines {
         line_idx: usize,
         byte_idx_in_line: usize,
     ) -> Option<Point<Pixels>> {
-        let line = self.lines.get(line_idx)?;
+        let line = self.line(line_idx)?;
         let end_idx = self.wrapped_line_end_idx(line_idx, byte_idx_in_line)?;
         line.position_for_index(end_idx, self.line_height)
     }
 
     pub fn height(&self) -> Pixels {
-        self.lines.iter().fold(px(0.), |height, line| {
-            height + line.size(self.line_height).height
-        })
+        self.tops.last().copied().unwrap_or_default()
     }
 
     pub fn width(&self) -> Pixels {
         self.lines
             .iter()
+            .chain(self.extra_lines.iter().map(|(_, line)| line))
             .fold(px(0.), |max_width, line| max_width.max(line.width()))
     }
 
+    pub fn is_shaped(&self, line_idx: usize) -> bool {
+        self.line(line_idx).is_some()
+    }
+
     pub fn line(&self, line_number: usize) -> Option<&WrappedLine> {
-        self.lines.get(line_number)
+        if let Some(idx) = line_number.checked_sub(self.first_line) {
+            if let Some(line) = self.lines.get(idx) {
+                return Some(line);
+            }
+        }
+
+        self.extra_lines
+            .iter()
+            .find(|(idx, _)| *idx == line_number)
+            .map(|(_, line)| line)
     }
 }
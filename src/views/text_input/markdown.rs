@@ -0,0 +1,739 @@
+use std::{collections::HashMap, ops::Range, path::Path};
+
+use ropey::Rope;
+use streaming_iterator::StreamingIterator;
+use tree_sitter::{Node, Parser, QueryCursor, Tree};
+
+use super::{
+    syntax::LanguageConfigManager,
+    syntax_map::{SyntaxLayer, SyntaxMap},
+    text_element::RopeProvider,
+};
+
+/// The blocks of a markdown buffer, read from the block tree and its injected layers.
+pub struct Document {
+    pub blocks: Vec<Block>,
+}
+
+pub struct Block {
+    /// Source lines the block was read from.
+    pub lines: Range<usize>,
+    pub kind: BlockKind,
+}
+
+pub enum BlockKind {
+    Heading {
+        level: usize,
+        content: Vec<Inline>,
+    },
+    Paragraph(Vec<Inline>),
+    /// Ordered lists have the number of their first item.
+    List {
+        start: Option<usize>,
+        items: Vec<ListItem>,
+    },
+    Quote(Vec<Block>),
+    Code(Code),
+    /// Raw html, highlighted like a code block.
+    Html(Code),
+    Table(Table),
+    ThematicBreak,
+}
+
+pub struct ListItem {
+    /// Set for task list items.
+    pub checked: Option<bool>,
+    pub blocks: Vec<Block>,
+}
+
+pub struct Code {
+    pub language: Option<String>,
+    pub text: String,
+    /// Byte ranges into `text` with their capture names, ordered and not overlapping.
+    pub highlights: Vec<(Range<usize>, String)>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Alignment {
+    None,
+    Left,
+    Center,
+    Right,
+}
+
+pub struct Table {
+    pub alignments: Vec<Alignment>,
+    pub header: Vec<Vec<Inline>>,
+    pub rows: Vec<Vec<Vec<Inline>>>,
+}
+
+pub enum Inline {
+    Text(String),
+    Emphasis(Vec<Inline>),
+    Strong(Vec<Inline>),
+    Strikethrough(Vec<Inline>),
+    Code(String),
+    Link { url: String, content: Vec<Inline> },
+    Image { url: String, alt: String },
+    Html(String),
+    LineBreak,
+}
+
+/// An ATX or setext heading, for the outline.
+pub struct Heading {
+    pub level: usize,
+    pub text: String,
+    /// Source line of the heading.
+    pub line: usize,
+}
+
+impl Document {
+    pub fn new(content: &Rope, tree: &Tree, syntax_map: &SyntaxMap) -> Self {
+        let root = tree.root_node();
+        Self {
+            blocks: Reader::new(content, root, syntax_map).blocks(root),
+        }
+    }
+}
+
+/// The headings in source order, including the ones nested in quotes and lists.
+pub fn headings(content: &Rope, tree: &Tree, syntax_map: &SyntaxMap) -> Vec<Heading> {
+    let root = tree.root_node();
+    let mut headings = Vec::new();
+    Reader::new(content, root, syntax_map).push_headings(root, &mut headings);
+    headings
+}
+
+/// Whether the extension of `path` is one of markdown's.
+pub fn is_markdown_path(path: &Path) -> bool {
+    LanguageConfigManager::new().language_id_for_path(path) == Some("markdown")
+}
+
+/// Parses `content` from scratch, for text that isn't open in an editor.
+pub fn parse(content: &Rope) -> Option<Document> {
+    let language_configs = LanguageConfigManager::new();
+    let markdown = language_configs.language_config_for_language_id("markdown")?;
+
+    let mut parser = Parser::new();
+    parser.set_language(&markdown.language).ok()?;
+    let tree = parser.parse_with(
+        &mut |byte, _| {
+            let (chunk, start_byte, _, _) = content.chunk_at_byte(byte);
+            &chunk.as_bytes()[byte - start_byte..]
+        },
+        None,
+    )?;
+
+    let mut syntax_map = SyntaxMap::default();
+    syntax_map.reparse(&tree, content, &markdown, &language_configs);
+    Some(Document::new(content, &tree, &syntax_map))
+}
+
+struct Reader<'a> {
+    content: &'a Rope,
+    /// Injected layers by the start of their range.
+    layers: HashMap<usize, &'a SyntaxLayer>,
+    /// Prefixes of continued lines, like the `> ` of a quote, they aren't part of the text.
+    skipped: Vec<Range<usize>>,
+    /// Link reference definitions by their normalized label.
+    references: HashMap<String, String>,
+}
+
+impl<'a> Reader<'a> {
+    fn new(content: &'a Rope, root: Node, syntax_map: &'a SyntaxMap) -> Self {
+        let mut reader = Self {
+            content,
+            layers: syntax_map
+                .layers_in(0..usize::MAX)
+                .map(|layer| (layer.range.start, layer))
+                .collect(),
+            skipped: Vec::new(),
+            references: HashMap::new(),
+        };
+        reader.collect(root);
+        reader.skipped.sort_by_key(|range| range.start);
+        reader
+    }
+
+    fn collect(&mut self, node: Node) {
+        match node.kind() {
+            "block_continuation" | "block_quote_marker" => self.skipped.push(node.byte_range()),
+            "link_reference_definition" => {
+                let label = child(node, "link_label").map(|label| self.source(label.byte_range()));
+                let url = child(node, "link_destination")
+                    .map(|url| destination(&self.source(url.byte_range())));
+                if let (Some(label), Some(url)) = (label, url) {
+                    self.references
+                        .entry(normalize_label(&label))
+                        .or_insert(url);
+                }
+            }
+            _ => {}
+        }
+
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            self.collect(child);
+        }
+    }
+
+    fn blocks(&self, node: Node) -> Vec<Block> {
+        let mut blocks = Vec::new();
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            self.push_block(child, &mut blocks);
+        }
+        blocks
+    }
+
+    fn push_block(&self, node: Node, blocks: &mut Vec<Block>) {
+        let kind = match node.kind() {
+            // sections only group a heading with what follows it
+            "section" => {
+                blocks.extend(self.blocks(node));
+                return;
+            }
+            "atx_heading" | "setext_heading" => {
+                let (level, content) = self.heading(node);
+                BlockKind::Heading { level, content }
+            }
+            "paragraph" => BlockKind::Paragraph(self.paragraph(node)),
+            "fenced_code_block" => {
+                let language = child(node, "info_string")
+                    .and_then(|info| child(info, "language"))
+                    .map(|language| self.source(language.byte_range()));
+                match child(node, "code_fence_content") {
+                    Some(content) => BlockKind::Code(self.code(content, language)),
+                    None => BlockKind::Code(Code {
+                        language,
+                        text: String::new(),
+                        highlights: Vec::new(),
+                    }),
+                }
+            }
+            "indented_code_block" => {
+                let text = self.text(node.byte_range());
+                let text = text
+                    .trim_end_matches('\n')
+                    .lines()
+                    .map(|line| {
+                        let indent = line.len() - line.trim_start_matches(' ').len();
+                        &line[indent.min(4)..]
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                BlockKind::Code(Code {
+                    language: None,
+                    text,
+                    highlights: Vec::new(),
+                })
+            }
+            "html_block" => BlockKind::Html(self.code(node, Some("html".to_string()))),
+            "block_quote" => BlockKind::Quote(self.blocks(node)),
+            "list" => self.list(node),
+            "pipe_table" => BlockKind::Table(self.table(node)),
+            "thematic_break" => BlockKind::ThematicBreak,
+            _ => return,
+        };
+
+        let end = node.end_position();
+        blocks.push(Block {
+            lines: node.start_position().row..end.row + usize::from(end.column > 0),
+            kind,
+        });
+    }
+
+    fn push_headings(&self, node: Node, headings: &mut Vec<Heading>) {
+        match node.kind() {
+            "atx_heading" | "setext_heading" => {
+                let (level, content) = self.heading(node);
+                headings.push(Heading {
+                    level,
+                    text: plain_text(&content).trim().to_string(),
+                    line: node.start_position().row,
+                });
+            }
+            // headings can't be in these, no need to walk their lines
+            "paragraph"
+            | "fenced_code_block"
+            | "indented_code_block"
+            | "html_block"
+            | "pipe_table" => {}
+            _ => {
+                for child in named_children(node) {
+                    self.push_headings(child, headings);
+                }
+            }
+        }
+    }
+
+    fn heading(&self, node: Node) -> (usize, Vec<Inline>) {
+        if node.kind() == "setext_heading" {
+            let level = if child(node, "setext_h1_underline").is_some() {
+                1
+            } else {
+                2
+            };
+            let content = child(node, "paragraph")
+                .map_or_else(Vec::new, |paragraph| self.paragraph(paragraph));
+            return (level, content);
+        }
+
+        let level = named_children(node)
+            .iter()
+            .find_map(|child| atx_level(child.kind()))
+            .unwrap_or(1);
+        let content = child(node, "inline").map_or_else(Vec::new, |inline| self.inlines(inline));
+        (level, content)
+    }
+
+    fn paragraph(&self, node: Node) -> Vec<Inline> {
+        let mut inlines = Vec::new();
+        for inline in named_children(node) {
+            if inline.kind() == "inline" {
+                if !inlines.is_empty() {
+                    push_text(&mut inlines, " ");
+                }
+                inlines.extend(self.inlines(inline));
+            }
+        }
+        inlines
+    }
+
+    fn list(&self, node: Node) -> BlockKind {
+        let mut start = None;
+        let mut items = Vec::new();
+
+        for item in named_children(node) {
+            if item.kind() != "list_item" {
+                continue;
+            }
+
+            let mut checked = None;
+            for child in named_children(item) {
+                match child.kind() {
+                    "list_marker_dot" | "list_marker_parenthesis" if items.is_empty() => {
+                        let marker = self.source(child.byte_range());
+                        start = marker
+                            .trim()
+                            .trim_end_matches(['.', ')'])
+                            .parse()
+                            .ok()
+                            .or(Some(1));
+                    }
+                    "task_list_marker_checked" => checked = Some(true),
+                    "task_list_marker_unchecked" => checked = Some(false),
+                    _ => {}
+                }
+            }
+
+            items.push(ListItem {
+                checked,
+                blocks: self.blocks(item),
+            });
+        }
+
+        BlockKind::List { start, items }
+    }
+
+    fn table(&self, node: Node) -> Table {
+        let mut table = Table {
+            alignments: Vec::new(),
+            header: Vec::new(),
+            rows: Vec::new(),
+        };
+
+        for row in named_children(node) {
+            match row.kind() {
+                "pipe_table_header" => table.header = self.cells(row),
+                "pipe_table_row" => table.rows.push(self.cells(row)),
+                "pipe_table_delimiter_row" => {
+                    table.alignments = named_children(row)
+                        .into_iter()
+                        .filter(|cell| cell.kind() == "pipe_table_delimiter_cell")
+                        .map(|cell| {
+                            let left = child(cell, "pipe_table_align_left").is_some();
+                            let right = child(cell, "pipe_table_align_right").is_some();
+                            match (left, right) {
+                                (true, true) => Alignment::Center,
+                                (true, false) => Alignment::Left,
+                                (false, true) => Alignment::Right,
+                                (false, false) => Alignment::None,
+                            }
+                        })
+                        .collect();
+                }
+                _ => {}
+            }
+        }
+
+        table
+    }
+
+    fn cells(&self, row: Node) -> Vec<Vec<Inline>> {
+        named_children(row)
+            .into_iter()
+            .filter(|cell| cell.kind() == "pipe_table_cell")
+            .map(|cell| self.inlines(cell))
+            .collect()
+    }
+
+    /// The inline layer injected into `node`, or its plain text when it has none.
+    fn inlines(&self, node: Node) -> Vec<Inline> {
+        let mut inlines = match self.layers.get(&node.start_byte()) {
+            Some(layer) if layer.language.language_id == "markdown_inline" => {
+                self.inline_children(layer.tree.root_node(), node.byte_range())
+            }
+            _ => {
+                let mut inlines = Vec::new();
+                push_text(&mut inlines, &self.text(node.byte_range()));
+                inlines
+            }
+        };
+
+        if let Some(Inline::Text(text)) = inlines.first_mut() {
+            *text = text.trim_start().to_string();
+        }
+        if let Some(Inline::Text(text)) = inlines.last_mut() {
+            *text = text.trim_end().to_string();
+        }
+        inlines.retain(|inline| !matches!(inline, Inline::Text(text) if text.is_empty()));
+        inlines
+    }
+
+    /// The children of `node` inside `range`, with the bytes between them as text.
+    fn inline_children(&self, node: Node, range: Range<usize>) -> Vec<Inline> {
+        let mut inlines = Vec::new();
+        let mut start = range.start;
+
+        for child in named_children(node) {
+            if child.start_byte() < start || child.end_byte() > range.end {
+                continue;
+            }
+
+            let inline = if is_delimiter(child) {
+                None
+            } else {
+                match self.inline(child) {
+                    Some(inline) => Some(inline),
+                    // left in the text, like a shortcut link without a definition
+                    None => continue,
+                }
+            };
+
+            push_text(&mut inlines, &self.text(start..child.start_byte()));
+            inlines.extend(inline);
+            start = child.end_byte();
+        }
+
+        push_text(&mut inlines, &self.text(start..range.end));
+        inlines
+    }
+
+    fn inline(&self, node: Node) -> Option<Inline> {
+        let inline = match node.kind() {
+            "emphasis" => Inline::Emphasis(self.inline_children(node, delimited(node))),
+            "strong_emphasis" => Inline::Strong(self.inline_children(node, delimited(node))),
+            "strikethrough" => Inline::Strikethrough(self.inline_children(node, delimited(node))),
+            "code_span" => {
+                let text = self.text(delimited(node)).replace('\n', " ");
+                // one space on both sides lets a code span start or end with a backtick
+                let text = match text.strip_prefix(' ').and_then(|t| t.strip_suffix(' ')) {
+                    Some(inner) if !inner.trim().is_empty() => inner.to_string(),
+                    _ => text,
+                };
+                Inline::Code(text)
+            }
+            "inline_link" => Inline::Link {
+                url: child(node, "link_destination")
+                    .map(|url| destination(&self.source(url.byte_range())))
+                    .unwrap_or_default(),
+                content: self.link_text(node),
+            },
+            "full_reference_link" => Inline::Link {
+                url: self.reference(child(node, "link_label")?)?,
+                content: self.link_text(node),
+            },
+            "collapsed_reference_link" | "shortcut_link" => Inline::Link {
+                url: self.reference(child(node, "link_text")?)?,
+                content: self.link_text(node),
+            },
+            "uri_autolink" => {
+                let url = self.source(node.byte_range());
+                let url = url
+                    .trim_start_matches('<')
+                    .trim_end_matches('>')
+                    .to_string();
+                Inline::Link {
+                    content: vec![Inline::Text(url.clone())],
+                    url,
+                }
+            }
+            "email_autolink" => {
+                let email = self.source(node.byte_range());
+                let email = email.trim_start_matches('<').trim_end_matches('>');
+                Inline::Link {
+                    url: format!("mailto:{email}"),
+                    content: vec![Inline::Text(email.to_string())],
+                }
+            }
+            "image" => {
+                let url = match child(node, "link_destination") {
+                    Some(url) => destination(&self.source(url.byte_range())),
+                    None => self.reference(child(node, "link_label")?)?,
+                };
+                let alt = child(node, "image_description")
+                    .map(|alt| plain_text(&self.inline_children(alt, alt.byte_range())))
+                    .unwrap_or_default();
+                Inline::Image { url, alt }
+            }
+            "hard_line_break" => Inline::LineBreak,
+            "backslash_escape" => Inline::Text(self.source(node.byte_range())[1..].to_string()),
+            "entity_reference" | "numeric_character_reference" => {
+                let entity = self.source(node.byte_range());
+                Inline::Text(decode_entity(&entity).unwrap_or(entity))
+            }
+            "html_tag" => Inline::Html(self.source(node.byte_range())),
+            _ => return None,
+        };
+        Some(inline)
+    }
+
+    fn link_text(&self, node: Node) -> Vec<Inline> {
+        child(node, "link_text")
+            .map(|text| self.inline_children(text, text.byte_range()))
+            .unwrap_or_default()
+    }
+
+    fn reference(&self, label: Node) -> Option<String> {
+        let label = normalize_label(&self.source(label.byte_range()));
+        self.references.get(&label).cloned()
+    }
+
+    /// The text of `node` with the highlights of the layer injected into it.
+    fn code(&self, node: Node, language: Option<String>) -> Code {
+        let pieces = self.pieces(node.byte_range());
+        let mut text = String::new();
+        let mut offsets = Vec::new();
+        for piece in &pieces {
+            offsets.push(text.len());
+            text.push_str(&self.source(piece.clone()));
+        }
+        if text.ends_with('\n') {
+            text.pop();
+        }
+
+        let mut highlights: Vec<(Range<usize>, String)> = Vec::new();
+        if let Some(layer) = self.layers.get(&node.start_byte()) {
+            let query = &layer.language.highlight_query;
+            let mut cursor = QueryCursor::new();
+            cursor.set_byte_range(node.byte_range());
+            let mut captures = cursor.captures(
+                query,
+                layer.tree.root_node(),
+                RopeProvider(self.content.slice(..)),
+            );
+
+            while let Some((mat, idx)) = captures.next() {
+                let capture = mat.captures[*idx];
+                let Some(name) = query.capture_names().get(capture.index as usize) else {
+                    continue;
+                };
+                for (piece, offset) in pieces.iter().zip(&offsets) {
+                    let start = capture.node.start_byte().max(piece.start);
+                    let end = capture.node.end_byte().min(piece.end);
+                    if start >= end {
+                        continue;
+                    }
+
+                    // the first capture wins, like in the editor
+                    let range = (offset + start - piece.start)..(offset + end - piece.start);
+                    let range = range.start..range.end.min(text.len());
+                    let last_end = highlights.last().map_or(0, |(range, _)| range.end);
+                    if range.start >= last_end && !range.is_empty() {
+                        highlights.push((range, name.to_string()));
+                    }
+                }
+            }
+        }
+
+        Code {
+            language,
+            text,
+            highlights,
+        }
+    }
+
+    fn source(&self, range: Range<usize>) -> String {
+        self.content.byte_slice(range).to_string()
+    }
+
+    /// `range` without the skipped line prefixes.
+    fn pieces(&self, range: Range<usize>) -> Vec<Range<usize>> {
+        let mut pieces = Vec::new();
+        let mut start = range.start;
+        let first = self
+            .skipped
+            .partition_point(|skipped| skipped.end <= range.start);
+        for skipped in &self.skipped[first..] {
+            if skipped.start >= range.end {
+                break;
+            }
+            if skipped.start > start {
+                pieces.push(start..skipped.start);
+            }
+            start = start.max(skipped.end);
+        }
+        if start < range.end {
+            pieces.push(start..range.end);
+        }
+        pieces
+    }
+
+    fn text(&self, range: Range<usize>) -> String {
+        self.pieces(range)
+            .into_iter()
+            .map(|piece| self.source(piece))
+            .collect()
+    }
+}
+
+fn child<'a>(node: Node<'a>, kind: &str) -> Option<Node<'a>> {
+    named_children(node)
+        .into_iter()
+        .find(|child| child.kind() == kind)
+}
+
+fn named_children(node: Node) -> Vec<Node> {
+    let mut cursor = node.walk();
+    node.named_children(&mut cursor).collect()
+}
+
+fn atx_level(kind: &str) -> Option<usize> {
+    kind.strip_prefix("atx_h")?
+        .strip_suffix("_marker")?
+        .parse()
+        .ok()
+}
+
+fn is_delimiter(node: Node) -> bool {
+    matches!(
+        node.kind(),
+        "emphasis_delimiter" | "code_span_delimiter" | "~"
+    )
+}
+
+/// The part of an emphasis or code span between its delimiters.
+fn delimited(node: Node) -> Range<usize> {
+    let mut cursor = node.walk();
+    let children: Vec<Node> = node.children(&mut cursor).collect();
+
+    let mut start = node.start_byte();
+    for child in &children {
+        if !is_delimiter(*child) || child.start_byte() != start {
+            break;
+        }
+        start = child.end_byte();
+    }
+
+    let mut end = node.end_byte();
+    for child in children.iter().rev() {
+        if !is_delimiter(*child) || child.end_byte() != end || child.start_byte() < start {
+            break;
+        }
+        end = child.start_byte();
+    }
+
+    start..end.max(start)
+}
+
+/// Soft line breaks become a space, like in a browser.
+fn push_text(inlines: &mut Vec<Inline>, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+
+    let mut joined = String::new();
+    for (idx, line) in text.split('\n').enumerate() {
+        if idx > 0 {
+            joined.truncate(joined.trim_end().len());
+            joined.push(' ');
+            joined.push_str(line.trim_start());
+        } else {
+            joined.push_str(line);
+        }
+    }
+
+    match inlines.last_mut() {
+        Some(Inline::Text(last)) => last.push_str(&joined),
+        _ => inlines.push(Inline::Text(joined)),
+    }
+}
+
+/// The text of `inlines` without formatting, for image descriptions and anchors.
+pub fn plain_text(inlines: &[Inline]) -> String {
+    let mut text = String::new();
+    for inline in inlines {
+        match inline {
+            Inline::Text(t) | Inline::Code(t) => text.push_str(t),
+            Inline::Emphasis(content)
+            | Inline::Strong(content)
+            | Inline::Strikethrough(content)
+            | Inline::Link { content, .. } => text.push_str(&plain_text(content)),
+            Inline::Image { alt, .. } => text.push_str(alt),
+            Inline::LineBreak => text.push(' '),
+            Inline::Html(_) => {}
+        }
+    }
+    text
+}
+
+/// `<url>` or `url "title"` as written after a link, just the url.
+fn destination(text: &str) -> String {
+    let text = text.trim();
+    match text
+        .strip_prefix('<')
+        .and_then(|text| text.strip_suffix('>'))
+    {
+        Some(url) => url.to_string(),
+        None => text.to_string(),
+    }
+}
+
+/// Labels match case-insensitively with their whitespace collapsed.
+fn normalize_label(label: &str) -> String {
+    label
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+fn decode_entity(entity: &str) -> Option<String> {
+    let name = entity.strip_prefix('&')?.strip_suffix(';')?;
+    let c = match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        "hellip" => '…',
+        "mdash" => '—',
+        "ndash" => '–',
+        _ => {
+            let number = name.strip_prefix('#')?;
+            let code = match number.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => number.parse().ok()?,
+            };
+            char::from_u32(code)?
+        }
+    };
+    Some(c.to_string())
+}
@@ -30,6 +30,11 @@ impl ScrollManager {
         }
     }
 
+    /// `lines` is the number of lines kept visible around the cursor.
+    pub fn set_padding_vertical(&mut self, lines: f32) {
+        self.padding_vertical = px(lines);
+    }
+
     fn next_show_epoch(&mut self) -> usize {
         self.show_epoch += 1;
         self.show_epoch
@@ -168,6 +173,13 @@ impl ScrollManager {
         cx.notify();
     }
 
+    /// Puts the view back to an earlier `offset`, pending moves are dropped.
+    pub fn set_offset(&mut self, offset: Point<Pixels>, cx: &mut ModelContext<Self>) {
+        self.next_calc_epoch();
+        self.offset = offset;
+        cx.notify();
+    }
+
     pub fn bounds(&self, bounds: &Bounds<Pixels>) -> Bounds<Pixels> {
         Bounds::new(
             point(bounds.right() - self.width, bounds.top()),
@@ -0,0 +1,60 @@
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Selection {
+    pub range: Range<usize>,
+    pub reversed: bool,
+}
+
+impl Selection {
+    pub fn new(range: Range<usize>, reversed: bool) -> Self {
+        Self { range, reversed }
+    }
+
+    pub fn cursor(offset: usize) -> Self {
+        Self::new(offset..offset, false)
+    }
+
+    pub fn head(&self) -> usize {
+        if self.reversed {
+            self.range.start
+        } else {
+            self.range.end
+        }
+    }
+
+    pub fn shift(&mut self, delta: isize) {
+        self.range.start = (self.range.start as isize + delta).max(0) as usize;
+        self.range.end = (self.range.end as isize + delta).max(0) as usize;
+    }
+}
+
+/// Sorts the selections and merges the ones that overlap or share a start.
+/// Returns the merged list and the new index of the selection at `primary_idx`.
+pub fn merge_selections(selections: Vec<Selection>, primary_idx: usize) -> (Vec<Selection>, usize) {
+    let mut indexed: Vec<(usize, Selection)> = selections.into_iter().enumerate().collect();
+    indexed.sort_by_key(|(_, selection)| (selection.range.start, selection.range.end));
+
+    let mut merged: Vec<Selection> = Vec::with_capacity(indexed.len());
+    let mut new_primary_idx = 0;
+
+    for (idx, selection) in indexed {
+        match merged.last_mut() {
+            Some(last)
+                if selection.range.start < last.range.end
+                    || selection.range.start == last.range.start =>
+            {
+                last.range.end = last.range.end.max(selection.range.end);
+            }
+            _ => merged.push(selection),
+        }
+
+        if idx == primary_idx {
+            new_primary_idx = merged.len() - 1;
+        }
+    }
+
+    (merged, new_primary_idx)
+}
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{path::Path, sync::Arc};
 use tree_sitter::{Language, Query};
 
 #[allow(dead_code)]
@@ -29,6 +29,7 @@ impl LanguageConfig {
     }
 }
 
+#[derive(Clone)]
 pub struct LanguageConfigManager {
     language_configs: Vec<Arc<LanguageConfig>>,
 }
@@ -97,4 +98,13 @@ impl LanguageConfigManager {
             .find(|lang| lang.language_id == id)
             .cloned()
     }
+
+    /// The language picked by the file's extension, used to look up language settings.
+    pub fn language_id_for_path(&self, path: &Path) -> Option<&'static str> {
+        let extension = path.extension()?.to_str()?;
+        self.language_configs
+            .iter()
+            .find(|lang| lang.file_types.contains(&extension))
+            .map(|lang| lang.language_id)
+    }
 }
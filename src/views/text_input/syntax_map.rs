@@ -0,0 +1,177 @@
+use std::{collections::HashMap, ops::Range, sync::Arc};
+
+use ropey::{Rope, RopeSlice};
+use streaming_iterator::StreamingIterator;
+use tree_sitter::{InputEdit, Node, Parser, Query, QueryCursor, QueryMatch, Tree};
+
+use super::{
+    syntax::{LanguageConfig, LanguageConfigManager},
+    text_element::RopeProvider,
+};
+
+/// The tree of one injected region, e.g. a fenced code block. It is parsed from the whole
+/// document restricted to its range, so it shares byte and point coordinates with the root tree.
+#[derive(Clone)]
+pub struct SyntaxLayer {
+    pub language: Arc<LanguageConfig>,
+    pub range: Range<usize>,
+    pub tree: Tree,
+    /// An edit touched the range, the tree has to be parsed again before it can be reused.
+    is_stale: bool,
+}
+
+/// All injection layers of a buffer, sorted by their start.
+#[derive(Clone, Default)]
+pub struct SyntaxMap {
+    layers: Vec<SyntaxLayer>,
+}
+
+impl SyntaxMap {
+    pub fn layers_in(&self, range: Range<usize>) -> impl Iterator<Item = &SyntaxLayer> {
+        self.layers
+            .iter()
+            .filter(move |layer| layer.range.start < range.end && layer.range.end > range.start)
+    }
+
+    pub fn clear(&mut self) {
+        self.layers.clear();
+    }
+
+    /// Applies the edit that was sent to the root tree to the layers after and around it.
+    pub fn edit(&mut self, edit: &InputEdit) {
+        for layer in &mut self.layers {
+            if edit.start_byte > layer.range.end {
+                continue;
+            }
+
+            if edit.old_end_byte < layer.range.start {
+                layer.range.start = layer.range.start - edit.old_end_byte + edit.new_end_byte;
+                layer.range.end = layer.range.end - edit.old_end_byte + edit.new_end_byte;
+            } else {
+                layer.is_stale = true;
+            }
+            layer.tree.edit(edit);
+        }
+    }
+
+    /// Looks up the injections of `root` again. Layers that kept their language and range
+    /// are reused, touched ones are reparsed incrementally and only new ones from scratch.
+    pub fn reparse(
+        &mut self,
+        root: &Tree,
+        content: &Rope,
+        language: &LanguageConfig,
+        language_configs: &LanguageConfigManager,
+    ) {
+        let Some(query) = language.injection_query.as_ref() else {
+            self.layers.clear();
+            return;
+        };
+
+        let mut old_layers: HashMap<(&'static str, usize), SyntaxLayer> = self
+            .layers
+            .drain(..)
+            .map(|layer| ((layer.language.language_id, layer.range.start), layer))
+            .collect();
+
+        let mut parser = Parser::new();
+        for (config, range) in injections(query, root, content.slice(..), language_configs) {
+            let byte_range = range.start_byte..range.end_byte;
+            let old_tree = match old_layers.remove(&(config.language_id, range.start_byte)) {
+                Some(layer) if !layer.is_stale && layer.range == byte_range => {
+                    self.layers.push(layer);
+                    continue;
+                }
+                old_layer => old_layer.map(|layer| layer.tree),
+            };
+
+            if parser.set_language(&config.language).is_err()
+                || parser.set_included_ranges(&[range]).is_err()
+            {
+                continue;
+            }
+
+            let Some(tree) = parser.parse_with(
+                &mut |byte, _| {
+                    let (chunk, start_byte, _, _) = content.chunk_at_byte(byte);
+                    &chunk.as_bytes()[byte - start_byte..]
+                },
+                old_tree.as_ref(),
+            ) else {
+                continue;
+            };
+
+            self.layers.push(SyntaxLayer {
+                language: config,
+                range: byte_range,
+                tree,
+                is_stale: false,
+            });
+        }
+
+        self.layers.sort_by_key(|layer| layer.range.start);
+    }
+}
+
+fn injections(
+    query: &Query,
+    root: &Tree,
+    source: RopeSlice,
+    language_configs: &LanguageConfigManager,
+) -> Vec<(Arc<LanguageConfig>, tree_sitter::Range)> {
+    let mut cursor = QueryCursor::new();
+    let mut matches = cursor.matches(query, root.root_node(), RopeProvider(source));
+
+    let mut injections = Vec::new();
+
+    while let Some(mat) = matches.next() {
+        let (mut injection_capture, content_node) = injection_pair(query, mat, &source);
+
+        if injection_capture.is_none() {
+            for prop in query.property_settings(mat.pattern_index) {
+                if prop.key.as_ref() == "injection.language" {
+                    injection_capture = prop.value.as_ref().map(|s| s.as_ref());
+                }
+            }
+        }
+
+        if let (Some(injection_capture), Some(content_node)) = (injection_capture, content_node) {
+            // fences in languages without a grammar stay plain text
+            if let Some(config) =
+                language_configs.language_config_for_language_id(injection_capture)
+            {
+                injections.push((config, content_node.range()));
+            }
+        }
+    }
+
+    injections
+}
+
+fn injection_pair<'a>(
+    query: &Query,
+    query_match: &QueryMatch<'a, 'a>,
+    source: &RopeSlice<'a>,
+) -> (Option<&'a str>, Option<Node<'a>>) {
+    let mut injection_capture = None;
+    let mut content_node = None;
+
+    for cap in query_match.captures {
+        // todo: this should be part of some HighlighConfig thingy and done on init, since it does not change
+        if let Some(capture_name) = query.capture_names().get(cap.index as usize) {
+            match *capture_name {
+                "injection.language" => {
+                    if let Some(name) = source.byte_slice(cap.node.byte_range()).as_str() {
+                        injection_capture = Some(name);
+                    }
+                }
+                "injection.content" => {
+                    content_node = Some(cap.node);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    (injection_capture, content_node)
+}
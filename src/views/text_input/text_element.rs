@@ -2,17 +2,15 @@ use gpui::*;
 use ropey::{Rope, RopeSlice};
 use smallvec::SmallVec;
 use std::ops::Range;
-use std::sync::Arc;
 use streaming_iterator::StreamingIterator;
-use tree_sitter::{Node, Parser, Query, QueryCursor, QueryMatch, TextProvider};
+use tree_sitter::{Node, QueryCursor, TextProvider, Tree};
 
-use crate::theme_manager::ActiveTheme;
+use crate::theme_manager::{ActiveTheme, SyntaxTheme};
 
-use super::{
-    lines::Lines,
-    syntax::{LanguageConfig, LanguageConfigManager},
-    text_input::TextInput,
-};
+use super::{lines::Lines, syntax_map::SyntaxLayer, text_input::TextInput};
+
+/// Lines shaped above and below the viewport, so scrolling a bit never shows a gap.
+const OVERSCAN_LINES: usize = 2;
 
 pub struct TextElement {
     input: View<TextInput>,
@@ -181,76 +179,86 @@ impl TextElement {
         Some(selection_quads)
     }
 
-    fn injection_pair<'a>(
+    /// Runs for the bytes in `visible`, only the syntax nodes overlapping it are looked at.
+    fn highlight_runs(
         &self,
-        query: &Query,
-        query_match: &QueryMatch<'a, 'a>,
-        source: &RopeSlice<'a>,
-    ) -> (Option<&'a str>, Option<Node<'a>>) {
-        let mut injection_capture = None;
-        let mut content_node = None;
-
-        for cap in query_match.captures {
-            // todo: this should be part of some HighlighConfig thingy and done on init, since it does not change
-            if let Some(capture_name) = query.capture_names().get(cap.index as usize) {
-                match *capture_name {
-                    "injection.language" => {
-                        if let Some(name) = source.byte_slice(cap.node.byte_range()).as_str() {
-                            injection_capture = Some(name);
-                        }
-                    }
-                    "injection.content" => {
-                        content_node = Some(cap.node);
-                    }
-                    _ => {}
-                }
-            }
-        }
-
-        (injection_capture, content_node)
-    }
-
-    fn query_tree(
-        &self,
-        query: &Query,
-        tree: &tree_sitter::Tree,
-        _base_run: &TextRun,
-        rope_slice: RopeSlice,
-        lang_config: &LanguageConfigManager,
-    ) -> Vec<(Arc<LanguageConfig>, Range<usize>)> {
-        let text = RopeProvider(rope_slice);
+        input: &TextInput,
+        tree: &Tree,
+        visible: Range<usize>,
+        run: &TextRun,
+        syntax: &SyntaxTheme,
+    ) -> Vec<TextRun> {
+        let display_text = &input.content;
+        let mut builder = RunBuilder::new(visible.clone(), run);
+
+        let markdown = input
+            .language_configs
+            .language_config_for_language_id("markdown")
+            .expect("Markdown should always be there");
+
+        let layers: Vec<&SyntaxLayer> = input.syntax_map.layers_in(visible.clone()).collect();
+        let mut injected = vec![false; layers.len()];
 
         let mut cursor = QueryCursor::new();
-        let mut matches = cursor.matches(query, tree.root_node(), text);
-
-        let mut injections = Vec::new();
-
-        while let Some(mat) = matches.next() {
-            let (mut injection_capture, content_node) =
-                self.injection_pair(query, mat, &rope_slice);
+        cursor.set_byte_range(visible.clone());
+        let mut captures = cursor.captures(
+            &markdown.highlight_query,
+            tree.root_node(),
+            RopeProvider(display_text.slice(..)),
+        );
 
-            if injection_capture.is_none() {
-                for prop in query.property_settings(mat.pattern_index) {
-                    if prop.key.as_ref() == "injection.language" {
-                        injection_capture = prop.value.as_ref().map(|s| s.as_ref());
+        while let Some((mat, _)) = captures.next() {
+            for cap in mat.captures {
+                for (idx, layer) in layers.iter().enumerate() {
+                    if injected[idx] || layer.range.start > cap.node.start_byte() {
+                        continue;
                     }
+                    injected[idx] = true;
+
+                    let config = &layer.language;
+                    let mut cursor = QueryCursor::new();
+                    cursor.set_byte_range(visible.clone());
+                    let mut captures = cursor.captures(
+                        &config.highlight_query,
+                        layer.tree.root_node(),
+                        RopeProvider(display_text.slice(..)),
+                    );
+
+                    while let Some((mat, _)) = captures.next() {
+                        for cap in mat.captures {
+                            if let Some(r) = self.highlight_node(
+                                cap.node,
+                                config
+                                    .highlight_query
+                                    .capture_names()
+                                    .get(cap.index as usize)
+                                    .copied(),
+                                run,
+                                syntax,
+                            ) {
+                                builder.push(cap.node.byte_range(), r);
+                            }
+                        }
+                    }
+                    builder.fill_to(layer.range.end);
                 }
-            }
 
-            if let (Some(injection_capture), Some(content_node)) = (injection_capture, content_node)
-            {
-                if let Some(lang_config) =
-                    lang_config.language_config_for_language_id(injection_capture)
-                {
-                    let r = content_node.range().start_byte..content_node.range().end_byte;
-                    injections.push((lang_config, r));
-                } else {
-                    println!("missing language: {injection_capture}");
+                if let Some(r) = self.highlight_node(
+                    cap.node,
+                    markdown
+                        .highlight_query
+                        .capture_names()
+                        .get(cap.index as usize)
+                        .copied(),
+                    run,
+                    syntax,
+                ) {
+                    builder.push(cap.node.byte_range(), r);
                 }
             }
         }
 
-        injections
+        builder.finish()
     }
 
     fn highlight_node(
@@ -258,48 +266,95 @@ impl TextElement {
         node: Node<'_>,
         name: Option<&str>,
         base_run: &TextRun,
+        syntax: &SyntaxTheme,
     ) -> Option<TextRun> {
-        let name = name?;
-        let base_run = TextRun {
-            len: node.end_byte() - node.start_byte(),
-            ..base_run.clone()
-        };
+        let style = syntax.style_for(name?)?;
+        let color = style.color.map_or(base_run.color, Hsla::from);
 
-        match name {
-            "text.strong" | "punctuation.special" => Some(TextRun {
-                font: base_run.font.bold(),
-                ..base_run
-            }),
-            "text.title" => Some(TextRun {
-                font: base_run.font.bold(),
-                color: hsla(0., 0., 0.8, 1.0),
-                ..base_run
-            }),
-            "text.emphasis" => Some(TextRun {
-                font: base_run.font.italic(),
-                ..base_run
-            }),
-            "punctuation.delimiter" | "punctuation.bracket" => Some(TextRun {
-                color: hsla(0., 0., 0.4, 1.0),
-                ..base_run
-            }),
-            "property" | "tag" | "text.literal" | "text.uri" | "text.reference" => Some(TextRun {
-                color: hsla(0.06, 0.92, 0.75, 1.0),
-                ..base_run
-            }),
-            "number" => Some(TextRun {
-                color: hsla(0.97, 0.65, 0.77, 1.0),
-                ..base_run
+        let mut font = base_run.font.clone();
+        if let Some(weight) = style.font_weight {
+            font.weight = weight;
+        }
+        if style.italic {
+            font.style = FontStyle::Italic;
+        }
+
+        Some(TextRun {
+            len: node.end_byte() - node.start_byte(),
+            font,
+            color,
+            underline: style.underline.then_some(UnderlineStyle {
+                color: Some(color),
+                thickness: px(1.),
+                wavy: false,
             }),
-            "boolean" => Some(TextRun {
-                color: hsla(1., 0.92, 0.75, 1.0),
-                ..base_run
+            strikethrough: style.strikethrough.then_some(StrikethroughStyle {
+                color: Some(color),
+                thickness: px(1.),
             }),
-            _ => {
-                println!("missing: {name} in {node:?}");
-                None
-            }
+            ..base_run.clone()
+        })
+    }
+}
+
+/// Turns captures into runs covering exactly one byte range, unstyled bytes get the base run.
+struct RunBuilder {
+    runs: Vec<TextRun>,
+    base_run: TextRun,
+    last_end: usize,
+    end: usize,
+}
+
+impl RunBuilder {
+    fn new(range: Range<usize>, base_run: &TextRun) -> Self {
+        Self {
+            runs: Vec::new(),
+            base_run: base_run.clone(),
+            last_end: range.start,
+            end: range.end,
+        }
+    }
+
+    /// The first capture wins, a later one starting inside an earlier one is dropped.
+    fn push(&mut self, range: Range<usize>, run: TextRun) {
+        if range.start < self.last_end && !self.runs.is_empty() {
+            return;
+        }
+
+        let start = range.start.max(self.last_end);
+        let end = range.end.min(self.end);
+        if start >= end {
+            return;
+        }
+
+        self.fill_to(start);
+        self.runs.push(TextRun {
+            len: end - start,
+            ..run
+        });
+        self.last_end = end;
+    }
+
+    fn fill_to(&mut self, end: usize) {
+        let end = end.min(self.end);
+        if end > self.last_end {
+            self.runs.push(TextRun {
+                len: end - self.last_end,
+                ..self.base_run.clone()
+            });
+            self.last_end = end;
+        }
+    }
+
+    fn finish(mut self) -> Vec<TextRun> {
+        self.fill_to(self.end);
+        if self.runs.is_empty() {
+            self.runs.push(TextRun {
+                len: 0,
+                ..self.base_run
+            });
         }
+        self.runs
     }
 }
 
@@ -307,7 +362,7 @@ pub struct PrepaintState {
     offset: Point<Pixels>,
     bounds: Bounds<Pixels>,
     lines: Option<Lines>,
-    cursor: Option<PaintQuad>,
+    cursors: Vec<PaintQuad>,
     selections: Option<Vec<PaintQuad>>,
     highlights: Option<Vec<PaintQuad>>,
     scroll_bar: Option<SmallVec<[PaintQuad; 2]>>,
@@ -370,12 +425,14 @@ impl Element for TextElement {
         (): &mut Self::RequestLayoutState,
         cx: &mut WindowContext,
     ) -> Self::PrepaintState {
-        let input = self.input.read(cx);
-        let selected_range = input.selected_range.clone();
-        let cursor = input.cursor_offset();
         let style = cx.text_style();
         let text_color = style.color;
-        let display_text = input.content.clone();
+        let font_size = style.font_size.to_pixels(cx.rem_size());
+        let line_height = cx.line_height();
+        let em_width = cx
+            .text_system()
+            .em_advance(cx.text_system().resolve_font(&style.font()), font_size)
+            .unwrap_or(font_size);
 
         let padding = px(8.);
         let new_bounds = Bounds::new(
@@ -386,8 +443,37 @@ impl Element for TextElement {
             ),
         );
 
+        let soft_wrap_enabled = self.input.read(cx).soft_wrap_enabled();
+        let wrap_width = soft_wrap_enabled.then_some(new_bounds.size.width);
+
+        let tops = self.input.update(cx, |input, _| {
+            input
+                .line_heights
+                .sync(&input.content, wrap_width, line_height, em_width);
+            input.line_heights.tops()
+        });
+
+        let input = self.input.read(cx);
+        let display_text = input.content.clone();
+        let cursor = input.cursor_offset();
+        let offset = input.scroll_manager.read(cx).offset(soft_wrap_enabled);
+
+        let index = Lines::new(SmallVec::new(), 0, Vec::new(), tops, line_height);
+        let visible = index.line_range_for_y(-offset.y, -offset.y + new_bounds.size.height);
+        let visible = visible.start.saturating_sub(OVERSCAN_LINES)
+            ..(visible.end + OVERSCAN_LINES).min(display_text.len_lines());
+
+        let start_char = display_text.line_to_char(visible.start);
+        let end_char = if visible.end < display_text.len_lines() {
+            display_text.line_to_char(visible.end) - 1
+        } else {
+            display_text.len_chars()
+        };
+        let start_byte = display_text.char_to_byte(start_char);
+        let end_byte = display_text.char_to_byte(end_char);
+
         let run = TextRun {
-            len: input.content.len_bytes(),
+            len: end_byte - start_byte,
             font: style.font(),
             color: text_color,
             background_color: None,
@@ -395,136 +481,11 @@ impl Element for TextElement {
             strikethrough: None,
         };
 
-        let runs = if input.parse_tree.is_some() {
-            let mut runs = vec![];
-            let tree = input.parse_tree.clone().unwrap();
-
-            let markdown = input
-                .language_configs
-                .language_config_for_language_id("markdown")
-                .expect("Markdown should always be there");
-
-            let md_query = markdown.injection_query.as_ref().unwrap();
-            let configs = self.query_tree(
-                md_query,
-                &tree,
-                &run,
-                display_text.slice(..),
-                &input.language_configs,
-            );
-
-            let mut cursor = QueryCursor::new();
-            let mut captures = cursor.captures(
-                &markdown.highlight_query,
-                tree.root_node(),
-                RopeProvider(display_text.slice(..)),
-            );
-
-            let mut last_end: usize = 0;
-
-            while let Some((mat, _)) = captures.next() {
-                for cap in mat.captures {
-                    for (config, range) in &configs {
-                        let node_start = cap.node.start_byte();
-                        if node_start < range.start || last_end > range.start {
-                            continue;
-                        }
-
-                        let mut parser = Parser::new();
-                        parser
-                            .set_language(&config.language)
-                            .expect("Error Loading Grammar");
-
-                        let text = display_text.slice(range.clone());
-                        let Some(parse_tree) = parser.parse_with(
-                            &mut |byte, _| {
-                                let (chunk, start_byte, _, _) = text.chunk_at_byte(byte);
-                                &chunk.as_bytes()[byte - start_byte..]
-                            },
-                            None,
-                        ) else {
-                            continue;
-                        };
-
-                        let mut cursor = QueryCursor::new();
-                        let mut captures = cursor.captures(
-                            &config.highlight_query,
-                            parse_tree.root_node(),
-                            RopeProvider(text),
-                        );
-
-                        while let Some((mat, _)) = captures.next() {
-                            for cap in mat.captures {
-                                if range.start + cap.node.start_byte() < last_end {
-                                    continue;
-                                }
-
-                                if let Some(r) = self.highlight_node(
-                                    cap.node,
-                                    config
-                                        .highlight_query
-                                        .capture_names()
-                                        .get(cap.index as usize)
-                                        .copied(),
-                                    &run,
-                                ) {
-                                    if range.start + cap.node.start_byte() > last_end {
-                                        runs.push(TextRun {
-                                            len: range.start + cap.node.start_byte() - last_end,
-                                            ..run.clone()
-                                        });
-                                    }
-
-                                    runs.push(r);
-                                    last_end = range.start + cap.node.end_byte();
-                                }
-                            }
-                        }
-                        if last_end < range.end {
-                            runs.push(TextRun {
-                                len: range.end - last_end,
-                                ..run.clone()
-                            });
-                            last_end = range.end;
-                        }
-                    }
-
-                    if last_end > cap.node.start_byte() {
-                        continue;
-                    }
-
-                    if let Some(r) = self.highlight_node(
-                        cap.node,
-                        markdown
-                            .highlight_query
-                            .capture_names()
-                            .get(cap.index as usize)
-                            .copied(),
-                        &run,
-                    ) {
-                        if cap.node.start_byte() > last_end {
-                            runs.push(TextRun {
-                                len: cap.node.start_byte() - last_end,
-                                ..run.clone()
-                            });
-                        }
-
-                        runs.push(r);
-                        last_end = cap.node.end_byte();
-                    }
-                }
-            }
-
-            if last_end < display_text.len_bytes() {
-                runs.push(TextRun {
-                    len: display_text.len_bytes() - last_end,
-                    ..run.clone()
-                });
+        let runs = match input.parse_tree.as_ref() {
+            Some(tree) => {
+                self.highlight_runs(input, tree, start_byte..end_byte, &run, &cx.theme().syntax)
             }
-
-            runs
-        } else {
-            vec![run]
+            None => vec![run.clone()],
         };
 
         // let runs = if let Some(marked_range) = input.marked_range.as_ref() {
@@ -554,68 +515,103 @@ impl Element for TextElement {
         //     vec![run]
         // };
 
-        let font_size = style.font_size.to_pixels(cx.rem_size());
-        let text: SharedString = display_text.to_string().into();
+        let text: SharedString = display_text.slice(start_char..end_char).to_string().into();
+        let lines_raw = if visible.is_empty() {
+            SmallVec::new()
+        } else {
+            cx.text_system()
+                .shape_text(text, font_size, &runs, wrap_width)
+                .unwrap()
+        };
 
-        let lines_raw = cx
-            .text_system()
-            .shape_text(
-                text.clone(),
-                font_size,
-                &runs,
-                if input.soft_wrap_enabled() {
-                    Some(new_bounds.size.width)
-                } else {
-                    None
-                },
-            )
-            .unwrap();
+        // the lines next to the cursor are needed to move it up and down while scrolled away
+        let cursor_line = display_text.char_to_line(cursor);
+        let extra_lines: Vec<(usize, WrappedLine)> = (cursor_line.saturating_sub(1)
+            ..(cursor_line + 2).min(display_text.len_lines()))
+            .filter(|line_idx| !visible.contains(line_idx))
+            .filter_map(|line_idx| {
+                let text = display_text.line(line_idx).to_string();
+                let text: SharedString = text.trim_end_matches('\n').to_string().into();
+                let runs = [TextRun {
+                    len: text.len(),
+                    ..run.clone()
+                }];
+                let line = cx
+                    .text_system()
+                    .shape_text(text, font_size, &runs, wrap_width)
+                    .ok()?
+                    .into_iter()
+                    .next()?;
+                Some((line_idx, line))
+            })
+            .collect();
 
-        let line_height = cx.line_height();
-        let lines = Lines::new(lines_raw, line_height);
+        let tops = self.input.update(cx, |input, _| {
+            input.line_heights.measured(visible.start, &lines_raw);
+            for (line_idx, line) in &extra_lines {
+                input
+                    .line_heights
+                    .measured(*line_idx, std::slice::from_ref(line));
+            }
+            input.line_heights.tops()
+        });
+
+        let lines = Lines::new(lines_raw, visible.start, extra_lines, tops, line_height);
 
-        let line_idx = display_text.char_to_line(cursor);
-        let line_byte_idx = display_text.line_to_byte(line_idx);
-        let cursor_byte_idx = display_text.char_to_byte(cursor);
-        let cursor_pos =
-            lines.position_for_byte_idx_in_line(cursor_byte_idx - line_byte_idx, line_idx);
+        let input = self.input.read(cx);
+        let all_selections = input.all_selections();
+        let is_visible = |offset: usize| offset >= start_char && offset <= end_char;
+        let clamp_to_visible =
+            |range: &Range<usize>| range.start.max(start_char)..range.end.min(end_char);
+
+        let cursor_pos = lines.position_for_char_idx(cursor, &display_text);
 
         let scroll_manager = input.scroll_manager.read(cx);
-        let offset = scroll_manager.offset(input.soft_wrap_enabled());
         let scroll_bar = input.scroll_manager.read_with(cx, |this, cx| {
             this.paint_bar(&bounds, lines.height(), cursor_pos, cx)
         });
 
-        let paint_cursor = if input.blink_manager.read(cx).show() {
-            Some(fill(
-                Bounds::new(
-                    point(
-                        new_bounds.left() + cursor_pos.x,
-                        new_bounds.top() + cursor_pos.y,
-                    ),
-                    size(px(2.), line_height),
-                ),
-                cx.theme().cursor,
-            ))
+        let paint_cursors = if input.blink_manager.read(cx).show() {
+            all_selections
+                .iter()
+                .filter(|selection| is_visible(selection.head()))
+                .map(|selection| {
+                    let pos = lines.position_for_char_idx(selection.head(), &display_text);
+                    fill(
+                        Bounds::new(
+                            point(new_bounds.left() + pos.x, new_bounds.top() + pos.y),
+                            size(px(2.), line_height),
+                        ),
+                        cx.theme().cursor,
+                    )
+                })
+                .collect()
         } else {
-            None
+            Vec::new()
         };
 
         let selection_color = cx.theme().selection_bg;
-        let selections = self.paint_range(
-            &selected_range,
-            selection_color,
-            &display_text,
-            &lines,
-            &new_bounds,
-        );
+        let selections: Vec<PaintQuad> = all_selections
+            .iter()
+            .filter_map(|selection| {
+                self.paint_range(
+                    &clamp_to_visible(&selection.range),
+                    selection_color,
+                    &display_text,
+                    &lines,
+                    &new_bounds,
+                )
+            })
+            .flatten()
+            .collect();
 
         let highlights: Vec<PaintQuad> = input
             .highlights
             .iter()
+            .filter(|range| range.end >= start_byte && range.start <= end_byte)
             .filter_map(|range| {
                 self.paint_range(
-                    &self.convert_byte_range_to_char_range(range, &display_text),
+                    &clamp_to_visible(&self.convert_byte_range_to_char_range(range, &display_text)),
                     rgb(0x000000),
                     &display_text,
                     &lines,
@@ -629,8 +625,8 @@ impl Element for TextElement {
             offset,
             bounds: new_bounds,
             lines: Some(lines),
-            cursor: paint_cursor,
-            selections,
+            cursors: paint_cursors,
+            selections: Some(selections),
             highlights: Some(highlights),
             scroll_bar,
             scroll_bar_hitbox: cx.insert_hitbox(scroll_manager.bounds(&bounds), false),
@@ -673,7 +669,7 @@ impl Element for TextElement {
             }
 
             let line_height = cx.line_height();
-            let mut offset_y = prepaint.offset.y;
+            let mut offset_y = prepaint.offset.y + lines.height_till_line_idx(lines.first_line);
 
             for line in &lines.lines {
                 let size = line.size(line_height);
@@ -689,7 +685,7 @@ impl Element for TextElement {
                 offset_y += size.height;
             }
 
-            if let Some(cursor) = prepaint.cursor.take() {
+            for cursor in prepaint.cursors.drain(..) {
                 let mut cursor = cursor.clone();
                 cursor.bounds.origin.x = prepaint.offset.x + cursor.bounds.origin.x;
                 cursor.bounds.origin.y = prepaint.offset.y + cursor.bounds.origin.y;
@@ -3,17 +3,23 @@ use prelude::FluentBuilder;
 use ropey::Rope;
 use std::cell::RefCell;
 use std::ops::Range;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
-use tree_sitter::{InputEdit, Parser, Tree};
+use std::time::{Duration, Instant};
+use tree_sitter::Tree;
 
 use super::blink_manager::BlinkManager;
+use super::buffer::{Buffer, BufferEdited};
 use super::char_kind::CharKind;
 use super::command::*;
-use super::lines::Lines;
+use super::history::History;
+use super::lines::{LineHeights, Lines};
 use super::scroll_manager::ScrollManager;
+use super::selection::{merge_selections, Selection};
 use super::syntax::LanguageConfigManager;
+use super::syntax_map::SyntaxMap;
 use super::text_element::TextElement;
+use crate::settings_manager::{CurrentSettings, FileSettings, SettingsManager};
 
 actions!(
     text_input,
@@ -51,12 +57,29 @@ actions!(
         SelectDocEnd,
         Undo,
         Redo,
+        AddCursorAbove,
+        AddCursorBelow,
+        SelectNextOccurrence,
+        SplitSelectionIntoLines,
+        CollapseSelections,
+        Tab,
         ContentChanged
     ]
 );
 
 type PaintCallback = Box<dyn FnOnce(&mut TextInput, &mut ViewContext<TextInput>)>;
 
+/// Typing that pauses for longer than this starts a new undo step.
+const COALESCE_INTERVAL: Duration = Duration::from_millis(1000);
+
+struct PendingTransaction {
+    depth: usize,
+    commands: Vec<Box<dyn Command>>,
+    old_selections: Vec<Selection>,
+    edit_kinds: Vec<Option<EditKind>>,
+    starts_word: bool,
+}
+
 #[derive(PartialEq)]
 pub enum TextInputMode {
     SingleLine,
@@ -66,11 +89,15 @@ pub enum TextInputMode {
 pub struct TextInput {
     mode: TextInputMode,
     pub focus_handle: FocusHandle,
+    pub buffer: Model<Buffer>,
+    /// Snapshot of the buffer's content, refreshed on every edit.
     pub content: Rope,
     pub selected_range: Range<usize>,
     selection_reversed: bool,
+    pub other_selections: Vec<Selection>,
     pub marked_range: Option<Range<usize>>,
     pub last_layout: Option<Lines>,
+    pub line_heights: LineHeights,
     pub last_bounds: Option<Bounds<Pixels>>,
     pub last_offset: Option<Point<Pixels>>,
     is_selecting: bool,
@@ -81,18 +108,18 @@ pub struct TextInput {
     pub blink_manager: Model<BlinkManager>,
     pub scroll_manager: Model<ScrollManager>,
 
-    undo_stack: Vec<Box<dyn Command>>,
-    redo_stack: Vec<Box<dyn Command>>,
+    transaction: Option<PendingTransaction>,
+    last_edit_at: Option<Instant>,
 
     on_next_paint_stack: Rc<RefCell<Vec<PaintCallback>>>,
 
-    current_file_path: Option<PathBuf>,
-    is_dirty: bool,
-
     settings_soft_wrap: bool,
+    /// Soft wrap was set for this file, so the default from the settings no longer applies.
+    soft_wrap_overridden: bool,
+    pub file_settings: FileSettings,
 
-    parser: Parser,
     pub parse_tree: Option<Tree>,
+    pub syntax_map: SyntaxMap,
     pub language_configs: LanguageConfigManager,
 
     _subscriptions: Vec<Subscription>,
@@ -103,67 +130,51 @@ impl EventEmitter<ContentChanged> for TextInput {}
 
 impl TextInput {
     pub fn new(mode: TextInputMode, cx: &mut ViewContext<Self>) -> Self {
+        let language_configs = LanguageConfigManager::new();
+        let buffer = cx.new_model(|_| Buffer::new(&language_configs));
+        Self::with_buffer(mode, buffer, language_configs, cx)
+    }
+
+    /// Another view of `buffer`, with its own selections and scroll position.
+    pub fn for_buffer(
+        mode: TextInputMode,
+        buffer: Model<Buffer>,
+        cx: &mut ViewContext<Self>,
+    ) -> Self {
+        Self::with_buffer(mode, buffer, LanguageConfigManager::new(), cx)
+    }
+
+    fn with_buffer(
+        mode: TextInputMode,
+        buffer: Model<Buffer>,
+        language_configs: LanguageConfigManager,
+        cx: &mut ViewContext<Self>,
+    ) -> Self {
         let blink_manager = cx.new_model(|_| BlinkManager::new());
         let scroll_manager = cx.new_model(|_| ScrollManager::new());
 
-        cx.bind_keys([
-            KeyBinding::new("enter", NewLine, None),
-            KeyBinding::new("cmd-enter", NewLineWithoutSplit, None),
-            KeyBinding::new("backspace", Backspace, None),
-            KeyBinding::new("delete", Delete, None),
-            KeyBinding::new("left", Left, None),
-            KeyBinding::new("right", Right, None),
-            KeyBinding::new("up", Up, None),
-            KeyBinding::new("down", Down, None),
-            KeyBinding::new("home", Home, None),
-            KeyBinding::new("end", End, None),
-            KeyBinding::new("shift-left", SelectLeft, None),
-            KeyBinding::new("shift-right", SelectRight, None),
-            KeyBinding::new("shift-up", SelectUp, None),
-            KeyBinding::new("shift-down", SelectDown, None),
-            KeyBinding::new("cmd-a", SelectAll, None),
-            KeyBinding::new("ctrl-cmd-space", ShowCharacterPalette, None),
-            KeyBinding::new("cmd-c", Copy, None),
-            KeyBinding::new("cmd-v", Paste, None),
-            KeyBinding::new("cmd-x", Cut, None),
-            KeyBinding::new("alt-left", MoveToWordStart, None),
-            KeyBinding::new("alt-right", MoveToWordEnd, None),
-            KeyBinding::new("cmd-left", MoveToLineStart, None),
-            KeyBinding::new("cmd-right", MoveToLineEnd, None),
-            KeyBinding::new("cmd-up", MoveToDocStart, None),
-            KeyBinding::new("cmd-down", MoveToDocEnd, None),
-            KeyBinding::new("shift-alt-left", SelectWordStart, None),
-            KeyBinding::new("shift-alt-right", SelectWordEnd, None),
-            KeyBinding::new("shift-cmd-left", SelectLineStart, None),
-            KeyBinding::new("shift-cmd-right", SelectLineEnd, None),
-            KeyBinding::new("shift-cmd-up", SelectDocStart, None),
-            KeyBinding::new("shift-cmd-down", SelectDocEnd, None),
-            KeyBinding::new("cmd-z", Undo, None),
-            KeyBinding::new("shift-cmd-z", Redo, None),
-        ]);
-
         let focus_handle = cx.focus_handle();
 
-        let language_configs = LanguageConfigManager::new();
-        let markdown = language_configs
-            .language_config_for_language_id("markdown")
-            .expect("Markdown should always be there");
-
-        let mut parser = Parser::new();
-        parser
-            .set_language(&markdown.language)
-            .expect("Error Loading MD Grammar");
-
-        let parse_tree = parser.parse("", None);
+        let (content, parse_tree, syntax_map) = {
+            let buffer = buffer.read(cx);
+            (
+                buffer.content.clone(),
+                buffer.parse_tree.clone(),
+                buffer.syntax_map.clone(),
+            )
+        };
 
-        Self {
+        let mut this = Self {
             mode,
             focus_handle: focus_handle.clone(),
-            content: "".into(),
+            buffer: buffer.clone(),
+            content,
             selected_range: 0..0,
             selection_reversed: false,
+            other_selections: Vec::new(),
             marked_range: None,
             last_layout: None,
+            line_heights: LineHeights::default(),
             last_bounds: None,
             last_offset: None,
             is_selecting: false,
@@ -171,18 +182,23 @@ impl TextInput {
             highlights: vec![],
             blink_manager: blink_manager.clone(),
             scroll_manager: scroll_manager.clone(),
-            undo_stack: Vec::new(),
-            redo_stack: Vec::new(),
+            transaction: None,
+            last_edit_at: None,
             on_next_paint_stack: Rc::default(),
-            is_dirty: false,
-            current_file_path: None,
             settings_soft_wrap: false,
-            parser,
+            soft_wrap_overridden: false,
+            file_settings: FileSettings::default(),
             parse_tree,
+            syntax_map,
             language_configs,
             _subscriptions: vec![
+                cx.observe(&buffer, |_, _, cx| cx.notify()),
+                cx.subscribe(&buffer, |this, _, event: &BufferEdited, cx| {
+                    this.buffer_edited(event, cx);
+                }),
                 cx.observe(&scroll_manager, |_, _, cx| cx.notify()),
                 cx.observe(&blink_manager, |_, _, cx| cx.notify()),
+                cx.observe_global::<SettingsManager>(Self::settings_changed),
                 cx.observe_window_activation(|this, cx| {
                     let active = cx.is_window_active();
                     if active {
@@ -210,7 +226,43 @@ impl TextInput {
                         .update(cx, |blink_manager, _| blink_manager.disable());
                 }),
             ],
+        };
+        this.settings_changed(cx);
+        this
+    }
+
+    fn settings_changed(&mut self, cx: &mut ViewContext<Self>) {
+        let file_path = self.file_path(cx);
+        let language_id = file_path
+            .as_deref()
+            .and_then(|path| self.language_configs.language_id_for_path(path));
+        self.file_settings = cx.settings_for(file_path.as_deref(), language_id);
+        let settings = self.file_settings.settings.clone();
+
+        self.blink_manager.update(cx, |blink_manager, cx| {
+            blink_manager.set_blinking(settings.cursor_blink, cx)
+        });
+        self.scroll_manager.update(cx, |scroll_manager, _| {
+            scroll_manager.set_padding_vertical(settings.scroll_padding)
+        });
+        if self.mode == TextInputMode::Full && !self.soft_wrap_overridden {
+            self.settings_soft_wrap = settings.soft_wrap;
         }
+        cx.notify();
+    }
+
+    /// Picks up a project settings file at `path` that was created, removed or written.
+    /// Returns whether the settings depend on it.
+    pub fn check_project_settings(&mut self, path: &Path, cx: &mut ViewContext<Self>) -> bool {
+        let is_affected = self
+            .file_settings
+            .project_file_paths()
+            .iter()
+            .any(|project_file| project_file == path);
+        if is_affected {
+            self.settings_changed(cx);
+        }
+        is_affected
     }
 
     pub fn notify_about_paint(&mut self, cx: &mut ViewContext<Self>) {
@@ -227,80 +279,264 @@ impl TextInput {
         RefCell::borrow_mut(&self.on_next_paint_stack).push(Box::new(on_notify));
     }
 
-    fn update_tree(&mut self, char_range: Range<usize>, snapshot: &Rope) {
-        let start_byte = snapshot.char_to_byte(char_range.start);
-        let end_byte = snapshot.char_to_byte(char_range.end);
-
-        let start_line = snapshot.byte_to_line(start_byte);
-        let end_line = snapshot.byte_to_line(end_byte);
+    fn sync_with_buffer(&mut self, cx: &mut ViewContext<Self>) {
+        let buffer = self.buffer.read(cx);
+        self.content = buffer.content.clone();
+        self.parse_tree = buffer.parse_tree.clone();
+        self.syntax_map = buffer.syntax_map.clone();
+    }
 
-        if let Some(mut tree) = self.parse_tree.take() {
-            tree.edit(&InputEdit {
-                start_byte,
-                old_end_byte: start_byte,
-                new_end_byte: end_byte,
-                start_position: tree_sitter::Point::new(start_line, start_byte - start_line),
-                old_end_position: tree_sitter::Point::new(start_line, start_byte - start_line),
-                new_end_position: tree_sitter::Point::new(end_line, end_byte - end_line),
-            });
+    /// Keeps the snapshot current and moves this view's selections past edits made elsewhere.
+    fn buffer_edited(&mut self, event: &BufferEdited, cx: &mut ViewContext<Self>) {
+        self.sync_with_buffer(cx);
+        if let Some(changed) = event.changed_range() {
+            self.line_heights.edited(&self.content, changed);
+        }
 
-            self.parse_tree = self.parser.parse_with(
-                &mut |byte, _| {
-                    let (chunk, start_byte, _, _) = self.content.chunk_at_byte(byte);
-                    &chunk.as_bytes()[byte - start_byte..]
-                },
-                Some(&tree),
-            );
+        if event.origin != cx.entity_id() {
+            let adjust = |offset: usize| {
+                event
+                    .edits
+                    .iter()
+                    .fold(offset, |offset, edit| edit.adjust(offset))
+            };
+
+            self.selected_range =
+                adjust(self.selected_range.start)..adjust(self.selected_range.end);
+            for selection in self.other_selections.iter_mut() {
+                selection.range = adjust(selection.range.start)..adjust(selection.range.end);
+            }
+            self.marked_range = None;
+            self.highlights.clear();
         }
+
+        cx.notify();
+    }
+
+    fn execute_with_tree(&mut self, command: &dyn Command, cx: &mut ViewContext<Self>) {
+        let origin = cx.entity_id();
+        self.buffer
+            .update(cx, |buffer, cx| buffer.execute(command, origin, cx));
+        self.sync_with_buffer(cx);
+    }
+
+    fn undo_with_tree(&mut self, command: &dyn Command, cx: &mut ViewContext<Self>) {
+        let origin = cx.entity_id();
+        self.buffer
+            .update(cx, |buffer, cx| buffer.undo(command, origin, cx));
+        self.sync_with_buffer(cx);
     }
 
     fn execute_command(&mut self, command: Box<dyn Command>, cx: &mut ViewContext<Self>) {
-        if command.update_tree_before() {
-            let snapshot = self.content.clone();
-            command.execute(&mut self.content);
-            self.update_tree(command.char_range(), &snapshot);
-        } else {
-            command.execute(&mut self.content);
-            self.update_tree(command.char_range(), &self.content.clone());
+        self.execute_with_tree(command.as_ref(), cx);
+        self.transact(cx, |this, _| {
+            if let Some(transaction) = this.transaction.as_mut() {
+                transaction.commands.push(command);
+            }
+        });
+    }
+
+    /// Everything executed inside `f` becomes a single undo step.
+    pub fn transact(
+        &mut self,
+        cx: &mut ViewContext<Self>,
+        f: impl FnOnce(&mut Self, &mut ViewContext<Self>),
+    ) {
+        self.start_transaction();
+        f(self, cx);
+        self.end_transaction(cx);
+    }
+
+    fn start_transaction(&mut self) {
+        if let Some(transaction) = self.transaction.as_mut() {
+            transaction.depth += 1;
+            return;
+        }
+
+        self.transaction = Some(PendingTransaction {
+            depth: 0,
+            commands: Vec::new(),
+            old_selections: self.all_selections(),
+            edit_kinds: Vec::new(),
+            starts_word: false,
+        });
+    }
+
+    fn end_transaction(&mut self, cx: &mut ViewContext<Self>) {
+        let Some(transaction) = self.transaction.as_mut() else {
+            return;
+        };
+
+        if transaction.depth > 0 {
+            transaction.depth -= 1;
+            return;
+        }
+
+        let Some(transaction) = self.transaction.take() else {
+            return;
+        };
+
+        if transaction.commands.is_empty() {
+            return;
         }
 
-        self.undo_stack.push(command);
-        self.redo_stack.clear();
-        self.is_dirty = true;
+        let kind = transaction
+            .edit_kinds
+            .first()
+            .copied()
+            .flatten()
+            .filter(|kind| transaction.edit_kinds.iter().all(|k| *k == Some(*kind)));
+
+        let group = GroupCommand::new(
+            transaction.commands,
+            transaction.old_selections,
+            self.all_selections(),
+            kind,
+        );
+
+        let now = Instant::now();
+        let within_interval = self
+            .last_edit_at
+            .is_some_and(|last_edit_at| now.duration_since(last_edit_at) < COALESCE_INTERVAL);
+
+        self.buffer.update(cx, |buffer, _| {
+            let old_selections = group.old_selections.clone();
+            buffer.push_undo(group, |last| {
+                within_interval
+                    && !transaction.starts_word
+                    && kind.is_some()
+                    && last.kind == kind
+                    && last.new_selections == old_selections
+            });
+        });
+
+        self.last_edit_at = Some(now);
 
         cx.emit(ContentChanged);
     }
 
+    /// Tells the running transaction what kind of edit happened so typing can be coalesced.
+    fn hint_edit_kind(&mut self, kind: Option<EditKind>, starts_word: bool) {
+        if let Some(transaction) = self.transaction.as_mut() {
+            if transaction.edit_kinds.is_empty() {
+                transaction.starts_word = starts_word;
+            }
+            transaction.edit_kinds.push(kind);
+        }
+    }
+
     fn undo(&mut self, _: &Undo, cx: &mut ViewContext<Self>) {
-        let Some(command) = self.undo_stack.pop() else {
+        let Some(group) = self.buffer.update(cx, |buffer, _| buffer.undo_stack.pop()) else {
             return;
         };
 
-        let r = command.char_range();
-        if command.update_tree_before() {
-            let prev_selection = command.undo(&mut self.content);
-            self.update_tree(r.end..r.start, &self.content.clone());
-            self.update_selected_range(&prev_selection, cx);
-        } else {
-            let snapshot = self.content.clone();
-            let prev_selection = command.undo(&mut self.content);
-            self.update_tree(r.end..r.start, &snapshot);
-            self.update_selected_range(&prev_selection, cx);
-        }
+        self.undo_with_tree(&group, cx);
+        self.restore_selections(group.old_selections.clone(), cx);
 
-        self.redo_stack.push(command);
+        self.buffer.update(cx, |buffer, _| {
+            buffer.redo_stack.push(group);
+        });
+        self.last_edit_at = None;
+        cx.emit(ContentChanged);
     }
 
     pub fn redo(&mut self, _: &Redo, cx: &mut ViewContext<Self>) {
-        let Some(command) = self.redo_stack.pop() else {
+        let Some(group) = self.buffer.update(cx, |buffer, _| buffer.redo_stack.pop()) else {
             return;
         };
 
-        let snapshot = self.content.clone();
-        let new_selection = command.execute(&mut self.content);
-        self.update_tree(command.char_range(), &snapshot);
-        self.undo_stack.push(command);
-        self.update_selected_range(&new_selection, cx);
+        self.execute_with_tree(&group, cx);
+        self.restore_selections(group.new_selections.clone(), cx);
+
+        self.buffer.update(cx, |buffer, _| {
+            buffer.undo_stack.push(group);
+        });
+        self.last_edit_at = None;
+        cx.emit(ContentChanged);
+    }
+
+    pub fn history(&self, cx: &AppContext) -> History {
+        let buffer = self.buffer.read(cx);
+        History::new(
+            &self.content,
+            &buffer.undo_stack,
+            &buffer.redo_stack,
+            self.all_selections(),
+        )
+    }
+
+    /// Takes over a previously stored history if it was recorded on the current content.
+    pub fn restore_history(&mut self, history: History, cx: &mut ViewContext<Self>) {
+        if !history.matches(&self.content) {
+            return;
+        }
+
+        self.buffer.update(cx, |buffer, _| {
+            let is_dirty = buffer.is_dirty();
+            buffer.undo_stack = history.undo_stack.into_iter().map(Into::into).collect();
+            buffer.redo_stack = history.redo_stack.into_iter().map(Into::into).collect();
+            buffer.set_dirty(is_dirty);
+        });
+        self.last_edit_at = None;
+        self.restore_selections(history.selections, cx);
+    }
+
+    /// Replaces every byte range with its text as a single undo step.
+    pub fn replace_ranges_bytes(
+        &mut self,
+        replacements: Vec<(Range<usize>, String)>,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let old_selection = self.selected_range.clone();
+
+        let mut replacements: Vec<(Range<usize>, String)> = replacements
+            .into_iter()
+            .map(|(range, text)| {
+                (
+                    self.content.byte_to_char(range.start)..self.content.byte_to_char(range.end),
+                    text,
+                )
+            })
+            .collect();
+        replacements.sort_by_key(|(range, _)| range.start);
+
+        if replacements.is_empty() {
+            return;
+        }
+
+        self.transact(cx, |this, cx| {
+            // going back to front keeps the positions of the remaining ranges valid
+            for (range, text) in replacements.iter().rev() {
+                if !range.is_empty() {
+                    let old_text = this.content.slice(range.clone()).to_string();
+                    this.execute_command(
+                        Box::new(DeleteCommand::new(
+                            range.start,
+                            old_text,
+                            old_selection.clone(),
+                        )),
+                        cx,
+                    );
+                }
+
+                if !text.is_empty() {
+                    this.execute_command(
+                        Box::new(InsertCommand::new(
+                            range.start,
+                            text.clone(),
+                            old_selection.clone(),
+                        )),
+                        cx,
+                    );
+                }
+            }
+
+            if let Some((range, text)) = replacements.first() {
+                let end = range.start + text.chars().count();
+                this.update_selected_range(&(end..end), cx);
+            }
+        });
+        cx.notify();
     }
 
     pub fn insert(&mut self, text: &str, cx: &mut ViewContext<Self>) {
@@ -308,8 +544,9 @@ impl TextInput {
     }
 
     pub fn set_file_path(&mut self, path: PathBuf, cx: &mut ViewContext<Self>) {
-        self.current_file_path = Some(path);
-        cx.notify();
+        self.buffer
+            .update(cx, |buffer, _| buffer.file_path = Some(path));
+        self.settings_changed(cx);
     }
 
     fn new_line(&mut self, _: &NewLine, cx: &mut ViewContext<Self>) {
@@ -327,50 +564,90 @@ impl TextInput {
             return;
         }
         // todo: handle selection
-        self.move_to(self.position_for_end_of_line(self.cursor_offset()), cx);
-        self.replace_text_in_range(None, "\n", cx);
+        self.for_each_selection(cx, |this, cx| {
+            this.move_to(this.position_for_end_of_line(this.cursor_offset()), cx);
+            this.replace_text_in_range(None, "\n", cx);
+        });
     }
 
-    fn backspace(&mut self, _: &Backspace, cx: &mut ViewContext<Self>) {
-        if self.selected_range.is_empty() {
-            self.select_to(self.previous_boundary(self.cursor_offset()), cx);
+    /// Indents with spaces up to the next tab stop.
+    fn tab(&mut self, _: &Tab, cx: &mut ViewContext<Self>) {
+        if self.mode != TextInputMode::Full {
+            cx.propagate();
+            return;
         }
-        self.replace_text_in_range(None, "", cx);
+
+        let tab_width = self.file_settings.settings.tab_width;
+        self.for_each_selection(cx, |this, cx| {
+            let start = this.selected_range.start;
+            let column = start - this.content.line_to_char(this.content.char_to_line(start));
+            let spaces = " ".repeat(tab_width - column % tab_width);
+            this.replace_text_in_range(None, &spaces, cx);
+        });
+    }
+
+    fn backspace(&mut self, _: &Backspace, cx: &mut ViewContext<Self>) {
+        self.for_each_selection(cx, |this, cx| {
+            if this.selected_range.is_empty() {
+                this.select_to(this.previous_boundary(this.cursor_offset()), cx);
+            }
+            this.replace_text_in_range(None, "", cx);
+        });
     }
 
     fn delete(&mut self, _: &Delete, cx: &mut ViewContext<Self>) {
-        if self.selected_range.is_empty() {
-            self.select_to(self.next_boundary(self.cursor_offset()), cx);
-        }
-        self.replace_text_in_range(None, "", cx);
+        self.for_each_selection(cx, |this, cx| {
+            if this.selected_range.is_empty() {
+                this.select_to(this.next_boundary(this.cursor_offset()), cx);
+            }
+            this.replace_text_in_range(None, "", cx);
+        });
     }
 
     fn left(&mut self, _: &Left, cx: &mut ViewContext<Self>) {
-        if self.selected_range.is_empty() {
-            self.move_to(self.previous_boundary(self.cursor_offset()), cx);
-        } else {
-            self.move_to(self.selected_range.start, cx);
-        }
+        self.for_each_selection(cx, |this, cx| {
+            if this.selected_range.is_empty() {
+                this.move_to(this.previous_boundary(this.cursor_offset()), cx);
+            } else {
+                this.move_to(this.selected_range.start, cx);
+            }
+        });
     }
 
     fn right(&mut self, _: &Right, cx: &mut ViewContext<Self>) {
-        if self.selected_range.is_empty() {
-            self.move_to(self.next_boundary(self.selected_range.end), cx);
-        } else {
-            self.move_to(self.selected_range.end, cx);
-        }
+        self.for_each_selection(cx, |this, cx| {
+            if this.selected_range.is_empty() {
+                this.move_to(this.next_boundary(this.selected_range.end), cx);
+            } else {
+                this.move_to(this.selected_range.end, cx);
+            }
+        });
     }
 
     fn up(&mut self, _: &Up, cx: &mut ViewContext<Self>) {
-        if let Some(pos) = self.position_for_up() {
-            self.move_to(pos, cx);
+        if self.mode == TextInputMode::SingleLine {
+            cx.propagate();
+            return;
         }
+
+        self.for_each_selection(cx, |this, cx| {
+            if let Some(pos) = this.position_for_up() {
+                this.move_to(pos, cx);
+            }
+        });
     }
 
     fn down(&mut self, _: &Down, cx: &mut ViewContext<Self>) {
-        if let Some(pos) = self.position_for_down() {
-            self.move_to(pos, cx);
+        if self.mode == TextInputMode::SingleLine {
+            cx.propagate();
+            return;
         }
+
+        self.for_each_selection(cx, |this, cx| {
+            if let Some(pos) = this.position_for_down() {
+                this.move_to(pos, cx);
+            }
+        });
     }
 
     fn home(&mut self, _: &Home, cx: &mut ViewContext<Self>) {
@@ -382,6 +659,11 @@ impl TextInput {
     }
 
     fn copy(&mut self, _: &Copy, cx: &mut ViewContext<Self>) {
+        if !self.other_selections.is_empty() {
+            cx.write_to_clipboard(ClipboardItem::new_string(self.selections_text()));
+            return;
+        }
+
         if self.selected_range.is_empty() {
             let start_of_line_idx = self.position_for_start_of_line();
             let end_of_line_idx =
@@ -399,12 +681,39 @@ impl TextInput {
     }
 
     fn paste(&mut self, _: &Paste, cx: &mut ViewContext<Self>) {
-        if let Some(text) = cx.read_from_clipboard().and_then(|item| item.text()) {
-            self.replace_text_in_range(None, &text, cx);
+        let Some(text) = cx.read_from_clipboard().and_then(|item| item.text()) else {
+            return;
+        };
+
+        // one line per cursor gets distributed, anything else is pasted at every cursor
+        let line_count = text.lines().count();
+        if !self.other_selections.is_empty() && line_count == self.other_selections.len() + 1 {
+            let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+            let mut order: Vec<usize> = (0..=self.other_selections.len()).collect();
+            let selections = self.all_selections();
+            order.sort_by_key(|idx| selections[*idx].range.start);
+
+            let mut texts = vec![String::new(); order.len()];
+            for idx in order.into_iter().rev() {
+                texts[idx] = lines.pop().unwrap_or_default();
+            }
+
+            self.for_each_selection_indexed(cx, |this, selection_idx, cx| {
+                this.replace_text_in_range(None, &texts[selection_idx], cx);
+            });
+            return;
         }
+
+        self.replace_text_in_range(None, &text, cx);
     }
 
     fn cut(&mut self, _: &Cut, cx: &mut ViewContext<Self>) {
+        if !self.other_selections.is_empty() {
+            cx.write_to_clipboard(ClipboardItem::new_string(self.selections_text()));
+            self.replace_text_in_range(None, "", cx);
+            return;
+        }
+
         if self.selected_range.is_empty() {
             let start_of_line_idx = self.position_for_start_of_line();
             let end_of_line_idx =
@@ -443,6 +752,8 @@ impl TextInput {
 
         if event.modifiers.shift {
             self.select_to(self.index_for_mouse_position(event.position), cx);
+        } else if event.modifiers.alt {
+            self.add_cursor(self.index_for_mouse_position(event.position), cx);
         } else if event.click_count == 2 {
             let offset = self.index_for_mouse_position(event.position);
             let prev = self.start_of_word(self.next_boundary(offset));
@@ -533,23 +844,31 @@ impl TextInput {
     }
 
     fn select_left(&mut self, _: &SelectLeft, cx: &mut ViewContext<Self>) {
-        self.select_to(self.previous_boundary(self.cursor_offset()), cx);
+        self.for_each_selection(cx, |this, cx| {
+            this.select_to(this.previous_boundary(this.cursor_offset()), cx);
+        });
     }
 
     fn select_right(&mut self, _: &SelectRight, cx: &mut ViewContext<Self>) {
-        self.select_to(self.next_boundary(self.cursor_offset()), cx);
+        self.for_each_selection(cx, |this, cx| {
+            this.select_to(this.next_boundary(this.cursor_offset()), cx);
+        });
     }
 
     fn select_up(&mut self, _: &SelectUp, cx: &mut ViewContext<Self>) {
-        if let Some(pos) = self.position_for_up() {
-            self.select_to(pos, cx);
-        }
+        self.for_each_selection(cx, |this, cx| {
+            if let Some(pos) = this.position_for_up() {
+                this.select_to(pos, cx);
+            }
+        });
     }
 
     fn select_down(&mut self, _: &SelectDown, cx: &mut ViewContext<Self>) {
-        if let Some(pos) = self.position_for_down() {
-            self.select_to(pos, cx);
-        }
+        self.for_each_selection(cx, |this, cx| {
+            if let Some(pos) = this.position_for_down() {
+                this.select_to(pos, cx);
+            }
+        });
     }
 
     fn select_all_handler(&mut self, _: &SelectAll, cx: &mut ViewContext<Self>) {
@@ -562,37 +881,53 @@ impl TextInput {
     }
 
     fn select_word_start(&mut self, _: &SelectWordStart, cx: &mut ViewContext<Self>) {
-        self.select_to(self.start_of_word(self.cursor_offset()), cx);
+        self.for_each_selection(cx, |this, cx| {
+            this.select_to(this.start_of_word(this.cursor_offset()), cx);
+        });
     }
 
     fn select_word_end(&mut self, _: &SelectWordEnd, cx: &mut ViewContext<Self>) {
-        self.select_to(self.end_of_word(self.cursor_offset()), cx);
+        self.for_each_selection(cx, |this, cx| {
+            this.select_to(this.end_of_word(this.cursor_offset()), cx);
+        });
     }
 
     fn select_line_start(&mut self, _: &SelectLineStart, cx: &mut ViewContext<Self>) {
-        let start_of_line_idx = self.position_for_start_of_line();
-        self.select_to(start_of_line_idx, cx);
+        self.for_each_selection(cx, |this, cx| {
+            let start_of_line_idx = this.position_for_start_of_line();
+            this.select_to(start_of_line_idx, cx);
+        });
     }
 
     fn select_line_end(&mut self, _: &SelectLineEnd, cx: &mut ViewContext<Self>) {
-        self.select_to(self.position_for_end_of_line(self.cursor_offset()), cx);
+        self.for_each_selection(cx, |this, cx| {
+            this.select_to(this.position_for_end_of_line(this.cursor_offset()), cx);
+        });
     }
 
     fn move_to_word_start(&mut self, _: &MoveToWordStart, cx: &mut ViewContext<Self>) {
-        self.move_to(self.start_of_word(self.cursor_offset()), cx);
+        self.for_each_selection(cx, |this, cx| {
+            this.move_to(this.start_of_word(this.cursor_offset()), cx);
+        });
     }
 
     fn move_to_word_end(&mut self, _: &MoveToWordEnd, cx: &mut ViewContext<Self>) {
-        self.move_to(self.end_of_word(self.cursor_offset()), cx);
+        self.for_each_selection(cx, |this, cx| {
+            this.move_to(this.end_of_word(this.cursor_offset()), cx);
+        });
     }
 
     fn move_to_line_start(&mut self, _: &MoveToLineStart, cx: &mut ViewContext<Self>) {
-        let start_of_line_idx = self.position_for_start_of_line();
-        self.move_to(start_of_line_idx, cx);
+        self.for_each_selection(cx, |this, cx| {
+            let start_of_line_idx = this.position_for_start_of_line();
+            this.move_to(start_of_line_idx, cx);
+        });
     }
 
     fn move_to_line_end(&mut self, _: &MoveToLineEnd, cx: &mut ViewContext<Self>) {
-        self.move_to(self.position_for_end_of_line(self.cursor_offset()), cx);
+        self.for_each_selection(cx, |this, cx| {
+            this.move_to(this.position_for_end_of_line(this.cursor_offset()), cx);
+        });
     }
 
     fn move_to_doc_start(&mut self, _: &MoveToDocStart, cx: &mut ViewContext<Self>) {
@@ -604,15 +939,20 @@ impl TextInput {
     }
 
     fn select_doc_start(&mut self, _: &SelectDocStart, cx: &mut ViewContext<Self>) {
-        self.select_to(0, cx);
+        self.for_each_selection(cx, |this, cx| {
+            this.select_to(0, cx);
+        });
     }
 
     fn select_doc_end(&mut self, _: &SelectDocEnd, cx: &mut ViewContext<Self>) {
-        self.select_to(self.content.len_chars(), cx);
+        self.for_each_selection(cx, |this, cx| {
+            this.select_to(this.content.len_chars(), cx);
+        });
     }
 
     pub fn move_to(&mut self, offset: usize, cx: &mut ViewContext<Self>) {
         self.selected_range = offset..offset;
+        self.other_selections.clear();
         self.blink_manager.update(cx, BlinkManager::pause);
 
         let epoch = self
@@ -628,6 +968,7 @@ impl TextInput {
             let line_idx = self.content.char_to_line(offset);
             let byte_idx = self.content.line_to_byte(line_idx);
             let cursor_idx = self.content.char_to_byte(offset);
+            let is_shaped = lines.is_shaped(line_idx);
 
             self.scroll_manager.update(cx, |this, cx| {
                 this.calc_offset_after_move(
@@ -639,6 +980,11 @@ impl TextInput {
                     cx,
                 );
             });
+
+            // only the line's y was known, its x can be checked once it was laid out
+            if !is_shaped {
+                self.on_next_paint(move |this, cx| this.update_scroll_manager(epoch, offset, cx));
+            }
         }
     }
 
@@ -669,6 +1015,260 @@ impl TextInput {
         cx.notify();
     }
 
+    // - Multiple selections
+
+    /// The primary selection.
+    pub fn selection(&self) -> Selection {
+        Selection::new(self.selected_range.clone(), self.selection_reversed)
+    }
+
+    /// All selections with the primary one last.
+    pub fn all_selections(&self) -> Vec<Selection> {
+        let mut selections = self.other_selections.clone();
+        selections.push(self.selection());
+        selections
+    }
+
+    fn set_selections(
+        &mut self,
+        selections: Vec<Selection>,
+        primary_idx: usize,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let (mut selections, primary_idx) = merge_selections(selections, primary_idx);
+        if selections.is_empty() {
+            return;
+        }
+
+        let primary = selections.remove(primary_idx);
+        self.selected_range = primary.range;
+        self.selection_reversed = primary.reversed;
+        self.other_selections = selections;
+        self.marked_range.take();
+
+        self.blink_manager.update(cx, BlinkManager::pause);
+        self.update_scroll_on_next_paint(self.cursor_offset(), cx);
+        cx.notify();
+    }
+
+    /// Puts back selections and a scroll offset taken before a preview moved the cursor.
+    pub fn restore_view(
+        &mut self,
+        selections: Vec<Selection>,
+        scroll_offset: Point<Pixels>,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let primary_idx = selections.len().saturating_sub(1);
+        let (mut selections, primary_idx) = merge_selections(selections, primary_idx);
+        if selections.is_empty() {
+            return;
+        }
+
+        let primary = selections.remove(primary_idx);
+        self.selected_range = primary.range;
+        self.selection_reversed = primary.reversed;
+        self.other_selections = selections;
+        self.scroll_manager
+            .update(cx, |this, cx| this.set_offset(scroll_offset, cx));
+        cx.notify();
+    }
+
+    pub fn scroll_offset(&self, cx: &AppContext) -> Point<Pixels> {
+        self.scroll_manager.read(cx).offset(false)
+    }
+
+    fn restore_selections(&mut self, selections: Vec<Selection>, cx: &mut ViewContext<Self>) {
+        let primary_idx = selections.len().saturating_sub(1);
+        self.set_selections(selections, primary_idx, cx);
+    }
+
+    fn for_each_selection(
+        &mut self,
+        cx: &mut ViewContext<Self>,
+        mut f: impl FnMut(&mut Self, &mut ViewContext<Self>),
+    ) {
+        self.for_each_selection_indexed(cx, |this, _, cx| f(this, cx));
+    }
+
+    /// Runs `f` once per selection with that selection made the primary one.
+    /// Selections are visited back to front so edits don't move the ones still to come,
+    /// and all commands that were executed end up in one undo step.
+    fn for_each_selection_indexed(
+        &mut self,
+        cx: &mut ViewContext<Self>,
+        mut f: impl FnMut(&mut Self, usize, &mut ViewContext<Self>),
+    ) {
+        if self.other_selections.is_empty() {
+            self.transact(cx, |this, cx| f(this, 0, cx));
+            return;
+        }
+
+        self.start_transaction();
+
+        let selections = self.all_selections();
+        self.other_selections.clear();
+
+        let mut order: Vec<usize> = (0..selections.len()).collect();
+        order.sort_by_key(|idx| std::cmp::Reverse(selections[*idx].range.start));
+
+        let mut results: Vec<Option<Selection>> = vec![None; selections.len()];
+
+        for idx in order {
+            self.selected_range = selections[idx].range.clone();
+            self.selection_reversed = selections[idx].reversed;
+
+            let len_before = self.content.len_chars() as isize;
+            f(self, idx, cx);
+            let delta = self.content.len_chars() as isize - len_before;
+
+            if delta != 0 {
+                for result in results.iter_mut().flatten() {
+                    result.shift(delta);
+                }
+            }
+
+            self.other_selections.clear();
+            results[idx] = Some(Selection::new(
+                self.selected_range.clone(),
+                self.selection_reversed,
+            ));
+        }
+
+        let new_selections: Vec<Selection> = results.into_iter().flatten().collect();
+        self.restore_selections(new_selections, cx);
+
+        self.end_transaction(cx);
+    }
+
+    fn selections_text(&self) -> String {
+        let mut selections = self.all_selections();
+        selections.sort_by_key(|selection| selection.range.start);
+        selections
+            .iter()
+            .map(|selection| self.content.slice(selection.range.clone()).to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn add_cursor(&mut self, offset: usize, cx: &mut ViewContext<Self>) {
+        let mut selections = self.all_selections();
+        selections.push(Selection::cursor(offset));
+        let primary_idx = selections.len() - 1;
+        self.set_selections(selections, primary_idx, cx);
+    }
+
+    fn add_cursor_above(&mut self, _: &AddCursorAbove, cx: &mut ViewContext<Self>) {
+        if let Some(pos) = self.position_for_up() {
+            self.add_cursor(pos, cx);
+        }
+    }
+
+    fn add_cursor_below(&mut self, _: &AddCursorBelow, cx: &mut ViewContext<Self>) {
+        if let Some(pos) = self.position_for_down() {
+            self.add_cursor(pos, cx);
+        }
+    }
+
+    fn select_next_occurrence(&mut self, _: &SelectNextOccurrence, cx: &mut ViewContext<Self>) {
+        if self.selected_range.is_empty() {
+            let offset = self.cursor_offset();
+            let start = self.start_of_word(self.next_boundary(offset));
+            let end = self.end_of_word(offset);
+            self.selected_range = start..end;
+            self.selection_reversed = false;
+            self.update_scroll_on_next_paint(end, cx);
+            cx.notify();
+            return;
+        }
+
+        let needle = self.content.slice(self.selected_range.clone()).to_string();
+        let needle_len = self.selected_range.len();
+        let haystack = self.content.to_string();
+        let search_start = self.content.char_to_byte(self.selected_range.end);
+
+        let candidates = haystack[search_start..]
+            .match_indices(&needle)
+            .map(|(byte_idx, _)| byte_idx + search_start)
+            .chain(
+                haystack[..search_start]
+                    .match_indices(&needle)
+                    .map(|(byte_idx, _)| byte_idx),
+            );
+
+        let selections = self.all_selections();
+        for byte_idx in candidates {
+            let start = self.content.byte_to_char(byte_idx);
+            let range = start..start + needle_len;
+            if selections.iter().any(|selection| selection.range == range) {
+                continue;
+            }
+
+            let mut selections = selections;
+            selections.push(Selection::new(range, false));
+            let primary_idx = selections.len() - 1;
+            self.set_selections(selections, primary_idx, cx);
+            return;
+        }
+    }
+
+    fn split_selection_into_lines(
+        &mut self,
+        _: &SplitSelectionIntoLines,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let mut selections = Vec::new();
+
+        for selection in self.all_selections() {
+            let start_line = self.content.char_to_line(selection.range.start);
+            let end_line = self.content.char_to_line(selection.range.end);
+
+            if start_line == end_line {
+                selections.push(selection);
+                continue;
+            }
+
+            for line_idx in start_line..=end_line {
+                let line_start = self.content.line_to_char(line_idx);
+                if line_idx == end_line && selection.range.end == line_start {
+                    break;
+                }
+
+                let start = selection.range.start.max(line_start);
+                let end = selection.range.end.min(self.line_end(line_idx));
+                selections.push(Selection::new(start..end, false));
+            }
+        }
+
+        let primary_idx = selections.len().saturating_sub(1);
+        self.set_selections(selections, primary_idx, cx);
+    }
+
+    fn collapse_selections(&mut self, _: &CollapseSelections, cx: &mut ViewContext<Self>) {
+        if self.other_selections.is_empty() {
+            cx.propagate();
+            return;
+        }
+
+        self.other_selections.clear();
+        cx.notify();
+    }
+
+    /// Char index of a 0-based line and column, clamped to the document and the line's end.
+    pub fn offset_for_line_column(&self, line_idx: usize, column: usize) -> usize {
+        let line_idx = line_idx.min(self.content.len_lines() - 1);
+        (self.content.line_to_char(line_idx) + column).min(self.line_end(line_idx))
+    }
+
+    /// Char index of the end of the line, before its line break.
+    fn line_end(&self, line_idx: usize) -> usize {
+        let line = self.content.line(line_idx);
+        let mut len = line.len_chars();
+        while len > 0 && matches!(line.char(len - 1), '\n' | '\r') {
+            len -= 1;
+        }
+        self.content.line_to_char(line_idx) + len
+    }
+
     // - Helper
 
     fn position_from_layout(&mut self, position: usize) -> Option<Point<Pixels>> {
@@ -676,11 +1276,7 @@ impl TextInput {
             return None;
         };
 
-        let line_idx = self.content.char_to_line(position);
-        let char_idx = self.content.line_to_byte(line_idx);
-        let cursor_idx = self.content.char_to_byte(position);
-
-        Some(layout.position_for_byte_idx_in_line(cursor_idx - char_idx, line_idx))
+        Some(layout.position_for_char_idx(position, &self.content))
     }
 
     fn position_for_up(&mut self) -> Option<usize> {
@@ -838,6 +1434,7 @@ impl TextInput {
 
     fn update_selected_range(&mut self, range: &Range<usize>, cx: &mut ViewContext<Self>) {
         self.selected_range = range.clone();
+        self.other_selections.clear();
         self.marked_range.take();
         self.blink_manager.update(cx, BlinkManager::pause);
         self.update_scroll_on_next_paint(range.start, cx);
@@ -858,6 +1455,7 @@ impl TextInput {
 
     pub fn set_soft_wrap(&mut self, enabled: bool, cx: &mut ViewContext<Self>) {
         self.settings_soft_wrap = enabled;
+        self.soft_wrap_overridden = true;
         self.update_scroll_on_next_paint(self.cursor_offset(), cx);
         cx.notify();
     }
@@ -874,16 +1472,26 @@ impl TextInput {
     }
 
     pub fn mark_dirty(&mut self, value: bool, cx: &mut ViewContext<Self>) {
-        self.is_dirty = value;
-        cx.notify();
+        self.buffer.update(cx, |buffer, cx| {
+            buffer.set_dirty(value);
+            cx.notify();
+        });
     }
 
-    pub fn is_dirty(&self) -> bool {
-        self.is_dirty
+    pub fn is_dirty(&self, cx: &AppContext) -> bool {
+        self.buffer.read(cx).is_dirty()
     }
 
-    pub fn file_path(&self) -> &Option<PathBuf> {
-        &self.current_file_path
+    pub fn file_path(&self, cx: &AppContext) -> Option<PathBuf> {
+        self.buffer.read(cx).file_path.clone()
+    }
+
+    /// Untitled buffers are markdown until they are saved with another extension.
+    pub fn is_markdown(&self, cx: &AppContext) -> bool {
+        match self.file_path(cx) {
+            Some(path) => self.language_configs.language_id_for_path(&path) == Some("markdown"),
+            None => true,
+        }
     }
 
     pub fn highlight(&mut self, highlights: Vec<Range<usize>>, cx: &mut ViewContext<Self>) {
@@ -899,12 +1507,14 @@ impl TextInput {
 
 impl Render for TextInput {
     fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let line_height = cx.settings().line_height;
+
         div()
             .flex_col()
             .w_full()
             .when(self.mode == TextInputMode::Full, |el| el.h_full())
             .when(self.mode == TextInputMode::SingleLine, |el| {
-                el.h(px(28.) + px(8.) + px(8.))
+                el.h(line_height + px(8.) + px(8.))
             })
             .overflow_hidden()
             .key_context("TextInput")
@@ -913,6 +1523,7 @@ impl Render for TextInput {
             .on_action(cx.listener(Self::new_line))
             .on_action(cx.listener(Self::new_line_without_split))
             .on_action(cx.listener(Self::backspace))
+            .on_action(cx.listener(Self::tab))
             .on_action(cx.listener(Self::delete))
             .on_action(cx.listener(Self::left))
             .on_action(cx.listener(Self::right))
@@ -943,6 +1554,11 @@ impl Render for TextInput {
             .on_action(cx.listener(Self::select_doc_end))
             .on_action(cx.listener(Self::undo))
             .on_action(cx.listener(Self::redo))
+            .on_action(cx.listener(Self::add_cursor_above))
+            .on_action(cx.listener(Self::add_cursor_below))
+            .on_action(cx.listener(Self::select_next_occurrence))
+            .on_action(cx.listener(Self::split_selection_into_lines))
+            .on_action(cx.listener(Self::collapse_selections))
             .on_mouse_down(MouseButton::Left, cx.listener(Self::on_mouse_down))
             .on_mouse_up(MouseButton::Left, cx.listener(Self::on_mouse_up))
             .on_mouse_up_out(MouseButton::Left, cx.listener(Self::on_mouse_up))
@@ -993,35 +1609,57 @@ impl ViewInputHandler for TextInput {
         text: &str,
         cx: &mut ViewContext<Self>,
     ) {
+        if range_utf16.is_none() && self.marked_range.is_none() && !self.other_selections.is_empty()
+        {
+            self.for_each_selection(cx, |this, cx| this.replace_text_in_range(None, text, cx));
+            return;
+        }
+
         let range = range_utf16
             .or(self.marked_range.clone())
             .unwrap_or(self.selected_range.clone());
 
-        if range.start != range.end {
-            let old_text = self.content.slice(range.clone()).to_string();
-            self.execute_command(
-                Box::new(DeleteCommand::new(
-                    range.start,
-                    old_text.clone(),
-                    self.selected_range.clone(),
-                )),
-                cx,
-            );
-        }
+        let mut chars = text.chars();
+        let kind = match (chars.next(), chars.next()) {
+            (Some(ch), None) if range.is_empty() && ch != '\n' => Some(EditKind::Insert),
+            (None, _) if range.len() == 1 => Some(EditKind::Delete),
+            _ => None,
+        };
+        let starts_word = text.chars().next().is_some_and(|ch| {
+            CharKind::kind(ch) == CharKind::Word
+                && range.start > 0
+                && CharKind::kind(self.content.char(range.start - 1)) != CharKind::Word
+        });
+
+        self.transact(cx, |this, cx| {
+            this.hint_edit_kind(kind, starts_word);
+
+            if range.start != range.end {
+                let old_text = this.content.slice(range.clone()).to_string();
+                this.execute_command(
+                    Box::new(DeleteCommand::new(
+                        range.start,
+                        old_text.clone(),
+                        this.selected_range.clone(),
+                    )),
+                    cx,
+                );
+            }
 
-        if !text.is_empty() {
-            self.execute_command(
-                Box::new(InsertCommand::new(
-                    range.start,
-                    text.to_string(),
-                    self.selected_range.clone(),
-                )),
-                cx,
-            );
-        }
+            if !text.is_empty() {
+                this.execute_command(
+                    Box::new(InsertCommand::new(
+                        range.start,
+                        text.to_string(),
+                        this.selected_range.clone(),
+                    )),
+                    cx,
+                );
+            }
 
-        let l = text.chars().count();
-        self.update_selected_range(&(range.start + l..range.start + l), cx);
+            let l = text.chars().count();
+            this.update_selected_range(&(range.start + l..range.start + l), cx);
+        });
         cx.notify();
     }
 
@@ -1032,20 +1670,18 @@ impl ViewInputHandler for TextInput {
         new_selected_range_utf16: Option<Range<usize>>,
         cx: &mut ViewContext<Self>,
     ) {
-        if let Some(marked_range) = self.marked_range.take() {
-            self.replace_text_in_range(Some(marked_range), "", cx);
-        } else {
-            let range = range_utf16.unwrap_or(self.selected_range.clone());
-            self.content.remove(range.start..range.end);
-            self.content.insert(range.start, new_text);
-
-            let l = new_text.chars().count();
-            self.marked_range = Some(range.start..range.start + l);
-            self.selected_range = new_selected_range_utf16.as_ref().map_or_else(
-                || range.start + l..range.start + l,
-                |new_range| new_range.start + range.start..new_range.end + range.end,
-            );
-        }
+        let range = range_utf16
+            .or(self.marked_range.clone())
+            .unwrap_or(self.selected_range.clone());
+        // edits go through the buffer, the content is only a snapshot of it
+        self.replace_text_in_range(Some(range.clone()), new_text, cx);
+
+        let l = new_text.chars().count();
+        self.marked_range = (l > 0).then(|| range.start..range.start + l);
+        self.selected_range = new_selected_range_utf16.as_ref().map_or_else(
+            || range.start + l..range.start + l,
+            |new_range| range.start + new_range.start..range.start + new_range.end,
+        );
 
         cx.notify();
     }
@@ -20,7 +20,7 @@ impl Render for Tooltip {
             .rounded_md()
             .border_1()
             .border_color(cx.theme().scroll_bar_border)
-            .font_family(cx.settings().font_family)
+            .font_family(cx.settings().font_family.clone())
             .text_xs()
             .text_color(cx.theme().editor_text)
             .child(self.text.clone())